@@ -73,6 +73,13 @@ impl Camera {
             ((screen_size.height() / 2.0) - window_coords.y) as f64 / self.zoom)) / SCALE_FACTOR
     }
 
+    pub fn world_space_to_window_space(&self, world_coords: DVec2, screen_size: Rect) -> Pos2 {
+        let offset = (world_coords * SCALE_FACTOR - self.get_translation()) * self.zoom;
+        Pos2::new(
+            (offset.x + screen_size.width() as f64 / 2.0) as f32,
+            (screen_size.height() as f64 / 2.0 - offset.y) as f32)
+    }
+
     pub fn recenter(&mut self) {
         self.extra_translation = DVec2::new(0.0, 0.0)
     }
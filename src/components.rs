@@ -1,9 +1,13 @@
+use serde::{Deserialize, Serialize};
+
 use crate::storage::{entity_allocator::EntityAllocator, index_storage::ComponentStorage};
 
-use self::{celestial_body_component::CelestialBodyComponent, mass_component::MassComponent, parent_component::ParentComponent, position_component::PositionComponent, trajectory_component::TrajectoryComponent, velocity_component::VelocityComponent, name_component::NameComponent, icon_component::IconComponent};
+use self::{celestial_body_component::CelestialBodyComponent, engine_component::EngineComponent, mass_component::MassComponent, parent_component::ParentComponent, position_component::PositionComponent, trajectory_component::TrajectoryComponent, velocity_component::VelocityComponent, name_component::NameComponent, icon_component::IconComponent, manoeuvre_node_component::ManoeuvreNodeComponent};
 
 pub mod celestial_body_component;
+pub mod engine_component;
 pub mod icon_component;
+pub mod manoeuvre_node_component;
 pub mod mass_component;
 pub mod name_component;
 pub mod parent_component;
@@ -11,10 +15,13 @@ pub mod position_component;
 pub mod trajectory_component;
 pub mod velocity_component;
 
+#[derive(Serialize, Deserialize)]
 pub struct Components {
     pub entity_allocator: EntityAllocator,
     pub celestial_body_components: ComponentStorage<CelestialBodyComponent>,
+    pub engine_components: ComponentStorage<EngineComponent>,
     pub icon_components: ComponentStorage<IconComponent>,
+    pub manoeuvre_node_components: ComponentStorage<ManoeuvreNodeComponent>,
     pub mass_components: ComponentStorage<MassComponent>,
     pub name_components: ComponentStorage<NameComponent>,
     pub parent_components: ComponentStorage<ParentComponent>,
@@ -28,7 +35,9 @@ impl Components {
         Self { 
             entity_allocator: EntityAllocator::new() ,
             celestial_body_components: ComponentStorage::new(),
+            engine_components: ComponentStorage::new(),
             icon_components: ComponentStorage::new(),
+            manoeuvre_node_components: ComponentStorage::new(),
             mass_components: ComponentStorage::new(),
             name_components: ComponentStorage::new(),
             parent_components: ComponentStorage::new(),
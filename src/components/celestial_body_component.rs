@@ -1,11 +1,23 @@
 use std::collections::HashSet;
 
 use eframe::epaint::Rgba;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::storage::entity_allocator::Entity;
 
+fn serialize_color<S: Serializer>(color: &Rgba, serializer: S) -> Result<S::Ok, S::Error> {
+    color.to_array().serialize(serializer)
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgba, D::Error> {
+    let [r, g, b, a] = <[f32; 4]>::deserialize(deserializer)?;
+    Ok(Rgba::from_rgba_premultiplied(r, g, b, a))
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct CelestialBodyComponent {
     radius: f64,
+    #[serde(serialize_with = "serialize_color", deserialize_with = "deserialize_color")]
     color: Rgba,
     children: HashSet<Entity>,
 }
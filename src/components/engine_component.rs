@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+
+/// Used to turn specific impulse into exhaust velocity
+pub const STANDARD_GRAVITY: f64 = 9.80665;
+
+/// The current mass (including fuel) lives in the MassComponent, this just describes the engine and the limits on mass
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineComponent {
+    thrust: f64,
+    specific_impulse: f64,
+    dry_mass: f64,
+    wet_mass: f64,
+}
+
+impl EngineComponent {
+    pub fn new(thrust: f64, specific_impulse: f64, dry_mass: f64, wet_mass: f64) -> Self {
+        Self { thrust, specific_impulse, dry_mass, wet_mass }
+    }
+
+    pub fn get_thrust(&self) -> f64 {
+        self.thrust
+    }
+
+    pub fn get_specific_impulse(&self) -> f64 {
+        self.specific_impulse
+    }
+
+    pub fn get_dry_mass(&self) -> f64 {
+        self.dry_mass
+    }
+
+    pub fn get_wet_mass(&self) -> f64 {
+        self.wet_mass
+    }
+
+    pub fn get_exhaust_velocity(&self) -> f64 {
+        self.specific_impulse * STANDARD_GRAVITY
+    }
+
+    pub fn get_mass_flow_rate(&self) -> f64 {
+        self.thrust / self.get_exhaust_velocity()
+    }
+
+    /// Rocket equation - the most dv we can get by burning all the fuel
+    pub fn get_remaining_dv(&self, mass: f64) -> f64 {
+        self.get_exhaust_velocity() * f64::ln(mass / self.dry_mass)
+    }
+
+    pub fn get_mass_after_dv(&self, mass: f64, dv: f64) -> f64 {
+        mass * f64::exp(-dv / self.get_exhaust_velocity())
+    }
+
+    pub fn get_burn_duration(&self, mass: f64, dv: f64) -> f64 {
+        (mass - self.get_mass_after_dv(mass, dv)) / self.get_mass_flow_rate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rocket_equation() {
+        let engine = EngineComponent::new(2.0e4, 350.0, 4.0e3, 1.0e4);
+        let exhaust_velocity = 350.0 * STANDARD_GRAVITY;
+        assert!((engine.get_remaining_dv(1.0e4) - exhaust_velocity * f64::ln(2.5)).abs() < 1.0e-9);
+        assert!((engine.get_mass_after_dv(1.0e4, engine.get_remaining_dv(1.0e4)) - 4.0e3).abs() < 1.0e-6);
+        // Burning all the fuel at a constant mass flow rate
+        let expected_duration = 6.0e3 / (2.0e4 / exhaust_velocity);
+        assert!((engine.get_burn_duration(1.0e4, engine.get_remaining_dv(1.0e4)) - expected_duration).abs() < 1.0e-6);
+    }
+}
@@ -1,18 +1,21 @@
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum IconType {
     ObjectIcon,
     BurnIcon,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum IconState {
     None,
     Hovered,
     Selected,
+    Targeted,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct IconComponent {
     visible: bool,
     position: DVec2,
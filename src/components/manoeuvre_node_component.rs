@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+use crate::storage::entity_allocator::Entity;
+
+use super::trajectory_component::manoeuvre::ManoeuvreId;
+
+/// Marks an entity as the on-screen node for one of a spacecraft's planned manoeuvres
+/// The manoeuvre itself lives in the spacecraft's trajectory component, and is looked up by its id
+#[derive(Serialize, Deserialize)]
+pub struct ManoeuvreNodeComponent {
+    spacecraft: Entity,
+    manoeuvre: ManoeuvreId,
+}
+
+impl ManoeuvreNodeComponent {
+    pub fn new(spacecraft: Entity, manoeuvre: ManoeuvreId) -> Self {
+        Self { spacecraft, manoeuvre }
+    }
+
+    pub fn get_spacecraft(&self) -> Entity {
+        self.spacecraft
+    }
+
+    pub fn get_manoeuvre(&self) -> ManoeuvreId {
+        self.manoeuvre
+    }
+}
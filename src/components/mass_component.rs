@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub struct MassComponent {
     mass: f64,
 }
@@ -10,4 +13,8 @@ impl MassComponent {
     pub fn get_mass(&self) -> f64 {
         self.mass
     }
+
+    pub fn set_mass(&mut self, mass: f64) {
+        self.mass = mass;
+    }
 }
\ No newline at end of file
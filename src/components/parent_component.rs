@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::storage::entity_allocator::Entity;
 
+#[derive(Serialize, Deserialize)]
 pub struct ParentComponent {
     parent: Entity,
 }
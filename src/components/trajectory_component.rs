@@ -1,24 +1,32 @@
 use std::{collections::VecDeque, rc::Rc, cell::RefCell};
 
-use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
-use crate::storage::entity_allocator::Entity;
-
-use self::segment::{Segment, orbit::Orbit};
-
-use super::Components;
+use self::{segment::{Segment, burn::Burn, orbit::Orbit}, manoeuvre::{Manoeuvre, ManoeuvreId}, prediction_horizon::PredictionHorizon};
 
+pub mod manoeuvre;
+pub mod prediction_horizon;
 pub mod segment;
 
+/// Segments are the output of prediction, while manoeuvres are the input - any time the manoeuvres change,
+/// the trajectory needs to be re-predicted from the time of the earliest change
+#[derive(Serialize, Deserialize)]
 pub struct TrajectoryComponent {
     segments: VecDeque<Segment>,
+    manoeuvres: Vec<(ManoeuvreId, Manoeuvre)>,
+    next_manoeuvre_id: u64,
+    horizon: PredictionHorizon,
+    /// Bumped whenever segments are thrown away or the whole trajectory is replaced, so anything worked out from the segments
+    /// (eg closest approaches) can tell when it's out of date - it only has to tell versions apart within one run, so isn't saved
+    #[serde(skip)]
+    version: u64,
 }
 
 impl TrajectoryComponent {
-    pub fn new(components: &Components, parent: Entity, position: DVec2, velocity: DVec2, time: f64) -> Self {
+    pub fn new(orbit: Orbit) -> Self {
         let mut segments = VecDeque::new();
-        segments.push_back(Segment::Orbit(Rc::new(RefCell::new(Orbit::new(components, parent, position, velocity, time)))));
-        Self { segments }
+        segments.push_back(Segment::Orbit(Rc::new(RefCell::new(orbit))));
+        Self { segments, manoeuvres: vec![], next_manoeuvre_id: 0, horizon: PredictionHorizon::default(), version: 0 }
     }
 
     pub fn get_segments(&self) -> &VecDeque<Segment> {
@@ -33,20 +41,112 @@ impl TrajectoryComponent {
         self.segments.back().unwrap().clone()
     }
 
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    /// Takes on a trajectory predicted somewhere else (eg on a worker thread) as a new version of this one
+    pub fn replace(&mut self, trajectory_component: TrajectoryComponent) {
+        let version = self.version + 1;
+        *self = trajectory_component;
+        self.version = version;
+    }
+
+    pub fn get_horizon(&self) -> PredictionHorizon {
+        self.horizon
+    }
+
+    pub fn set_horizon(&mut self, horizon: PredictionHorizon) {
+        self.horizon = horizon;
+    }
+
+    /// Where the horizon ends as seen from the time, if that can be worked out from what's been predicted so far
+    pub fn get_horizon_end_time(&self, time: f64) -> Option<f64> {
+        self.horizon.get_end_time(&self.segments, time)
+    }
+
+    /// Sorted by time, with manoeuvres at the same time in the order they were added
+    pub fn get_manoeuvres(&self) -> &Vec<(ManoeuvreId, Manoeuvre)> {
+        &self.manoeuvres
+    }
+
+    /// The id the next manoeuvre will get
+    #[cfg(test)]
+    pub fn get_next_manoeuvre_id(&self) -> ManoeuvreId {
+        ManoeuvreId::new(self.next_manoeuvre_id)
+    }
+
+    pub fn get_manoeuvre(&self, id: ManoeuvreId) -> Option<Manoeuvre> {
+        self.manoeuvres.iter()
+            .find(|(other, _)| *other == id)
+            .map(|(_, manoeuvre)| *manoeuvre)
+    }
+
+    /// Several manoeuvres can be at the same time, in which case each one waits for the burns before it to finish
+    pub fn add_manoeuvre(&mut self, manoeuvre: Manoeuvre) -> ManoeuvreId {
+        let id = ManoeuvreId::new(self.next_manoeuvre_id);
+        self.next_manoeuvre_id += 1;
+        self.insert_manoeuvre(id, manoeuvre);
+        id
+    }
+
+    /// Keeps the id, so anything referring to the manoeuvre (eg its node) carries on referring to it even if it's moved
+    pub fn set_manoeuvre(&mut self, id: ManoeuvreId, manoeuvre: Manoeuvre) {
+        self.remove_manoeuvre(id);
+        self.insert_manoeuvre(id, manoeuvre);
+    }
+
+    fn insert_manoeuvre(&mut self, id: ManoeuvreId, manoeuvre: Manoeuvre) {
+        let index = self.manoeuvres.partition_point(|(_, other)| other.time <= manoeuvre.time);
+        self.manoeuvres.insert(index, (id, manoeuvre));
+    }
+
+    pub fn remove_manoeuvre(&mut self, id: ManoeuvreId) {
+        self.manoeuvres.retain(|(other, _)| *other != id);
+    }
+
+    /// Removes every manoeuvre at or after the time
+    pub fn remove_manoeuvres_after(&mut self, time: f64) {
+        self.manoeuvres.retain(|(_, manoeuvre)| manoeuvre.time < time);
+    }
+
+    /// The first manoeuvre in [start_time, end_time)
+    pub fn get_next_manoeuvre(&self, start_time: f64, end_time: f64) -> Option<Manoeuvre> {
+        self.manoeuvres.iter()
+            .map(|(_, manoeuvre)| *manoeuvre)
+            .find(|manoeuvre| manoeuvre.time >= start_time && manoeuvre.time < end_time)
+    }
+
+    /// The first burn predicted from the manoeuvre, since a burn that goes through an SOI change is split in two
+    /// None if the manoeuvre hasn't been predicted yet, or if prediction hasn't got as far as it
+    pub fn get_manoeuvre_burn(&self, id: ManoeuvreId) -> Option<Rc<RefCell<Burn>>> {
+        self.segments.iter().find_map(|segment| match segment {
+            Segment::Burn(burn) if burn.borrow().get_manoeuvre() == id => Some(burn.clone()),
+            _ => None,
+        })
+    }
+
     pub fn add_segment(&mut self, segment: Segment) {
         self.segments.push_back(segment);
     }
 
     pub fn remove_segments_after(&mut self, time: f64) {
+        self.version += 1;
         loop {
             match self.segments.back_mut().unwrap() {
-                Segment::Burn(_) => {
-                    panic!("Attempt to splice a burn")
+                // Burns are recreated from the manoeuvres during prediction, so any that haven't started can be thrown away,
+                // but a burn that's already underway at the time is kept whole and prediction carries on from its end
+                Segment::Burn(burn) => {
+                    if burn.borrow().get_start_time() >= time {
+                        self.segments.pop_back();
+                    } else {
+                        return;
+                    }
                 },
                 Segment::Orbit(orbit) => {
                     if orbit.borrow().get_start_time() > time {
                         self.segments.pop_back();
-                    } else if orbit.borrow().is_time_within_orbit(time) {
+                    } else if orbit.borrow().get_end_time() > time {
                         orbit.borrow_mut().trim_to_end_at(time);
                     } else {
                         return;
@@ -65,11 +165,22 @@ impl TrajectoryComponent {
     pub fn update(&mut self, time: f64, delta_time: f64) {
         if let Some(segment) = self.segments.front_mut() { 
             segment.update(delta_time);
-            if segment.is_finished() {
-                let overshot_time = segment.get_overshot_time(time);
-                self.segments.pop_front();
-                self.segments.front_mut().unwrap().update(overshot_time);
+        }
+        // A big enough time step can go straight through several segments (eg a short burn), so keep moving on until
+        // the time is inside the current segment, but never past the end of prediction
+        let mut started_manoeuvres = vec![];
+        while self.segments.len() > 1 && self.segments.front().unwrap().is_finished() {
+            let overshot_time = self.segments.front().unwrap().get_overshot_time(time);
+            if let Some(Segment::Burn(burn)) = self.segments.pop_front() {
+                started_manoeuvres.push(burn.borrow().get_manoeuvre());
             }
+            self.segments.front_mut().unwrap().update(overshot_time);
+        }
+        // A manoeuvre stops being planned once its burn has started, which isn't necessarily at its time if it had to
+        // wait for an earlier burn to finish
+        if let Some(Segment::Burn(burn)) = self.segments.front() {
+            started_manoeuvres.push(burn.borrow().get_manoeuvre());
         }
+        self.manoeuvres.retain(|(id, _)| !started_manoeuvres.contains(id));
     }
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Identifies a planned manoeuvre for as long as it exists, however it's edited - unlike its time, which changes when it's moved
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ManoeuvreId(u64);
+
+impl ManoeuvreId {
+    pub(super) fn new(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+/// A planned burn, which prediction turns into one or more burn segments when the trajectory reaches its time
+/// The tangent direction is the direction of the velocity at the start of the burn, so it's only known once the trajectory up to that point has been predicted
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Manoeuvre {
+    pub time: f64,
+    pub tangent_dv: f64,
+    pub normal_dv: f64,
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::segment::Segment;
+
+pub const DEFAULT_PREDICTION_DURATION: f64 = 10000000.0;
+
+/// How far ahead of the current time a trajectory is predicted - predictions are extended as time catches up with them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PredictionHorizon {
+    Duration(f64),
+    /// Periods of whichever orbit the entity is on at the time
+    Orbits(f64),
+    /// Up to the given number of SOI changes, then one more period of the orbit after the last one
+    SoiChanges(usize),
+}
+
+impl Default for PredictionHorizon {
+    fn default() -> Self {
+        PredictionHorizon::Duration(DEFAULT_PREDICTION_DURATION)
+    }
+}
+
+/// Hyperbolas and burns don't have a period
+fn get_period(segment: &Segment) -> Option<f64> {
+    match segment {
+        Segment::Burn(_) => None,
+        Segment::Orbit(orbit) => orbit.borrow().get_period(),
+    }
+}
+
+impl PredictionHorizon {
+    /// None if the end can't be worked out from what's been predicted so far, eg because there aren't enough SOI changes yet
+    pub fn get_end_time(&self, segments: &VecDeque<Segment>, time: f64) -> Option<f64> {
+        let current_index = segments.iter().position(|segment| segment.get_end_time() >= time).unwrap_or(segments.len() - 1);
+        match self {
+            PredictionHorizon::Duration(duration) => Some(time + duration),
+            PredictionHorizon::Orbits(orbits) => Some(time + orbits * get_period(&segments[current_index])?),
+            PredictionHorizon::SoiChanges(soi_changes) => {
+                let mut index = current_index;
+                for _ in 0..*soi_changes {
+                    let parent = segments[index].get_parent();
+                    index = (index + 1..segments.len()).find(|i| segments[*i].get_parent() != parent)?;
+                }
+                let start_time = f64::max(time, segments[index].get_start_time());
+                Some(start_time + get_period(&segments[index])?)
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::{rc::Rc, cell::RefCell};
 
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
 use crate::storage::entity_allocator::Entity;
 
@@ -9,7 +10,7 @@ use self::{orbit::Orbit, burn::Burn};
 pub mod burn;
 pub mod orbit;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Segment {
     Burn(Rc<RefCell<Burn>>),
     Orbit(Rc<RefCell<Orbit>>)
@@ -78,6 +79,13 @@ impl Segment {
         }
     }
 
+    pub fn get_end_time(&self) -> f64 {
+        match self {
+            Segment::Burn(burn) => burn.borrow().get_end_time(),
+            Segment::Orbit(orbit) => orbit.borrow().get_end_time(),
+        }
+    }
+
     pub fn get_position_at_time(&self, time: f64) -> DVec2 {
         match self {
             Segment::Burn(burn) => burn.borrow().get_point_at_time(time).get_position(),
@@ -98,6 +106,13 @@ impl Segment {
         }
     }
 
+    pub fn get_max_speed(&self) -> f64 {
+        match self {
+            Segment::Burn(burn) => burn.borrow().get_max_speed(),
+            Segment::Orbit(orbit) => orbit.borrow().get_max_speed(),
+        }
+    }
+
     pub fn reset(&self) {
         match self {
             Segment::Burn(burn) => burn.borrow_mut().reset(),
@@ -1,42 +1,72 @@
-use nalgebra_glm::DVec2;
+use nalgebra_glm::{vec2, DVec2};
+use serde::{Deserialize, Serialize};
 
-use crate::{storage::entity_allocator::Entity, state::State};
+use crate::{storage::entity_allocator::Entity, simulation::Simulation, components::trajectory_component::manoeuvre::{Manoeuvre, ManoeuvreId}};
 
-use self::burn_point::BurnPoint;
+use self::{burn_point::{BurnPoint, Thrust}, integrator::{integrate, Integrator}};
 
-mod burn_point;
+use super::Segment;
 
-const TIME_STEP: f64 = 0.1;
+mod burn_point;
+pub mod integrator;
 
-fn compute_burn_points(start_point: &BurnPoint, start_time: f64, duration: f64) -> Vec<BurnPoint> {
-    let mut points = vec![];
-    let mut point = start_point.clone();
-    while point.get_time() < start_time + duration {
-        points.push(point.clone());
-        point = point.next(TIME_STEP);
+/// The mass at the end of the last burn before this one, or the current mass if there are no other burns
+fn compute_start_mass(simulation: &Simulation, entity: Entity) -> f64 {
+    let trajectory_component = simulation.components.trajectory_components.get(&entity).unwrap();
+    let last_burn = trajectory_component.get_segments().iter().rev().find_map(|segment| match segment {
+        Segment::Burn(burn) => Some(burn.clone()),
+        Segment::Orbit(_) => None,
+    });
+    match last_burn {
+        Some(burn) => burn.borrow().get_end_mass(),
+        None => simulation.components.mass_components.get(&entity).unwrap().get_mass(),
     }
-    points
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Burn {
+    /// The manoeuvre the burn was predicted from - both pieces of a split burn have the same one
+    manoeuvre: ManoeuvreId,
     parent: Entity,
     tangent_direction: DVec2,
     tangent_dv: f64,
     normal_dv: f64,
+    thrust: Thrust,
+    integrator: Integrator,
     current_point: BurnPoint,
     points: Vec<BurnPoint>,
 }
 
 impl Burn {
-    pub fn new(state: &State, entity: Entity, parent: Entity, tangent_direction: DVec2, start_time: f64) -> Self {
-        let tangent_dv = 0.0;
-        let normal_dv = 0.0;
-        let acceleration = 2.0; // this will eventually depend on the spacecraft - ie rocket equation time :)
-        let total_dv = 10000.0; // todo
-        let duration = total_dv / acceleration;
-        let start_point = BurnPoint::new(state, entity, parent, start_time);
-        let points = compute_burn_points(&start_point, start_time, duration);
-        Self { parent, tangent_direction, tangent_dv, normal_dv, current_point: start_point, points }
+    /// If the requested dv is more than the fuel allows, it's scaled down to burn all the remaining fuel
+    /// The start time is usually the manoeuvre's, but can be later if the manoeuvre had to wait for an earlier burn to finish
+    pub fn new(simulation: &Simulation, entity: Entity, parent: Entity, tangent_direction: DVec2, id: ManoeuvreId, manoeuvre: Manoeuvre, start_time: f64) -> Self {
+        let (tangent_dv, normal_dv) = (manoeuvre.tangent_dv, manoeuvre.normal_dv);
+        let engine = simulation.components.engine_components.get(&entity).expect("Only objects with engines can burn");
+        let start_mass = compute_start_mass(simulation, entity);
+        let requested_dv = f64::sqrt(tangent_dv.powi(2) + normal_dv.powi(2));
+        let total_dv = f64::min(requested_dv, engine.get_remaining_dv(start_mass).max(0.0));
+        let scale = if requested_dv == 0.0 { 0.0 } else { total_dv / requested_dv };
+        let (tangent_dv, normal_dv) = (tangent_dv * scale, normal_dv * scale);
+
+        let normal_direction = vec2(-tangent_direction.y, tangent_direction.x);
+        let dv = tangent_direction * tangent_dv + normal_direction * normal_dv;
+        let direction = if total_dv == 0.0 { tangent_direction } else { dv.normalize() };
+        let thrust = Thrust { direction, force: engine.get_thrust(), mass_flow_rate: engine.get_mass_flow_rate() };
+
+        let duration = engine.get_burn_duration(start_mass, total_dv);
+        let previous_orbit = simulation.components.trajectory_components.get(&entity).unwrap().get_final_segment().as_orbit().clone();
+        let position = previous_orbit.borrow().get_end_position();
+        let velocity = previous_orbit.borrow().get_end_velocity();
+        let parent_mass = simulation.components.mass_components.get(&parent).unwrap().get_mass();
+        let start_point = BurnPoint::new(parent_mass, start_time, position, velocity, start_mass);
+        let integrator = simulation.burn_integrator;
+        let points = integrate(&start_point, &thrust, integrator, start_time + duration);
+        Self { manoeuvre: id, parent, tangent_direction, tangent_dv, normal_dv, thrust, integrator, current_point: start_point, points }
+    }
+
+    pub fn get_manoeuvre(&self) -> ManoeuvreId {
+        self.manoeuvre
     }
 
     pub fn get_start_time(&self) -> f64 {
@@ -51,6 +81,10 @@ impl Burn {
         self.points.first().unwrap().get_velocity()
     }
 
+    pub fn get_start_mass(&self) -> f64 {
+        self.points.first().unwrap().get_mass()
+    }
+
     pub fn get_current_time(&self) -> f64 {
         self.current_point.get_time()
     }
@@ -63,6 +97,10 @@ impl Burn {
         self.current_point.get_velocity()
     }
 
+    pub fn get_current_mass(&self) -> f64 {
+        self.current_point.get_mass()
+    }
+
     pub fn get_end_time(&self) -> f64 {
         self.points.last().unwrap().get_time()
     }
@@ -75,42 +113,202 @@ impl Burn {
         self.points.last().unwrap().get_velocity()
     }
 
+    pub fn get_end_mass(&self) -> f64 {
+        self.points.last().unwrap().get_mass()
+    }
+
+    pub fn get_tangent_direction(&self) -> DVec2 {
+        self.tangent_direction
+    }
+
+    pub fn get_tangent_dv(&self) -> f64 {
+        self.tangent_dv
+    }
+
+    pub fn get_normal_dv(&self) -> f64 {
+        self.normal_dv
+    }
+
     pub fn get_total_dv(&self) -> f64 {
-        10000.0 //f64::sqrt(self.tangent_dv.powi(2) + self.normal_dv.powi(2))
+        f64::sqrt(self.tangent_dv.powi(2) + self.normal_dv.powi(2))
     }
 
     pub fn get_duration(&self) -> f64 {
-        let acceleration = 2.0; // this will eventually depend on the spacecraft - ie rocket equation time :)
-        self.get_total_dv() / acceleration
+        self.get_end_time() - self.get_start_time()
+    }
+
+    pub fn get_max_speed(&self) -> f64 {
+        self.points.iter().map(|point| point.get_velocity().magnitude()).fold(0.0, f64::max)
     }
 
     pub fn get_parent(&self) -> Entity {
         self.parent
     }
 
+    pub fn get_integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    pub fn get_point_count(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Cuts off everything after time and returns it as a new burn relative to new_parent, which continues with the same thrust until
+    /// the original end time - thrust direction is fixed relative to the parent, and parents don't rotate, so the direction is unchanged
+    /// The dv is shared between the two pieces according to how much of the fuel each one burns
+    pub fn split(&mut self, time: f64, new_parent: Entity, new_parent_mass: f64, position: DVec2, velocity: DVec2) -> Burn {
+        let split_point = self.get_point_at_time(time);
+        let start_point = BurnPoint::new(new_parent_mass, time, position, velocity, split_point.get_mass());
+        let points = integrate(&start_point, &self.thrust, self.integrator, self.get_end_time());
+        let fraction = f64::ln(self.get_start_mass() / split_point.get_mass()) / f64::ln(self.get_start_mass() / self.get_end_mass());
+        let (tangent_dv, normal_dv) = (self.tangent_dv, self.normal_dv);
+        self.tangent_dv = tangent_dv * fraction;
+        self.normal_dv = normal_dv * fraction;
+        self.points.retain(|point| point.get_time() < time);
+        self.points.push(split_point);
+        Self {
+            manoeuvre: self.manoeuvre,
+            parent: new_parent,
+            tangent_direction: self.tangent_direction,
+            tangent_dv: tangent_dv * (1.0 - fraction),
+            normal_dv: normal_dv * (1.0 - fraction),
+            thrust: self.thrust,
+            integrator: self.integrator,
+            current_point: start_point,
+            points,
+        }
+    }
+
     pub fn get_point_at_time(&self, time: f64) -> BurnPoint {
-        let time_after_start = time - self.get_start_time();
-        if let Some(closest_previous_point) = self.points.get((time_after_start / TIME_STEP) as usize) {
-            let delta_time = time_after_start % TIME_STEP;
-            closest_previous_point.next(delta_time)
-        } else {
-            self.points.last().unwrap().clone()
+        if time >= self.get_end_time() {
+            return self.points.last().unwrap().clone();
         }
+        // Adaptive integrators space the points unevenly, so search for the last point at or before the time
+        let index = self.points.partition_point(|point| point.get_time() <= time).saturating_sub(1);
+        let closest_previous_point = &self.points[index];
+        closest_previous_point.next(&self.thrust, time - closest_previous_point.get_time())
     }
 
     pub fn is_finished(&self) -> bool {
-        self.current_point.get_time() > self.points.last().unwrap().get_time()
+        self.current_point.get_time() >= self.get_end_time()
     }
 
     pub fn get_overshot_time(&self, time: f64) -> f64 {
-        time - self.points.last().unwrap().get_time()
+        time - self.get_end_time()
     }
 
     pub fn reset(&mut self) {
         self.current_point = self.points.first().unwrap().clone();
     }
 
+    /// Uses the precomputed points rather than stepping the current point, since delta_time can be huge while warping
     pub fn update(&mut self, delta_time: f64) {
-        self.current_point = self.current_point.next(delta_time);
+        self.current_point = self.get_point_at_time(self.current_point.get_time() + delta_time);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use eframe::epaint::Rgba;
+
+    use crate::{storage::entity_builder::{add_root_object, add_child_object}, components::{engine_component::EngineComponent, trajectory_component::segment::orbit::Orbit}, systems::trajectory_update_system::trajectory_update_system};
+
+    use super::*;
+
+    const START_MASS: f64 = 1.0e4;
+
+    /// The parent is so light and far away that the spacecraft is effectively in free space
+    fn free_space_simulation() -> (Simulation, Entity, Entity) {
+        let mut simulation = Simulation::new();
+        let parent = add_root_object(&mut simulation.components, "star".to_string(), "parent".to_string(), vec2(0.0, 0.0), vec2(0.0, 0.0), 1.0, 1.0, Rgba::WHITE);
+        let engine = EngineComponent::new(2.0e4, 350.0, 4.0e3, START_MASS);
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), parent, vec2(1.0e12, 0.0), vec2(0.0, 1.0), START_MASS, Some(engine));
+        (simulation, spacecraft, parent)
+    }
+
+    fn add_burn(simulation: &mut Simulation, spacecraft: Entity, parent: Entity, tangent_dv: f64, normal_dv: f64) -> Rc<RefCell<Burn>> {
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().remove_segments_after(0.0);
+        let burn = Burn::new(simulation, spacecraft, parent, vec2(0.0, 1.0), ManoeuvreId::default(), Manoeuvre { time: 0.0, tangent_dv, normal_dv }, 0.0);
+        let orbit = Orbit::new(&simulation.components, parent, burn.get_end_position(), burn.get_end_velocity(), burn.get_end_time());
+        let burn = Rc::new(RefCell::new(burn));
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_segment(Segment::Burn(burn.clone()));
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_segment(Segment::Orbit(Rc::new(RefCell::new(orbit))));
+        burn
+    }
+
+    #[test]
+    fn test_rocket_equation() {
+        let (mut simulation, spacecraft, parent) = free_space_simulation();
+        let burn = add_burn(&mut simulation, spacecraft, parent, 1200.0, 500.0);
+        let burn = burn.borrow();
+        let engine = simulation.components.engine_components.get(&spacecraft).unwrap();
+
+        let achieved_dv = burn.get_end_velocity() - burn.get_start_velocity();
+        let expected_dv = vec2(-500.0, 1200.0);
+        assert!((achieved_dv - expected_dv).magnitude() / 1300.0 < 1.0e-3);
+        assert!((burn.get_end_mass() - engine.get_mass_after_dv(START_MASS, 1300.0)).abs() < 1.0e-6);
+        assert!((burn.get_duration() - engine.get_burn_duration(START_MASS, 1300.0)).abs() < 1.0e-6);
+
+        // The engine pushes on less and less mass as fuel is used up
+        let early_acceleration = burn.get_point_at_time(1.0).get_velocity() - burn.get_point_at_time(0.0).get_velocity();
+        let late_acceleration = burn.get_end_velocity() - burn.get_point_at_time(burn.get_end_time() - 1.0).get_velocity();
+        assert!(late_acceleration.magnitude() > early_acceleration.magnitude() * 1.1);
+    }
+
+    #[test]
+    fn test_dv_limited_by_fuel() {
+        let (mut simulation, spacecraft, parent) = free_space_simulation();
+        let burn = add_burn(&mut simulation, spacecraft, parent, 1.0e5, 0.0);
+        let remaining_dv = simulation.components.engine_components.get(&spacecraft).unwrap().get_remaining_dv(START_MASS);
+        assert!((burn.borrow().get_total_dv() - remaining_dv).abs() < 1.0e-6);
+        assert!((burn.borrow().get_end_mass() - 4.0e3).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_mass_updated() {
+        let (mut simulation, spacecraft, parent) = free_space_simulation();
+        let burn = add_burn(&mut simulation, spacecraft, parent, 1000.0, 0.0);
+        let duration = burn.borrow().get_duration();
+        let get_mass = |simulation: &Simulation| simulation.components.mass_components.get(&spacecraft).unwrap().get_mass();
+
+        simulation.time += duration / 2.0;
+        trajectory_update_system(&mut simulation, duration / 2.0);
+        assert!(get_mass(&simulation) < START_MASS);
+        assert!(get_mass(&simulation) > burn.borrow().get_end_mass());
+
+        // Overshoots the end of the burn, so the burn is removed in the same update
+        simulation.time += duration;
+        trajectory_update_system(&mut simulation, duration);
+        assert_eq!(get_mass(&simulation), burn.borrow().get_end_mass());
+    }
+
+    /// Warping can step over a whole burn in one update, which should end up in the same place as going through it slowly
+    #[test]
+    fn test_burn_skipped_in_one_update() {
+        let start_time = 100.0;
+        let simulate = |steps: usize| {
+            let (mut simulation, spacecraft, parent) = free_space_simulation();
+            simulation.predict_until(start_time);
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().remove_segments_after(start_time);
+            let burn = Burn::new(&simulation, spacecraft, parent, vec2(0.0, 1.0), ManoeuvreId::default(), Manoeuvre { time: start_time, tangent_dv: 1000.0, normal_dv: 0.0 }, start_time);
+            let orbit = Orbit::new(&simulation.components, parent, burn.get_end_position(), burn.get_end_velocity(), burn.get_end_time());
+            let end_mass = burn.get_end_mass();
+            let end_time = burn.get_end_time() + 50.0;
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_segment(Segment::Burn(Rc::new(RefCell::new(burn))));
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_segment(Segment::Orbit(Rc::new(RefCell::new(orbit))));
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().predict(100.0);
+            for _ in 0..steps {
+                let delta_time = end_time / steps as f64;
+                simulation.time += delta_time;
+                trajectory_update_system(&mut simulation, delta_time);
+            }
+            let mass = simulation.components.mass_components.get(&spacecraft).unwrap().get_mass();
+            assert_eq!(mass, end_mass);
+            assert!(matches!(simulation.components.trajectory_components.get(&spacecraft).unwrap().get_current_segment(), Segment::Orbit(_)));
+            simulation.components.position_components.get(&spacecraft).unwrap().get_absolute_position()
+        };
+        assert!((simulate(1) - simulate(1000)).magnitude() < 1.0e-3);
+    }
+}
@@ -1,33 +1,105 @@
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
-use crate::{storage::entity_allocator::Entity, state::State, components::trajectory_component::segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT};
+use crate::components::trajectory_component::segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT;
 
-#[derive(Clone)]
+/// Force and mass flow rate are constant for the whole burn, and the direction is fixed relative to the parent rather than following the velocity
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Thrust {
+    pub direction: DVec2,
+    pub force: f64,
+    pub mass_flow_rate: f64,
+}
+
+/// Rate of change of position and velocity
+type Derivative = (DVec2, DVec2);
+
+// Dormand-Prince coefficients - the 5th order weights are the same as the final row of the tableau,
+// and the 4th order weights are only used for the difference that estimates the error
+const DORMAND_PRINCE_TABLEAU: [&[f64]; 6] = [
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0],
+    &[9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0],
+    &[35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+const DORMAND_PRINCE_ERROR_WEIGHTS: [f64; 7] = [
+    35.0 / 384.0 - 5179.0 / 57600.0,
+    0.0,
+    500.0 / 1113.0 - 7571.0 / 16695.0,
+    125.0 / 192.0 - 393.0 / 640.0,
+    -2187.0 / 6784.0 + 92097.0 / 339200.0,
+    11.0 / 84.0 - 187.0 / 2100.0,
+    -1.0 / 40.0,
+];
+
+fn weighted_sum(derivatives: &[Derivative], weights: &[f64]) -> Derivative {
+    derivatives.iter().zip(weights).fold((DVec2::zeros(), DVec2::zeros()), |sum, (derivative, weight)| {
+        (sum.0 + derivative.0 * *weight, sum.1 + derivative.1 * *weight)
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BurnPoint {
     parent_mass: f64,
     time: f64,
     position: DVec2,
     velocity: DVec2,
+    mass: f64,
 }
 
 impl BurnPoint {
-    pub fn new(state: &State, entity: Entity, parent: Entity, time: f64) -> Self {
-        let segment = state.components.trajectory_components.get(&entity).unwrap().get_final_segment();
-        let previous_orbit = segment.as_orbit();
-        let position = previous_orbit.borrow().get_end_position();
-        let velocity = previous_orbit.borrow().get_end_velocity();
-        let parent_mass = state.components.mass_components.get(&parent).unwrap().get_mass();
-        Self { parent_mass, time, position, velocity }
+    pub fn new(parent_mass: f64, time: f64, position: DVec2, velocity: DVec2, mass: f64) -> Self {
+        Self { parent_mass, time, position, velocity, mass }
     }
 
-    pub fn next(&self,  delta_time: f64) -> Self {
+    fn get_derivative(&self, thrust: &Thrust) -> Derivative {
         let distance = self.position.magnitude();
-        let acceleration = -self.position.normalize() * (GRAVITATIONAL_CONSTANT * self.parent_mass) / distance.powi(2);
+        let gravity_acceleration = -self.position.normalize() * (GRAVITATIONAL_CONSTANT * self.parent_mass) / distance.powi(2);
+        // Acceleration rises as the fuel is used up
+        let thrust_acceleration = thrust.direction * thrust.force / self.mass;
+        (self.velocity, gravity_acceleration + thrust_acceleration)
+    }
+
+    /// Mass flow rate is constant, so mass can be found exactly rather than integrated
+    fn offset(&self, thrust: &Thrust, delta_time: f64, change: Derivative) -> Self {
         let parent_mass = self.parent_mass;
         let time = self.time + delta_time;
-        let velocity = self.velocity + acceleration * delta_time;
-        let position = self.position + self.velocity * delta_time;
-        BurnPoint { parent_mass, time, position, velocity }
+        let position = self.position + change.0;
+        let velocity = self.velocity + change.1;
+        let mass = self.mass - thrust.mass_flow_rate * delta_time;
+        BurnPoint { parent_mass, time, position, velocity, mass }
+    }
+
+    /// Derivative at the intermediate point found from a row of a Runge-Kutta tableau
+    /// The rows are all consistent, so the fraction of the step is just the sum of the row
+    fn get_stage(&self, thrust: &Thrust, delta_time: f64, derivatives: &[Derivative], row: &[f64]) -> Derivative {
+        let fraction: f64 = row.iter().sum();
+        let change = weighted_sum(derivatives, row);
+        self.offset(thrust, fraction * delta_time, (change.0 * delta_time, change.1 * delta_time)).get_derivative(thrust)
+    }
+
+    /// A single classic RK4 step
+    pub fn next(&self, thrust: &Thrust, delta_time: f64) -> Self {
+        let mut derivatives = vec![self.get_derivative(thrust)];
+        for row in [&[0.5][..], &[0.0, 0.5], &[0.0, 0.0, 1.0]] {
+            derivatives.push(self.get_stage(thrust, delta_time, &derivatives, row));
+        }
+        let change = weighted_sum(&derivatives, &[1.0 / 6.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 6.0]);
+        self.offset(thrust, delta_time, (change.0 * delta_time, change.1 * delta_time))
+    }
+
+    /// A single Dormand-Prince step, returning the 5th order result along with the estimated position and velocity errors
+    pub fn next_with_error(&self, thrust: &Thrust, delta_time: f64) -> (Self, DVec2, DVec2) {
+        let mut derivatives = vec![self.get_derivative(thrust)];
+        for row in DORMAND_PRINCE_TABLEAU {
+            derivatives.push(self.get_stage(thrust, delta_time, &derivatives, row));
+        }
+        let change = weighted_sum(&derivatives, DORMAND_PRINCE_TABLEAU[5]);
+        let error = weighted_sum(&derivatives, &DORMAND_PRINCE_ERROR_WEIGHTS);
+        let point = self.offset(thrust, delta_time, (change.0 * delta_time, change.1 * delta_time));
+        (point, error.0 * delta_time, error.1 * delta_time)
     }
 
     pub fn get_time(&self) -> f64 {
@@ -41,4 +113,8 @@ impl BurnPoint {
     pub fn get_velocity(&self) -> DVec2 {
         self.velocity
     }
-}
\ No newline at end of file
+
+    pub fn get_mass(&self) -> f64 {
+        self.mass
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use super::burn_point::{BurnPoint, Thrust};
+
+const DORMAND_PRINCE_INITIAL_TIME_STEP: f64 = 1.0;
+const DORMAND_PRINCE_MIN_TIME_STEP: f64 = 1.0e-6;
+const DORMAND_PRINCE_SAFETY_FACTOR: f64 = 0.9;
+const DORMAND_PRINCE_MIN_SCALE: f64 = 0.2;
+const DORMAND_PRINCE_MAX_SCALE: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Integrator {
+    /// Classic fourth order Runge-Kutta with a fixed time step
+    Rk4 { time_step: f64 },
+    /// Adaptive time step which keeps the estimated error of each step, relative to the position and velocity, below tolerance
+    DormandPrince { tolerance: f64 },
+}
+
+impl Default for Integrator {
+    fn default() -> Self {
+        Integrator::DormandPrince { tolerance: 1.0e-10 }
+    }
+}
+
+fn integrate_rk4(start_point: &BurnPoint, thrust: &Thrust, time_step: f64, end_time: f64) -> Vec<BurnPoint> {
+    let mut points = vec![start_point.clone()];
+    let mut point = start_point.clone();
+    while point.get_time() < end_time {
+        // The last step is shortened to land exactly on the end of the burn
+        let delta_time = f64::min(time_step, end_time - point.get_time());
+        point = point.next(thrust, delta_time);
+        points.push(point.clone());
+    }
+    points
+}
+
+fn integrate_dormand_prince(start_point: &BurnPoint, thrust: &Thrust, tolerance: f64, end_time: f64) -> Vec<BurnPoint> {
+    let mut points = vec![start_point.clone()];
+    let mut point = start_point.clone();
+    let mut time_step = DORMAND_PRINCE_INITIAL_TIME_STEP;
+    while point.get_time() < end_time {
+        let delta_time = f64::min(time_step, end_time - point.get_time());
+        let (next_point, position_error, velocity_error) = point.next_with_error(thrust, delta_time);
+        let error = f64::max(
+            position_error.magnitude() / f64::max(point.get_position().magnitude(), 1.0),
+            velocity_error.magnitude() / f64::max(point.get_velocity().magnitude(), 1.0));
+        let scale = if error == 0.0 {
+            DORMAND_PRINCE_MAX_SCALE
+        } else {
+            (DORMAND_PRINCE_SAFETY_FACTOR * (tolerance / error).powf(0.2)).clamp(DORMAND_PRINCE_MIN_SCALE, DORMAND_PRINCE_MAX_SCALE)
+        };
+        if error <= tolerance || delta_time <= DORMAND_PRINCE_MIN_TIME_STEP {
+            point = next_point;
+            points.push(point.clone());
+        }
+        time_step = f64::max(delta_time * scale, DORMAND_PRINCE_MIN_TIME_STEP);
+    }
+    points
+}
+
+/// Always includes the start point, and the last point is at the end of the burn
+pub fn integrate(start_point: &BurnPoint, thrust: &Thrust, integrator: Integrator, end_time: f64) -> Vec<BurnPoint> {
+    match integrator {
+        Integrator::Rk4 { time_step } => integrate_rk4(start_point, thrust, time_step, end_time),
+        Integrator::DormandPrince { tolerance } => integrate_dormand_prince(start_point, thrust, tolerance, end_time),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, EARTH_MASS}, components::trajectory_component::segment::orbit::{Orbit, orbit_direction::GRAVITATIONAL_CONSTANT}};
+
+    use super::*;
+
+    /// Returns the largest distance between the integrated points and the analytic ellipse over one full orbit, relative to the periapsis distance
+    fn max_error_over_orbit(integrator: Integrator) -> (f64, usize) {
+        let (simulation, earth) = earth_simulation();
+        let (periapsis, eccentricity) = (7.0e6, 0.5);
+        let position = vec2(periapsis, 0.0);
+        let velocity = vec2(0.0, f64::sqrt(GRAVITATIONAL_CONSTANT * EARTH_MASS * (1.0 + eccentricity) / periapsis));
+        let orbit = Orbit::new(&simulation.components, earth, position, velocity, 0.0);
+        let period = orbit.get_period().unwrap();
+
+        let thrust = Thrust { direction: vec2(1.0, 0.0), force: 0.0, mass_flow_rate: 0.0 };
+        let start_point = BurnPoint::new(EARTH_MASS, 0.0, position, velocity, 1.0e3);
+        let points = integrate(&start_point, &thrust, integrator, period);
+        assert_eq!(points.last().unwrap().get_time(), period);
+        let max_error = points.iter()
+            .map(|point| {
+                let expected_position = orbit.get_position_from_theta(orbit.get_theta_from_time(point.get_time()));
+                (point.get_position() - expected_position).magnitude() / periapsis
+            })
+            .fold(0.0, f64::max);
+        (max_error, points.len())
+    }
+
+    #[test]
+    fn test_rk4_zero_thrust_matches_ellipse() {
+        let (max_error, _) = max_error_over_orbit(Integrator::Rk4 { time_step: 0.5 });
+        assert!(max_error < 1.0e-9, "max_error={}", max_error);
+    }
+
+    #[test]
+    fn test_dormand_prince_zero_thrust_matches_ellipse() {
+        let (max_error, point_count) = max_error_over_orbit(Integrator::default());
+        assert!(max_error < 1.0e-7, "max_error={}", max_error);
+        // The whole point of adapting the step is to need far fewer points than a fixed step would
+        let (_, fixed_point_count) = max_error_over_orbit(Integrator::Rk4 { time_step: 0.5 });
+        assert!(point_count * 10 < fixed_point_count, "adaptive={} fixed={}", point_count, fixed_point_count);
+    }
+
+    #[test]
+    fn test_zero_duration() {
+        let thrust = Thrust { direction: vec2(1.0, 0.0), force: 1.0e3, mass_flow_rate: 1.0 };
+        let start_point = BurnPoint::new(EARTH_MASS, 5.0, vec2(7.0e6, 0.0), vec2(0.0, 7.0e3), 1.0e3);
+        for integrator in [Integrator::Rk4 { time_step: 0.1 }, Integrator::default()] {
+            assert_eq!(integrate(&start_point, &thrust, integrator, 5.0).len(), 1);
+        }
+    }
+}
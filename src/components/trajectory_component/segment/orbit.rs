@@ -1,17 +1,21 @@
 use std::f64::consts::PI;
 
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
 use crate::{storage::entity_allocator::Entity, components::Components};
 
-use self::{conic::{Conic, new_conic}, orbit_point::OrbitPoint, orbit_direction::OrbitDirection};
+use self::{conic::{Conic, new_conic, serialize_conic, deserialize_conic}, orbit_point::OrbitPoint, orbit_direction::OrbitDirection, orbital_elements::OrbitalElements};
 
-mod conic;
+pub mod conic;
 pub mod orbit_direction;
+pub mod orbital_elements;
 mod orbit_point;
 
+#[derive(Serialize, Deserialize)]
 pub struct Orbit {
     parent: Entity,
+    #[serde(serialize_with = "serialize_conic", deserialize_with = "deserialize_conic")]
     conic: Box<dyn Conic>,
     start_point: OrbitPoint,
     end_point: OrbitPoint,
@@ -28,6 +32,17 @@ impl Orbit {
         Self { parent, conic, start_point, end_point, current_point }
     }
 
+    /// Builds the conic straight from the elements, rather than going through a state vector
+    pub fn from_elements(components: &Components, parent: Entity, elements: OrbitalElements, time: f64) -> Self {
+        let parent_mass = components.mass_components.get(&parent).unwrap().get_mass();
+        let conic = elements.to_conic(parent_mass);
+        let position = conic.get_position(elements.get_theta(&*conic));
+        let start_point = OrbitPoint::new(&*conic, position, time);
+        let end_point = start_point.clone();
+        let current_point = start_point.clone();
+        Self { parent, conic, start_point, end_point, current_point }
+    }
+
     pub fn get_start_time(&self) -> f64 {
         self.start_point.get_time()
     }
@@ -132,6 +147,11 @@ impl Orbit {
         time - self.end_point.get_time()
     }
 
+    /// Orbits are fastest at periapsis, whether they're ellipses or hyperbolas
+    pub fn get_max_speed(&self) -> f64 {
+        self.get_velocity_from_theta(self.get_arugment_of_periapsis()).magnitude()
+    }
+
     pub fn get_period(&self) -> Option<f64> {
         self.conic.get_period()
     }
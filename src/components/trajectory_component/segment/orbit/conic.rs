@@ -1,13 +1,22 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, f64::consts::PI};
 
 use nalgebra_glm::{vec2, DVec2};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use self::{ellipse::Ellipse, hyperbola::Hyperbola};
+use self::{ellipse::Ellipse, hyperbola::Hyperbola, parabola::Parabola};
 
 use super::{orbit_direction::{OrbitDirection, GRAVITATIONAL_CONSTANT}, orbit_point::OrbitPoint};
 
 mod ellipse;
 mod hyperbola;
+mod kepler;
+mod parabola;
+
+/// Within this distance of e = 1, the ellipse and hyperbola formulas lose too much precision, so the universal variable formulation is used instead
+const PARABOLIC_ECCENTRICITY_TOLERANCE: f64 = 1.0e-2;
+
+/// Near zero, the Stumpff functions are computed from their series to avoid dividing by (nearly) zero
+const STUMPFF_SERIES_THRESHOLD: f64 = 0.1;
 
 // https://phys.libretexts.org/Bookshelves/Astronomy__Cosmology/Celestial_Mechanics_(Tatum)/09%3A_The_Two_Body_Problem_in_Two_Dimensions/9.08%3A_Orbital_Elements_and_Velocity_Vector#mjx-eqn-9.5.31
 // https://orbital-mechanics.space/time-since-periapsis-and-keplers-equation/elliptical-orbits.html
@@ -24,7 +33,8 @@ fn semi_major_axis(position: DVec2, velocity: DVec2, standard_gravitational_para
 }
 
 fn eccentricity(position: DVec2, velocity: DVec2, standard_gravitational_parameter: f64, semi_major_axis: f64) -> f64 {
-    (1.0 - ((position.magnitude_squared() * transverse_velocity(position, velocity).powi(2)) / (standard_gravitational_parameter * semi_major_axis))).sqrt()
+    // Rounding can push this slightly below zero for circular orbits, which would give NaN
+    (1.0 - ((position.magnitude_squared() * transverse_velocity(position, velocity).powi(2)) / (standard_gravitational_parameter * semi_major_axis))).max(0.0).sqrt()
 }
 
 fn argument_of_periapsis(position: DVec2, velocity: DVec2, standard_gravitational_parameter: f64) -> f64 {
@@ -36,6 +46,64 @@ fn specific_angular_momentum(position: DVec2, velocity: DVec2) -> f64 {
     position.magnitude() * transverse_velocity(position, velocity)
 }
 
+/// Same sign convention as specific_angular_momentum, ie positive for anticlockwise orbits
+fn specific_angular_momentum_from_elements(standard_gravitational_parameter: f64, semi_major_axis: f64, eccentricity: f64, direction: OrbitDirection) -> f64 {
+    let magnitude = f64::sqrt(standard_gravitational_parameter * semi_major_axis * (1.0 - eccentricity.powi(2)));
+    match direction {
+        OrbitDirection::AntiClockwise => magnitude,
+        OrbitDirection::Clockwise => -magnitude,
+    }
+}
+
+/// Stumpff functions for the universal variable formulation, used by both parabolas and the Lambert solver
+pub fn stumpff_c(z: f64) -> f64 {
+    if z.abs() < STUMPFF_SERIES_THRESHOLD {
+        // 1/2! - z/4! + z^2/6! - ...
+        let mut term = 0.5;
+        let mut sum = term;
+        for k in 1..10 {
+            term *= -z / ((2 * k + 1) * (2 * k + 2)) as f64;
+            sum += term;
+        }
+        sum
+    } else if z > 0.0 {
+        (1.0 - f64::cos(z.sqrt())) / z
+    } else {
+        (f64::cosh((-z).sqrt()) - 1.0) / -z
+    }
+}
+
+pub fn stumpff_s(z: f64) -> f64 {
+    if z.abs() < STUMPFF_SERIES_THRESHOLD {
+        // 1/3! - z/5! + z^2/7! - ...
+        let mut term = 1.0 / 6.0;
+        let mut sum = term;
+        for k in 1..10 {
+            term *= -z / ((2 * k + 2) * (2 * k + 3)) as f64;
+            sum += term;
+        }
+        sum
+    } else if z > 0.0 {
+        let root_z = z.sqrt();
+        (root_z - f64::sin(root_z)) / root_z.powi(3)
+    } else {
+        let root_z = (-z).sqrt();
+        (f64::sinh(root_z) - root_z) / root_z.powi(3)
+    }
+}
+
+/// Wraps an angle into the range (-pi, pi], the same range as atan2
+pub fn normalize_angle(theta: f64) -> f64 {
+    let theta = theta % (2.0 * PI);
+    if theta > PI {
+        theta - 2.0 * PI
+    } else if theta <= -PI {
+        theta + 2.0 * PI
+    } else {
+        theta
+    }
+}
+
 fn copysign(value: f64, sign: f64) -> f64 {
     if sign < 0.0 {
         -value.abs()
@@ -49,15 +117,51 @@ pub fn new_conic(parent_mass: f64, position: DVec2, velocity: DVec2) -> Box<dyn
     let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
     let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
     let direction = OrbitDirection::from_position_and_velocity(position, velocity);
-    if eccentricity <= 1.0 {
+    if (eccentricity - 1.0).abs() < PARABOLIC_ECCENTRICITY_TOLERANCE {
+        Box::new(Parabola::new(position, velocity, standard_gravitational_parameter, eccentricity, direction))
+    } else if eccentricity < 1.0 {
         Box::new(Ellipse::new(position, velocity, standard_gravitational_parameter, semi_major_axis, eccentricity, direction))
     } else {
         Box::new(Hyperbola::new(position, velocity, standard_gravitational_parameter, semi_major_axis, eccentricity, direction))
     }
 }
 
+/// Trait objects can't be deserialized directly, so conics are converted to and from this when saving and loading
+#[derive(Serialize, Deserialize)]
+pub enum ConicData {
+    Ellipse(Ellipse),
+    Hyperbola(Hyperbola),
+    Parabola(Parabola),
+}
+
+#[allow(clippy::borrowed_box)]
+pub fn serialize_conic<S: Serializer>(conic: &Box<dyn Conic>, serializer: S) -> Result<S::Ok, S::Error> {
+    conic.to_data().serialize(serializer)
+}
+
+pub fn deserialize_conic<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<dyn Conic>, D::Error> {
+    Ok(match ConicData::deserialize(deserializer)? {
+        ConicData::Ellipse(ellipse) => Box::new(ellipse),
+        ConicData::Hyperbola(hyperbola) => Box::new(hyperbola),
+        ConicData::Parabola(parabola) => Box::new(parabola),
+    })
+}
+
+/// Semi-major axis should be negative for hyperbolas, which is what falls out of the state vector calculation anyway
+pub fn new_conic_from_elements(parent_mass: f64, semi_major_axis: f64, eccentricity: f64, argument_of_periapsis: f64, direction: OrbitDirection) -> Box<dyn Conic> {
+    let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * parent_mass;
+    if (eccentricity - 1.0).abs() < PARABOLIC_ECCENTRICITY_TOLERANCE {
+        Box::new(Parabola::from_elements(standard_gravitational_parameter, semi_major_axis, eccentricity, argument_of_periapsis, direction))
+    } else if eccentricity < 1.0 {
+        Box::new(Ellipse::from_elements(standard_gravitational_parameter, semi_major_axis, eccentricity, argument_of_periapsis, direction))
+    } else {
+        Box::new(Hyperbola::from_elements(standard_gravitational_parameter, semi_major_axis, eccentricity, argument_of_periapsis, direction))
+    }
+}
+
 // Describes all the static parmeters of an elliptic orbit, but says nothing about the current state of the object in the orbit
 pub trait Conic: Debug + Send {
+    fn to_data(&self) -> ConicData;
     fn get_theta_from_time_since_periapsis(&self, time: f64) -> f64;
     fn get_time_since_periapsis(&self, theta: f64) -> f64;
     fn get_time_since_last_periapsis(&self, orbit_point: &OrbitPoint) -> f64;
@@ -70,18 +174,31 @@ pub trait Conic: Debug + Send {
     fn get_argument_of_periapsis(&self) -> f64;
     fn get_eccentricity(&self) -> f64;
     fn get_remaining_orbits(&self, remaining_time: f64) -> i32;
+    /// Takes and returns points relative to the focus, rotated so that periapsis is along the positive x axis
     fn solve_for_closest_point(&self, p: DVec2) -> DVec2;
     fn is_time_between_points(&self, start: &OrbitPoint, end: &OrbitPoint, time_since_periapsis: f64) -> bool;
 }
 
 #[cfg(test)]
 mod tests {
-    use std::f64::consts::PI;
-
     use super::*;
 
     use nalgebra_glm::vec2;
 
+    use crate::simulation::test_util::EARTH_MASS;
+
+    #[test]
+    fn test_stumpff_functions_continuous() {
+        for z in [-STUMPFF_SERIES_THRESHOLD, STUMPFF_SERIES_THRESHOLD] {
+            let below = z * (1.0 - 1.0e-9);
+            let above = z * (1.0 + 1.0e-9);
+            assert!((stumpff_c(below) - stumpff_c(above)).abs() < 1.0e-9);
+            assert!((stumpff_s(below) - stumpff_s(above)).abs() < 1.0e-9);
+        }
+        assert!((stumpff_c(0.0) - 0.5).abs() < 1.0e-15);
+        assert!((stumpff_s(0.0) - 1.0 / 6.0).abs() < 1.0e-15);
+    }
+
     #[test]
     fn test_semi_major_axis() {
         // https://nssdc.gsfc.nasa.gov/planetary/factsheet/mercuryfact.html
@@ -130,7 +247,7 @@ mod tests {
     fn test_argument_of_periapsis_2() {
         let position = vec2(0.4055e9 * f64::cos(PI/6.0), 0.4055e9 * f64::sin(PI/6.0));
         let velocity = vec2(0.570e3 * f64::cos(PI/6.0 + PI/2.0), 0.570e3 * f64::sin(PI/6.0 + PI/2.0));
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let argument_of_periapsis = argument_of_periapsis(position, velocity, standard_gravitational_parameter);
         let expected_argument_of_periapsis = PI / 6.0 - PI;
         assert!((argument_of_periapsis - expected_argument_of_periapsis).abs() < 0.01);
@@ -140,7 +257,7 @@ mod tests {
     fn test_argument_of_periapsis_3() {
         let position = vec2(369236029.3588132, 143598629.71966434);
         let velocity = vec2(47.79968959560202, -607.3920534306773);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let argument_of_periapsis = argument_of_periapsis(position, velocity, standard_gravitational_parameter);
         let expected_argument_of_periapsis = PI / 6.0 - PI;
         assert!((argument_of_periapsis - expected_argument_of_periapsis).abs() < 0.01);
@@ -150,7 +267,7 @@ mod tests {
     fn test_argument_of_periapsis_4() {
         let position = vec2(221244867.9581085, 278127601.0974563);
         let velocity = vec2(772.33035113478, -73.80334890759599);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let argument_of_periapsis = argument_of_periapsis(position, velocity, standard_gravitational_parameter);
         let expected_argument_of_periapsis = PI / 6.0 - PI;
         assert!((argument_of_periapsis - expected_argument_of_periapsis).abs() < 0.01);
@@ -160,9 +277,49 @@ mod tests {
     fn test_argument_of_periapsis_5() {
         let position = vec2(321699434.0757532, 238177462.81333557);
         let velocity = vec2(-448.8853759438255, 386.13875843572083);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let argument_of_periapsis = argument_of_periapsis(position, velocity, standard_gravitational_parameter);
         let expected_argument_of_periapsis = -2.615930001576588;
         assert!((argument_of_periapsis - expected_argument_of_periapsis).abs() < 0.01);
     }
+
+    #[test]
+    fn test_near_parabolic_sweep() {
+        // Sweeps through the boundaries between all three conics, checking that every state along the orbit
+        // has the same energy and angular momentum as the start, and that time -> theta -> time gives back the same time
+        let parent_mass = EARTH_MASS;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * parent_mass;
+        let periapsis = 1.0e7;
+        for i in 0..=40 {
+            let eccentricity = 0.99 + i as f64 * 0.0005;
+            for direction in [1.0, -1.0] {
+                for rotation in [0.0, 2.0, -1.3] {
+                    let speed = f64::sqrt(standard_gravitational_parameter * (1.0 + eccentricity) / periapsis);
+                    let position = periapsis * vec2(f64::cos(rotation), f64::sin(rotation));
+                    let velocity = direction * speed * vec2(-f64::sin(rotation), f64::cos(rotation));
+                    let energy = velocity.magnitude_squared() / 2.0 - standard_gravitational_parameter / periapsis;
+                    let angular_momentum = specific_angular_momentum(position, velocity);
+                    let conic = new_conic(parent_mass, position, velocity);
+                    let mut previous_true_anomaly = None;
+                    for time in [-50000.0, -10000.0, -1000.0, -100.0, 0.0, 100.0, 1000.0, 10000.0, 50000.0] {
+                        let theta = conic.get_theta_from_time_since_periapsis(time);
+                        let new_position = conic.get_position(theta);
+                        let new_velocity = conic.get_velocity(new_position, theta);
+                        assert!(new_position.x.is_finite() && new_position.y.is_finite() && new_velocity.x.is_finite() && new_velocity.y.is_finite());
+                        let new_energy = new_velocity.magnitude_squared() / 2.0 - standard_gravitational_parameter / new_position.magnitude();
+                        assert!((new_energy - energy).abs() < 1.0e-6 * standard_gravitational_parameter / periapsis, "e={} t={}", eccentricity, time);
+                        assert!((specific_angular_momentum(new_position, new_velocity) - angular_momentum).abs() < 1.0e-6 * angular_momentum.abs(), "e={} t={}", eccentricity, time);
+                        let new_time = conic.get_time_since_periapsis(theta);
+                        assert!((new_time - time).abs() < 1.0e-6 * f64::max(time.abs(), 1.0), "e={} t={} got {}", eccentricity, time, new_time);
+                        // True anomaly should always increase in the direction of motion
+                        let true_anomaly = direction * normalize_angle(theta - rotation);
+                        if let Some(previous_true_anomaly) = previous_true_anomaly {
+                            assert!(true_anomaly > previous_true_anomaly);
+                        }
+                        previous_true_anomaly = Some(true_anomaly);
+                    }
+                }
+            }
+        }
+    }
 }
\ No newline at end of file
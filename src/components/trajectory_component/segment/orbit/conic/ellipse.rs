@@ -1,42 +1,17 @@
 use std::f64::consts::PI;
 
 use nalgebra_glm::{vec2, DVec2};
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::components::trajectory_component::segment::orbit::{orbit_direction::OrbitDirection, orbit_point::OrbitPoint};
 
-use super::{argument_of_periapsis, Conic, specific_angular_momentum, copysign};
+use super::{kepler::solve_elliptic, argument_of_periapsis, Conic, ConicData, specific_angular_momentum, specific_angular_momentum_from_elements, copysign, normalize_angle};
 
 fn period(standard_gravitational_parameter: f64, semi_major_axis: f64) -> f64 {
     2.0 * PI * f64::sqrt(semi_major_axis.powi(3) / standard_gravitational_parameter)
 }
-fn solve_kepler_equation(eccentricity: f64, mean_anomaly: f64, start_offset: f64) -> f64 {
-    let max_delta_squared = (1.0e-7_f64).powi(2);
-    let max_attempts = 500;
-    // Choosing an initial seed: https://www.aanda.org/articles/aa/full_html/2022/02/aa41423-21/aa41423-21.html#S5
-    // Yes, they're actually serious about that 0.999999 thing (lmao)
-    let mut eccentric_anomaly = mean_anomaly + start_offset
-        + (0.999999 * 4.0 * eccentricity * mean_anomaly * (PI - mean_anomaly))
-        / (8.0 * eccentricity * mean_anomaly + 4.0 * eccentricity * (eccentricity - PI) + PI.powi(2));
-    let mut attempts = 0;
-    loop {
-        let delta = -(eccentric_anomaly - eccentricity * f64::sin(eccentric_anomaly) - mean_anomaly) / (1.0 - eccentricity * f64::cos(eccentric_anomaly));
-        if delta.powi(2) < max_delta_squared {
-            break;
-        }
-        if attempts > max_attempts {
-            // Try with different start value
-            let mut rng = rand::thread_rng();
-            let start_offset = (rng.gen::<f64>() - 0.5) * 5.0;
-            return solve_kepler_equation(eccentricity, mean_anomaly, start_offset)
-        }
-        eccentric_anomaly += delta;
-        attempts += 1;
-    }
-    eccentric_anomaly
-}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ellipse {
     semi_major_axis: f64,
     eccentricity: f64,
@@ -53,12 +28,22 @@ impl Ellipse {
         let specific_angular_momentum = specific_angular_momentum(position, velocity);
         Ellipse { semi_major_axis, eccentricity, period, argument_of_periapsis, direction, specific_angular_momentum }
     }
+
+    pub(in super) fn from_elements(standard_gravitational_parameter: f64, semi_major_axis: f64, eccentricity: f64, argument_of_periapsis: f64, direction: OrbitDirection) -> Self {
+        let period = period(standard_gravitational_parameter, semi_major_axis);
+        let specific_angular_momentum = specific_angular_momentum_from_elements(standard_gravitational_parameter, semi_major_axis, eccentricity, direction);
+        Ellipse { semi_major_axis, eccentricity, period, argument_of_periapsis, direction, specific_angular_momentum }
+    }
 }
 
 impl Conic for Ellipse {
+    fn to_data(&self) -> ConicData {
+        ConicData::Ellipse(self.clone())
+    }
+
     fn get_theta_from_time_since_periapsis(&self, time_since_periapsis: f64) -> f64 {
         let mean_anomaly = (2.0 * PI * time_since_periapsis) / self.period;
-        let eccentric_anomaly = solve_kepler_equation(self.eccentricity, mean_anomaly, 0.0);
+        let eccentric_anomaly = solve_elliptic(self.eccentricity, mean_anomaly);
         let mut true_anomaly = 2.0 * f64::atan(f64::sqrt((1.0 + self.eccentricity) / (1.0 - self.eccentricity)) * f64::tan(eccentric_anomaly / 2.0));
         // The sign of atan flips halfway through the orbit
         // So we need to add 2pi halfway through the orbit to keep things consistent
@@ -66,7 +51,7 @@ impl Conic for Ellipse {
             true_anomaly = -true_anomaly;
         }
         let theta = true_anomaly + self.argument_of_periapsis;
-        theta % (2.0 * PI)
+        normalize_angle(theta)
     }
 
     fn get_time_since_periapsis(&self, theta: f64) -> f64 {
@@ -140,6 +125,9 @@ impl Conic for Ellipse {
 
     /// https://stackoverflow.com/a/46007540
     fn solve_for_closest_point(&self, p: DVec2) -> DVec2 {
+        // The solver works relative to the center, which is on the far side of the focus from periapsis
+        let focus = vec2(self.semi_major_axis * self.eccentricity, 0.0);
+        let p = p + focus;
         let px = f64::abs(p[0]);
         let py = f64::abs(p[1]);
 
@@ -168,10 +156,10 @@ impl Conic for Ellipse {
             let delta_t = delta_c / f64::sqrt(a.powi(2) * f64::sin(t).powi(2) + b.powi(2) * f64::cos(t).powi(2));
 
             t += delta_t;
-            t = f64::min(PI / 2.0, f64::max(0.0, t))
+            t = t.clamp(0.0, PI / 2.0)
         }
 
-        vec2(copysign(a * f64::cos(t), p[0]), copysign(b * f64::sin(t), p[1]))
+        vec2(copysign(a * f64::cos(t), p[0]), copysign(b * f64::sin(t), p[1])) - focus
     }
 
     fn is_time_between_points(&self, start: &OrbitPoint, end: &OrbitPoint, time: f64) -> bool {
@@ -186,7 +174,7 @@ impl Conic for Ellipse {
 #[cfg(test)]
 mod tests {
 
-    use crate::components::trajectory_component::segment::orbit::{orbit_direction::GRAVITATIONAL_CONSTANT, conic::{semi_major_axis, eccentricity}};
+    use crate::{components::trajectory_component::segment::orbit::{orbit_direction::GRAVITATIONAL_CONSTANT, conic::{semi_major_axis, eccentricity}}, simulation::test_util::EARTH_MASS};
 
     use super::*;
 
@@ -230,7 +218,7 @@ mod tests {
     fn test_time_since_periapsis_from_theta() {
         let position = vec2(6678100.0,  0.0);
         let velocity = vec2(0.0, 15000.0);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let semi_major_axis = 1.53000e7;
         let eccentricity = 0.3725;
         let direction = OrbitDirection::from_position_and_velocity(position, velocity);
@@ -245,7 +233,7 @@ mod tests {
     fn test_theta_from_time_since_periapsis_1() {
         let position = vec2(6678100.0,  0.0);
         let velocity = vec2(0.0, 15000.0);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let semi_major_axis = 1.53000e7;
         let eccentricity = 0.3725;
         let direction = OrbitDirection::from_position_and_velocity(position, velocity);
@@ -260,7 +248,7 @@ mod tests {
     fn test_theta_from_time_since_periapsis_2() {
         let position = vec2(-83760632.16012573, -305649596.3836937);
         let velocity = vec2(-929.2507297680404, 1168.0344669650149);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
         let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
         let direction = OrbitDirection::from_position_and_velocity(position, velocity);
@@ -292,7 +280,7 @@ mod tests {
     fn test_position_from_true_anomaly_2() {
         let position = vec2(321699434.0757532, 238177462.81333557);
         let velocity = vec2(-448.8853759438255, 386.13875843572083);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
         let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
         let direction = OrbitDirection::from_position_and_velocity(position, velocity);
@@ -326,7 +314,7 @@ mod tests {
     fn test_velocity_from_true_anomaly_2() {
         let position = vec2(234851481.38196197, 174455271.78610012);
         let velocity = vec2(-250.6798696407834, 817.5591126812552);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 5.9722e24;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
         let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
         let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
         let direction = OrbitDirection::from_position_and_velocity(position, velocity);
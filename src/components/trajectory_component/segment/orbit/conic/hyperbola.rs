@@ -1,35 +1,13 @@
 use std::f64::consts::PI;
 
 use nalgebra_glm::{vec2, DVec2};
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::components::trajectory_component::segment::orbit::{orbit_direction::OrbitDirection, orbit_point::OrbitPoint};
 
-use super::{argument_of_periapsis, Conic, specific_angular_momentum, copysign};
-
-fn solve_kepler_equation(eccentricity: f64, mean_anomaly: f64, start_offset: f64) -> f64 {
-    let max_delta_squared = (1.0e-7_f64).powi(2);
-    let max_attempts = 500;
-    let mut eccentric_anomaly = mean_anomaly + start_offset;
-    let mut attempts = 0;
-    for _ in 0..1000 {
-        let delta = -(eccentricity * f64::sinh(eccentric_anomaly) - eccentric_anomaly - mean_anomaly) / (eccentricity * f64::cosh(eccentric_anomaly) - 1.0);
-        if delta.powi(2) < max_delta_squared {
-            break;
-        }
-        if attempts > max_attempts {
-            // Try with different start value
-            let mut rng = rand::thread_rng();
-            let start_offset = (rng.gen::<f64>() - 0.5) * 5.0;
-            return solve_kepler_equation(eccentricity, mean_anomaly, start_offset)
-        }
-        eccentric_anomaly += delta;
-        attempts += 1;
-    }
-    eccentric_anomaly
-}
+use super::{kepler::solve_hyperbolic, argument_of_periapsis, Conic, ConicData, specific_angular_momentum, specific_angular_momentum_from_elements, copysign, normalize_angle};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hyperbola {
     standard_gravitational_parameter: f64,
     semi_major_axis: f64,
@@ -45,16 +23,25 @@ impl Hyperbola {
         let specific_angular_momentum = specific_angular_momentum(position, velocity);
         Hyperbola { standard_gravitational_parameter, semi_major_axis, eccentricity, argument_of_periapsis, direction, specific_angular_momentum }
     }
+
+    pub(in super) fn from_elements(standard_gravitational_parameter: f64, semi_major_axis: f64, eccentricity: f64, argument_of_periapsis: f64, direction: OrbitDirection) -> Self {
+        let specific_angular_momentum = specific_angular_momentum_from_elements(standard_gravitational_parameter, semi_major_axis, eccentricity, direction);
+        Hyperbola { standard_gravitational_parameter, semi_major_axis, eccentricity, argument_of_periapsis, direction, specific_angular_momentum }
+    }
 }
 
 impl Conic for Hyperbola {
+    fn to_data(&self) -> ConicData {
+        ConicData::Hyperbola(self.clone())
+    }
+
     fn get_theta_from_time_since_periapsis(&self, time_since_periapsis: f64) -> f64 {
         let x = self.standard_gravitational_parameter.powi(2) / self.specific_angular_momentum.powi(3);
         let mean_anomaly = x * time_since_periapsis * (self.eccentricity.powi(2) - 1.0).powf(3.0 / 2.0);
-        let eccentric_anomaly = solve_kepler_equation(self.eccentricity, mean_anomaly, 0.0);
+        let eccentric_anomaly = solve_hyperbolic(self.eccentricity, mean_anomaly);
         let true_anomaly = 2.0 * f64::atan(f64::sqrt((self.eccentricity + 1.0) / (self.eccentricity - 1.0)) * f64::tanh(eccentric_anomaly / 2.0));
         let theta = true_anomaly + self.argument_of_periapsis;
-        theta % (2.0 * PI)
+        normalize_angle(theta)
     }
 
     fn get_time_since_periapsis(&self, theta: f64) -> f64 {
@@ -115,6 +102,9 @@ impl Conic for Hyperbola {
     /// But as we get closer to the line the solution gets more accurate
     /// So this actually works fine for our purposes despite being broken (lol)
     fn solve_for_closest_point(&self, p: DVec2) -> DVec2 {
+        // The solver works relative to the center, which is on the far side of the focus from periapsis
+        let focus = vec2(self.semi_major_axis * self.eccentricity, 0.0);
+        let p = p + focus;
         let px = f64::abs(p[0]);
         let py = f64::abs(p[1]);
 
@@ -143,10 +133,10 @@ impl Conic for Hyperbola {
             let delta_t = delta_c / f64::sqrt(a.powi(2) * f64::sinh(t).powi(2) + b.powi(2) * f64::cosh(t).powi(2));
     
             t += delta_t;
-            t = f64::min(PI / 2.0, f64::max(0.0, t))
+            t = t.clamp(0.0, PI / 2.0)
         }
     
-        vec2(copysign(a * f64::cosh(t), p[0]), copysign(b * f64::sinh(t), p[1]))
+        vec2(copysign(a * f64::cosh(t), p[0]), copysign(b * f64::sinh(t), p[1])) - focus
     }
 
     fn is_time_between_points(&self, start: &OrbitPoint, end: &OrbitPoint, time: f64) -> bool {
@@ -162,7 +152,7 @@ impl Conic for Hyperbola {
 mod tests {
     use std::f64::consts::PI;
 
-    use crate::components::trajectory_component::segment::orbit::{orbit_direction::GRAVITATIONAL_CONSTANT, conic::{semi_major_axis, eccentricity}};
+    use crate::{components::trajectory_component::segment::orbit::{orbit_direction::GRAVITATIONAL_CONSTANT, conic::{semi_major_axis, eccentricity}}, simulation::test_util::MOON_MASS};
 
     use super::*;
 
@@ -244,7 +234,7 @@ mod tests {
     fn test_theta_from_time_since_periapsis_4() {
         let position = vec2(-33839778.563934326, -31862122.134700775);
         let velocity = vec2(1187.3296202582328, 268.8766709200928);
-        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * 7.346e22;
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * MOON_MASS;
         let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
         let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
         let direction = OrbitDirection::from_position_and_velocity(position, velocity);
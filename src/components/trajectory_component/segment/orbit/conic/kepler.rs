@@ -0,0 +1,108 @@
+use std::f64::consts::PI;
+
+use super::normalize_angle;
+
+// Every version of Kepler's equation we need to solve is strictly increasing in the anomaly being solved for,
+// so with a bracket around the solution Newton's method can be made to always converge by falling back to
+// bisection whenever a step would leave the bracket. No random restarts, so the same input always gives the same output
+
+const MAX_ITERATIONS: usize = 200;
+const RELATIVE_TOLERANCE: f64 = 1.0e-15;
+
+/// Finds x in [lower, upper] where function(x) = 0
+/// function returns the value and derivative at x, and must be strictly increasing with a root inside the bracket
+pub fn solve_bracketed(function: impl Fn(f64) -> (f64, f64), mut lower: f64, mut upper: f64, initial_guess: f64) -> f64 {
+    let mut x = initial_guess.clamp(lower, upper);
+    for _ in 0..MAX_ITERATIONS {
+        let (value, derivative) = function(x);
+        if value == 0.0 {
+            return x;
+        }
+        if value > 0.0 {
+            upper = x;
+        } else {
+            lower = x;
+        }
+        let mut next = x - value / derivative;
+        if !(next > lower && next < upper) {
+            next = (lower + upper) / 2.0;
+        }
+        let tolerance = RELATIVE_TOLERANCE * f64::max(x.abs(), 1.0);
+        if (next - x).abs() <= tolerance || upper - lower <= tolerance {
+            return next;
+        }
+        x = next;
+    }
+    x
+}
+
+/// Solves M = E - e sin(E) for the eccentric anomaly E
+/// E is within e of M, since |E - M| = e|sin(E)|, which gives the bracket
+pub fn solve_elliptic(eccentricity: f64, mean_anomaly: f64) -> f64 {
+    // Solve within a single orbit, then add the whole orbits back on
+    let reduced_mean_anomaly = normalize_angle(mean_anomaly);
+    let whole_orbits = mean_anomaly - reduced_mean_anomaly;
+    let function = |eccentric_anomaly: f64| {
+        let value = eccentric_anomaly - eccentricity * f64::sin(eccentric_anomaly) - reduced_mean_anomaly;
+        let derivative = 1.0 - eccentricity * f64::cos(eccentric_anomaly);
+        (value, derivative)
+    };
+    // Starting at pi for high eccentricities avoids the flat region near periapsis where Newton overshoots
+    let initial_guess = if eccentricity > 0.8 { PI.copysign(reduced_mean_anomaly) } else { reduced_mean_anomaly };
+    whole_orbits + solve_bracketed(function, reduced_mean_anomaly - eccentricity, reduced_mean_anomaly + eccentricity, initial_guess)
+}
+
+/// Solves M = e sinh(H) - H for the hyperbolic anomaly H
+/// For positive M, e sinh(H) >= M gives the lower bound, and since sinh(H) >= H, (e - 1) sinh(H) <= M gives the upper bound
+pub fn solve_hyperbolic(eccentricity: f64, mean_anomaly: f64) -> f64 {
+    let target = mean_anomaly.abs();
+    let function = |hyperbolic_anomaly: f64| {
+        let value = eccentricity * f64::sinh(hyperbolic_anomaly) - hyperbolic_anomaly - target;
+        let derivative = eccentricity * f64::cosh(hyperbolic_anomaly) - 1.0;
+        (value, derivative)
+    };
+    let lower = f64::asinh(target / eccentricity);
+    let upper = f64::asinh(target / (eccentricity - 1.0));
+    solve_bracketed(function, lower, upper, lower).copysign(mean_anomaly)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eccentricities(start: f64, end: f64, count: usize) -> impl Iterator<Item = f64> {
+        (0..=count).map(move |i| start + (end - start) * i as f64 / count as f64)
+    }
+
+    #[test]
+    fn test_elliptic_grid() {
+        for eccentricity in eccentricities(0.0, 0.999, 200) {
+            for i in -400..=400 {
+                let mean_anomaly = i as f64 * 0.02;
+                let eccentric_anomaly = solve_elliptic(eccentricity, mean_anomaly);
+                let residual = eccentric_anomaly - eccentricity * f64::sin(eccentric_anomaly) - mean_anomaly;
+                assert!(residual.abs() < 1.0e-12 * f64::max(mean_anomaly.abs(), 1.0), "e={} M={} residual={}", eccentricity, mean_anomaly, residual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hyperbolic_grid() {
+        for eccentricity in eccentricities(1.001, 20.0, 200) {
+            for i in -400..=400 {
+                // Hyperbolic mean anomaly is unbounded, so cover a much wider range
+                let mean_anomaly = (i as f64 * 0.05).powi(3);
+                let hyperbolic_anomaly = solve_hyperbolic(eccentricity, mean_anomaly);
+                let residual = eccentricity * f64::sinh(hyperbolic_anomaly) - hyperbolic_anomaly - mean_anomaly;
+                assert!(residual.abs() < 1.0e-12 * f64::max(mean_anomaly.abs(), 1.0), "e={} M={} residual={}", eccentricity, mean_anomaly, residual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        for (eccentricity, mean_anomaly) in [(0.99, 0.001), (0.5, 3.0), (0.999, -3.1)] {
+            assert_eq!(solve_elliptic(eccentricity, mean_anomaly).to_bits(), solve_elliptic(eccentricity, mean_anomaly).to_bits());
+        }
+    }
+}
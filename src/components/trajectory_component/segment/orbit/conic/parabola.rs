@@ -0,0 +1,305 @@
+use std::f64::consts::PI;
+
+use nalgebra_glm::{vec2, DVec2};
+use serde::{Deserialize, Serialize};
+
+use crate::components::trajectory_component::segment::orbit::{orbit_direction::OrbitDirection, orbit_point::OrbitPoint};
+
+use super::{kepler::solve_bracketed, argument_of_periapsis, Conic, ConicData, specific_angular_momentum, normalize_angle, stumpff_c, stumpff_s};
+
+// https://en.wikipedia.org/wiki/Universal_variable_formulation
+// https://orbital-mechanics.space/time-since-periapsis-and-keplers-equation/universal-variables.html
+// Everything here is written in terms of the semi-latus rectum rather than the semi-major axis, since
+// the semi-major axis goes to infinity as eccentricity approaches 1, but the semi-latus rectum doesn't
+
+/// Keeps closest points a little short of the asymptotes, where the radius would be infinite
+const MAX_TRUE_ANOMALY_MARGIN: f64 = 1.0e-3;
+
+/// atan(kx)/k where k^2 = k_squared, which becomes atanh for negative k_squared and just x when k is 0
+fn atan_ratio(k_squared: f64, x: f64) -> f64 {
+    if k_squared > 0.0 {
+        let k = k_squared.sqrt();
+        f64::atan(k * x) / k
+    } else if k_squared < 0.0 {
+        let k = (-k_squared).sqrt();
+        f64::atanh(k * x) / k
+    } else {
+        x
+    }
+}
+
+/// Inverse of atan_ratio, ie tan(kx)/k
+fn tan_ratio(k_squared: f64, x: f64) -> f64 {
+    if k_squared > 0.0 {
+        let k = k_squared.sqrt();
+        f64::tan(k * x) / k
+    } else if k_squared < 0.0 {
+        let k = (-k_squared).sqrt();
+        f64::tanh(k * x) / k
+    } else {
+        x
+    }
+}
+
+/// Used for orbits with eccentricity close to 1, where the ellipse and hyperbola formulas fall apart, but works for any eccentricity
+/// Despite the name, this handles orbits on both sides of e = 1, so it can be closed (with a period) or open
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parabola {
+    standard_gravitational_parameter: f64,
+    semi_latus_rectum: f64,
+    eccentricity: f64,
+    direction: OrbitDirection,
+    argument_of_periapsis: f64,
+    specific_angular_momentum: f64,
+}
+
+impl Parabola {
+    pub(in super) fn new(position: DVec2, velocity: DVec2, standard_gravitational_parameter: f64, eccentricity: f64, direction: OrbitDirection) -> Self {
+        let argument_of_periapsis = argument_of_periapsis(position, velocity, standard_gravitational_parameter);
+        let specific_angular_momentum = specific_angular_momentum(position, velocity);
+        let semi_latus_rectum = specific_angular_momentum.powi(2) / standard_gravitational_parameter;
+        Parabola { standard_gravitational_parameter, semi_latus_rectum, eccentricity, direction, argument_of_periapsis, specific_angular_momentum }
+    }
+
+    pub(in super) fn from_elements(standard_gravitational_parameter: f64, semi_major_axis: f64, eccentricity: f64, argument_of_periapsis: f64, direction: OrbitDirection) -> Self {
+        let semi_latus_rectum = semi_major_axis * (1.0 - eccentricity.powi(2));
+        let specific_angular_momentum = match direction {
+            OrbitDirection::AntiClockwise => f64::sqrt(standard_gravitational_parameter * semi_latus_rectum),
+            OrbitDirection::Clockwise => -f64::sqrt(standard_gravitational_parameter * semi_latus_rectum),
+        };
+        Parabola { standard_gravitational_parameter, semi_latus_rectum, eccentricity, direction, argument_of_periapsis, specific_angular_momentum }
+    }
+
+    fn get_periapsis_distance(&self) -> f64 {
+        self.semi_latus_rectum / (1.0 + self.eccentricity)
+    }
+
+    /// 1/a, which unlike a stays finite (and passes through 0) as eccentricity goes through 1
+    fn get_alpha(&self) -> f64 {
+        (1.0 - self.eccentricity) * (1.0 + self.eccentricity) / self.semi_latus_rectum
+    }
+
+    /// Time since periapsis multiplied by sqrt(mu) - the derivative of this with respect to the universal anomaly is just the radius
+    fn get_scaled_time(&self, universal_anomaly: f64) -> f64 {
+        let z = self.get_alpha() * universal_anomaly.powi(2);
+        self.eccentricity * universal_anomaly.powi(3) * stumpff_s(z) + self.get_periapsis_distance() * universal_anomaly
+    }
+
+    fn get_radius(&self, universal_anomaly: f64) -> f64 {
+        let z = self.get_alpha() * universal_anomaly.powi(2);
+        self.get_periapsis_distance() + self.eccentricity * universal_anomaly.powi(2) * stumpff_c(z)
+    }
+
+    /// Scaled time strictly increases with the universal anomaly, and sqrt(mu) * t / q is always past the solution
+    /// because the radius can never be less than the periapsis distance, so the solution is always bracketed
+    fn solve_for_universal_anomaly(&self, time_since_periapsis: f64) -> f64 {
+        let target = self.standard_gravitational_parameter.sqrt() * time_since_periapsis.abs();
+        let function = |universal_anomaly: f64| (self.get_scaled_time(universal_anomaly) - target, self.get_radius(universal_anomaly));
+        let upper = target / self.get_periapsis_distance();
+        solve_bracketed(function, 0.0, upper, upper / 2.0).copysign(time_since_periapsis)
+    }
+
+    /// Measured in the direction of motion, unlike theta - argument_of_periapsis which is always anticlockwise
+    fn get_true_anomaly_from_universal_anomaly(&self, universal_anomaly: f64) -> f64 {
+        let k_squared = (1.0 - self.eccentricity) / (1.0 + self.eccentricity);
+        let x = universal_anomaly * (1.0 + self.eccentricity) / (2.0 * self.semi_latus_rectum.sqrt());
+        2.0 * f64::atan(tan_ratio(k_squared, x))
+    }
+
+    fn get_universal_anomaly_from_true_anomaly(&self, true_anomaly: f64) -> f64 {
+        let k_squared = (1.0 - self.eccentricity) / (1.0 + self.eccentricity);
+        let x = atan_ratio(k_squared, f64::tan(true_anomaly / 2.0));
+        x * 2.0 * self.semi_latus_rectum.sqrt() / (1.0 + self.eccentricity)
+    }
+
+    fn get_signed_true_anomaly(&self, theta: f64) -> f64 {
+        let true_anomaly = normalize_angle(theta - self.argument_of_periapsis);
+        match self.direction {
+            OrbitDirection::AntiClockwise => true_anomaly,
+            OrbitDirection::Clockwise => -true_anomaly,
+        }
+    }
+}
+
+impl Conic for Parabola {
+    fn to_data(&self) -> ConicData {
+        ConicData::Parabola(self.clone())
+    }
+
+    fn get_theta_from_time_since_periapsis(&self, time_since_periapsis: f64) -> f64 {
+        let universal_anomaly = self.solve_for_universal_anomaly(time_since_periapsis);
+        let true_anomaly = self.get_true_anomaly_from_universal_anomaly(universal_anomaly);
+        let theta = match self.direction {
+            OrbitDirection::AntiClockwise => self.argument_of_periapsis + true_anomaly,
+            OrbitDirection::Clockwise => self.argument_of_periapsis - true_anomaly,
+        };
+        normalize_angle(theta)
+    }
+
+    fn get_time_since_periapsis(&self, theta: f64) -> f64 {
+        let universal_anomaly = self.get_universal_anomaly_from_true_anomaly(self.get_signed_true_anomaly(theta));
+        self.get_scaled_time(universal_anomaly) / self.standard_gravitational_parameter.sqrt()
+    }
+
+    fn get_time_since_last_periapsis(&self, orbit_point: &OrbitPoint) -> f64 {
+        orbit_point.get_time_since_periapsis()
+    }
+
+    fn get_position(&self, theta: f64) -> DVec2 {
+        let true_anomaly = theta - self.argument_of_periapsis;
+        let radius = self.semi_latus_rectum / (1.0 + self.eccentricity * true_anomaly.cos());
+        vec2(radius * theta.cos(), radius * theta.sin())
+    }
+
+    fn get_velocity(&self, position: DVec2, theta: f64) -> DVec2 {
+        let true_anomaly = theta - self.argument_of_periapsis;
+        let radius = position.magnitude();
+        let radius_derivative_with_respect_to_theta = self.semi_latus_rectum * self.eccentricity * true_anomaly.sin()
+            / (self.eccentricity * true_anomaly.cos() + 1.0).powi(2);
+        let position_derivative_with_respect_to_theta = vec2(
+            radius_derivative_with_respect_to_theta * theta.cos() - radius * theta.sin(),
+            radius_derivative_with_respect_to_theta * theta.sin() + radius * theta.cos());
+        let angular_speed = self.specific_angular_momentum / radius.powi(2);
+        position_derivative_with_respect_to_theta * angular_speed
+    }
+
+    fn get_direction(&self) -> OrbitDirection {
+        self.direction
+    }
+
+    fn get_period(&self) -> Option<f64> {
+        if self.eccentricity < 1.0 {
+            Some(2.0 * PI * f64::sqrt(self.get_semi_major_axis().powi(3) / self.standard_gravitational_parameter))
+        } else {
+            None
+        }
+    }
+
+    /// Infinite for an exact parabola
+    fn get_semi_major_axis(&self) -> f64 {
+        1.0 / self.get_alpha()
+    }
+
+    fn get_semi_minor_axis(&self) -> f64 {
+        self.semi_latus_rectum / f64::sqrt((1.0 - self.eccentricity.powi(2)).abs())
+    }
+
+    fn get_argument_of_periapsis(&self) -> f64 {
+        self.argument_of_periapsis
+    }
+
+    fn get_eccentricity(&self) -> f64 {
+        self.eccentricity
+    }
+
+    fn get_remaining_orbits(&self, remaining_time: f64) -> i32 {
+        match self.get_period() {
+            Some(period) => (remaining_time / period) as i32,
+            None => 0,
+        }
+    }
+
+    /// Just projects the point onto the conic along the line from the focus, which is close enough when the point is near the line
+    /// Everything is relative to the focus, since the center runs off to infinity as eccentricity approaches 1
+    fn solve_for_closest_point(&self, p: DVec2) -> DVec2 {
+        let mut true_anomaly = f64::atan2(p.y, p.x);
+        // Open orbits only reach out as far as their asymptotes, so anything beyond that is projected onto the far end instead
+        if self.eccentricity >= 1.0 {
+            let max_true_anomaly = f64::acos(-1.0 / self.eccentricity) * (1.0 - MAX_TRUE_ANOMALY_MARGIN);
+            true_anomaly = true_anomaly.clamp(-max_true_anomaly, max_true_anomaly);
+        }
+        let radius = self.semi_latus_rectum / (1.0 + self.eccentricity * true_anomaly.cos());
+        vec2(radius * true_anomaly.cos(), radius * true_anomaly.sin())
+    }
+
+    fn is_time_between_points(&self, start: &OrbitPoint, end: &OrbitPoint, time: f64) -> bool {
+        time > start.get_time() && time < end.get_time()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{components::trajectory_component::segment::orbit::{orbit_direction::GRAVITATIONAL_CONSTANT, conic::{ellipse::Ellipse, hyperbola::Hyperbola, semi_major_axis, eccentricity}}, simulation::test_util::EARTH_MASS};
+
+    use super::*;
+
+    fn state_at_periapsis(eccentricity: f64) -> (DVec2, DVec2, f64) {
+        let standard_gravitational_parameter = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let periapsis = 1.0e7;
+        let speed = f64::sqrt(standard_gravitational_parameter * (1.0 + eccentricity) / periapsis);
+        (vec2(periapsis, 0.0), vec2(0.0, speed), standard_gravitational_parameter)
+    }
+
+    #[test]
+    fn test_exact_parabola_barker() {
+        // For an exact parabola, Barker's equation gives time directly from true anomaly
+        let (position, velocity, standard_gravitational_parameter) = state_at_periapsis(1.0);
+        let parabola = Parabola::new(position, velocity, standard_gravitational_parameter, 1.0, OrbitDirection::AntiClockwise);
+        let p = parabola.semi_latus_rectum;
+        for true_anomaly in [-2.5, -1.0, 0.0, 0.5, 2.0, 3.0] {
+            let d = f64::tan(true_anomaly / 2.0);
+            let expected_time = 0.5 * f64::sqrt(p.powi(3) / standard_gravitational_parameter) * (d + d.powi(3) / 3.0);
+            let time = parabola.get_time_since_periapsis(true_anomaly);
+            assert!((time - expected_time).abs() < 1.0e-9 * expected_time.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn test_matches_ellipse_and_hyperbola() {
+        // Outside of the near-parabolic band the other conics are accurate, so they should agree
+        for (nominal_eccentricity, direction) in [(0.9, OrbitDirection::AntiClockwise), (0.95, OrbitDirection::Clockwise), (1.05, OrbitDirection::AntiClockwise), (1.2, OrbitDirection::Clockwise)] {
+            let (position, mut velocity, standard_gravitational_parameter) = state_at_periapsis(nominal_eccentricity);
+            if let OrbitDirection::Clockwise = direction {
+                velocity = -velocity;
+            }
+            let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
+            let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
+            let other: Box<dyn Conic> = if nominal_eccentricity < 1.0 {
+                Box::new(Ellipse::new(position, velocity, standard_gravitational_parameter, semi_major_axis, eccentricity, direction))
+            } else {
+                Box::new(Hyperbola::new(position, velocity, standard_gravitational_parameter, semi_major_axis, eccentricity, direction))
+            };
+            let parabola = Parabola::new(position, velocity, standard_gravitational_parameter, eccentricity, direction);
+            for time in [-3000.0, -500.0, 0.0, 100.0, 2000.0] {
+                let theta = parabola.get_theta_from_time_since_periapsis(time);
+                let expected_theta = other.get_theta_from_time_since_periapsis(time);
+                assert!(normalize_angle(theta - expected_theta).abs() < 1.0e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_closest_point_exact_parabola() {
+        // The center of an exact parabola is infinitely far away, so this only works if nothing is done relative to it
+        let (position, velocity, standard_gravitational_parameter) = state_at_periapsis(1.0);
+        let parabola = Parabola::new(position, velocity, standard_gravitational_parameter, 1.0, OrbitDirection::AntiClockwise);
+        for true_anomaly in [-2.5, -1.0, 0.0, 0.5, 2.0] {
+            let on_orbit = parabola.get_position(true_anomaly);
+            let closest_point = parabola.solve_for_closest_point(on_orbit * 1.01);
+            assert!((closest_point - on_orbit).magnitude() < 1.0e-6 * on_orbit.magnitude());
+        }
+        // Directly behind the focus, where the orbit never reaches
+        let closest_point = parabola.solve_for_closest_point(vec2(-1.0e7, 0.0));
+        assert!(closest_point.x.is_finite() && closest_point.y.is_finite());
+    }
+
+    #[test]
+    fn test_closest_point_matches_ellipse_and_hyperbola() {
+        // All the conics should agree on where the focus is, so points on the orbit map back onto themselves
+        for nominal_eccentricity in [0.5, 0.9, 1.05, 1.2] {
+            let (position, velocity, standard_gravitational_parameter) = state_at_periapsis(nominal_eccentricity);
+            let semi_major_axis = semi_major_axis(position, velocity, standard_gravitational_parameter);
+            let eccentricity = eccentricity(position, velocity, standard_gravitational_parameter, semi_major_axis);
+            let other: Box<dyn Conic> = if nominal_eccentricity < 1.0 {
+                Box::new(Ellipse::new(position, velocity, standard_gravitational_parameter, semi_major_axis, eccentricity, OrbitDirection::AntiClockwise))
+            } else {
+                Box::new(Hyperbola::new(position, velocity, standard_gravitational_parameter, semi_major_axis, eccentricity, OrbitDirection::AntiClockwise))
+            };
+            for true_anomaly in [-1.0, 0.0, 0.3, 1.0] {
+                let on_orbit = other.get_position(true_anomaly);
+                let closest_point = other.solve_for_closest_point(on_orbit);
+                assert!((closest_point - on_orbit).magnitude() < 1.0e-3 * on_orbit.magnitude());
+            }
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
 use super::conic::transverse_velocity;
 
 pub const GRAVITATIONAL_CONSTANT: f64 = 6.674e-11;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum OrbitDirection {
     AntiClockwise,
     Clockwise,
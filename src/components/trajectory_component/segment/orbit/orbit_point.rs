@@ -1,8 +1,9 @@
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
 use super::conic::Conic;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrbitPoint {
     theta: f64,
     time: f64,
@@ -0,0 +1,154 @@
+use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
+
+use super::{conic::{new_conic, new_conic_from_elements, normalize_angle, Conic}, orbit_direction::OrbitDirection};
+
+/// Where along the conic the object is
+/// True anomaly follows the same convention as the rest of the conic code, ie it's measured anticlockwise from periapsis
+/// regardless of the orbit's direction, so a clockwise orbit reaches positive true anomalies just before periapsis
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Anomaly {
+    True(f64),
+    TimeSincePeriapsis(f64),
+}
+
+/// Classical orbital elements, restricted to 2D
+/// Semi-major axis is negative for hyperbolas, the same as when it's calculated from a state vector
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub argument_of_periapsis: f64,
+    pub direction: OrbitDirection,
+    pub anomaly: Anomaly,
+}
+
+impl OrbitalElements {
+    pub fn from_state_vector(parent_mass: f64, position: DVec2, velocity: DVec2) -> Self {
+        let conic = new_conic(parent_mass, position, velocity);
+        let argument_of_periapsis = conic.get_argument_of_periapsis();
+        let true_anomaly = normalize_angle(f64::atan2(position.y, position.x) - argument_of_periapsis);
+        Self {
+            semi_major_axis: conic.get_semi_major_axis(),
+            eccentricity: conic.get_eccentricity(),
+            argument_of_periapsis,
+            direction: conic.get_direction(),
+            anomaly: Anomaly::True(true_anomaly),
+        }
+    }
+
+    /// Parabolas (eccentricity of exactly 1) aren't supported, and hyperbolas can only be placed between their asymptotes
+    pub fn is_valid(&self) -> bool {
+        let shape_is_valid = if self.eccentricity < 1.0 {
+            self.eccentricity >= 0.0 && self.semi_major_axis > 0.0
+        } else {
+            self.eccentricity > 1.0 && self.semi_major_axis < 0.0
+        };
+        let anomaly_is_valid = match self.anomaly {
+            Anomaly::True(true_anomaly) => self.eccentricity < 1.0 || normalize_angle(true_anomaly).abs() < f64::acos(-1.0 / self.eccentricity),
+            Anomaly::TimeSincePeriapsis(time_since_periapsis) => time_since_periapsis.is_finite(),
+        };
+        shape_is_valid && anomaly_is_valid && self.argument_of_periapsis.is_finite()
+    }
+
+    pub(in super) fn to_conic(self, parent_mass: f64) -> Box<dyn Conic> {
+        new_conic_from_elements(parent_mass, self.semi_major_axis, self.eccentricity, self.argument_of_periapsis, self.direction)
+    }
+
+    /// The angle anticlockwise from the x axis, which is what the conic functions take
+    pub(in super) fn get_theta(&self, conic: &dyn Conic) -> f64 {
+        match self.anomaly {
+            Anomaly::True(true_anomaly) => normalize_angle(true_anomaly + self.argument_of_periapsis),
+            Anomaly::TimeSincePeriapsis(time_since_periapsis) => conic.get_theta_from_time_since_periapsis(time_since_periapsis),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use nalgebra_glm::vec2;
+
+    use crate::{components::trajectory_component::segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT, simulation::test_util::EARTH_MASS};
+
+    use super::*;
+
+    fn to_state_vector(elements: OrbitalElements, parent_mass: f64) -> (DVec2, DVec2) {
+        let conic = elements.to_conic(parent_mass);
+        let theta = elements.get_theta(&*conic);
+        let position = conic.get_position(theta);
+        let velocity = conic.get_velocity(position, theta);
+        (position, velocity)
+    }
+
+    fn angle_difference(a: f64, b: f64) -> f64 {
+        normalize_angle(a - b).abs()
+    }
+
+    fn assert_round_trip(elements: OrbitalElements) {
+        assert!(elements.is_valid());
+        let (position, velocity) = to_state_vector(elements, EARTH_MASS);
+        let new_elements = OrbitalElements::from_state_vector(EARTH_MASS, position, velocity);
+        let Anomaly::True(true_anomaly) = elements.anomaly else { unreachable!() };
+        let Anomaly::True(new_true_anomaly) = new_elements.anomaly else { unreachable!() };
+        assert!((new_elements.semi_major_axis - elements.semi_major_axis).abs() / elements.semi_major_axis.abs() < 1.0e-9);
+        // Eccentricity is found with a square root of something close to zero for near-circular orbits, which magnifies rounding errors
+        assert!((new_elements.eccentricity - elements.eccentricity).abs() < 1.0e-7);
+        assert_eq!(new_elements.direction, elements.direction);
+        // Argument of periapsis is meaningless for a circle
+        if elements.eccentricity > 1.0e-3 {
+            assert!(angle_difference(new_elements.argument_of_periapsis, elements.argument_of_periapsis) < 1.0e-6);
+            assert!(angle_difference(new_true_anomaly, true_anomaly) < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn test_ellipse_round_trip() {
+        for direction in [OrbitDirection::AntiClockwise, OrbitDirection::Clockwise] {
+            for eccentricity in [0.0, 0.1, 0.5, 0.9, 0.99] {
+                for argument_of_periapsis in [-2.5, -0.3, 0.0, 1.0, 3.0] {
+                    for true_anomaly in [-3.0, -1.5, 0.0, 0.7, 2.9] {
+                        let anomaly = Anomaly::True(true_anomaly);
+                        assert_round_trip(OrbitalElements { semi_major_axis: 1.0e7, eccentricity, argument_of_periapsis, direction, anomaly });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hyperbola_round_trip() {
+        for direction in [OrbitDirection::AntiClockwise, OrbitDirection::Clockwise] {
+            for eccentricity in [1.01, 1.5, 3.0, 10.0] {
+                let max_true_anomaly = f64::acos(-1.0 / eccentricity);
+                for argument_of_periapsis in [-2.5, -0.3, 0.0, 1.0, 3.0] {
+                    for fraction in [-0.9, -0.5, 0.0, 0.3, 0.9] {
+                        let anomaly = Anomaly::True(fraction * max_true_anomaly);
+                        assert_round_trip(OrbitalElements { semi_major_axis: -1.0e7, eccentricity, argument_of_periapsis, direction, anomaly });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_since_periapsis() {
+        // A quarter of the way through a circular orbit should be a quarter turn from periapsis
+        let semi_major_axis: f64 = 1.0e7;
+        let period = 2.0 * PI * f64::sqrt(semi_major_axis.powi(3) / (GRAVITATIONAL_CONSTANT * EARTH_MASS));
+        let anomaly = Anomaly::TimeSincePeriapsis(period / 4.0);
+        let elements = OrbitalElements { semi_major_axis, eccentricity: 0.0, argument_of_periapsis: 0.0, direction: OrbitDirection::AntiClockwise, anomaly };
+        let (position, velocity) = to_state_vector(elements, EARTH_MASS);
+        assert!((position - vec2(0.0, semi_major_axis)).magnitude() < 1.0);
+        assert!(velocity.x < 0.0);
+    }
+
+    #[test]
+    fn test_invalid_elements() {
+        let elements = OrbitalElements { semi_major_axis: 1.0e7, eccentricity: 1.5, argument_of_periapsis: 0.0, direction: OrbitDirection::AntiClockwise, anomaly: Anomaly::True(0.0) };
+        assert!(!elements.is_valid());
+        let elements = OrbitalElements { semi_major_axis: -1.0e7, eccentricity: 1.5, argument_of_periapsis: 0.0, direction: OrbitDirection::AntiClockwise, anomaly: Anomaly::True(3.0) };
+        assert!(!elements.is_valid());
+    }
+}
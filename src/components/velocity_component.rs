@@ -1,5 +1,7 @@
 use nalgebra_glm::DVec2;
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 pub struct VelocityComponent {
     absolute_velocity: DVec2,
 }
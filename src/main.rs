@@ -1,25 +1,40 @@
+use std::{env, process};
+
+use scenario::{Scenario, DEFAULT_SCENARIO};
+use simulation::Simulation;
 use state::State;
-use eframe::{CreationContext, NativeOptions, Renderer, run_native};
+use eframe::{NativeOptions, Renderer, run_native};
 
 mod camera;
 mod components;
+mod planner;
 mod storage;
 mod state;
 mod rendering;
 mod resources;
+mod save;
+mod scenario;
+mod simulation;
 mod systems;
 mod util;
 
-fn create_app(creation_context: &CreationContext<'_>) -> Box<dyn eframe::App> {
-    Box::new(State::new(creation_context))
-}
-
 fn main() -> Result<(), eframe::Error> {
+    // The scenario is loaded before the window is created, so a bad scenario fails immediately with a readable error
+    let scenario_path = env::args().nth(1).unwrap_or(DEFAULT_SCENARIO.to_string());
+    let mut simulation = Simulation::new();
+    let selected_entity = match Scenario::load(&scenario_path).and_then(|scenario| scenario.build(&mut simulation)) {
+        Ok(selected_entity) => selected_entity,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+
     let options = NativeOptions {
         renderer: Renderer::Glow,
         multisampling: 16,
         ..Default::default()
     };
     
-    run_native("Transfer Window", options, Box::new(create_app))
+    run_native("Transfer Window", options, Box::new(move |creation_context| Box::new(State::new(creation_context, simulation, selected_entity))))
 }
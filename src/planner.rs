@@ -0,0 +1,248 @@
+use std::{rc::Rc, cell::RefCell, f64::consts::PI};
+
+use nalgebra_glm::DVec2;
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::{manoeuvre::Manoeuvre, segment::{Segment, orbit::{Orbit, conic::normalize_angle, orbit_direction::{GRAVITATIONAL_CONSTANT, OrbitDirection}}}}, systems::util::get_segment_at_time};
+
+pub mod bi_elliptic;
+pub mod hohmann;
+pub mod lambert;
+pub mod porkchop;
+
+/// How many points per orbit of the spacecraft to check when searching for an angle, before narrowing down with bisection
+const PHASE_SEARCH_STEPS_PER_ORBIT: f64 = 36.0;
+const MAX_PHASE_SEARCH_STEPS: usize = 10000;
+const PHASE_BISECTION_ITERATIONS: usize = 60;
+/// Phasing departs from wherever the spacecraft happens to be when the angle lines up, with a tangential burn - on an eccentric
+/// orbit that burn isn't perpendicular to the radius, so the transfer's far side doesn't end up where the phasing assumed
+const MAX_PHASED_DEPARTURE_ECCENTRICITY: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferTarget {
+    /// Another body orbiting the same parent as the spacecraft, assumed to be on a roughly circular orbit
+    Body(Entity),
+    /// A circular orbit with this radius around the spacecraft's parent
+    CircularOrbit(f64),
+}
+
+/// The burns are all tangential, since transfers only ever speed up or slow down along the current velocity
+/// For transfers to a body, the transfer ends in an encounter, so there's no burn to match the body's orbit
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub start_time: f64,
+    pub burns: Vec<Manoeuvre>,
+    pub transfer_time: f64,
+}
+
+impl Transfer {
+    pub fn get_total_dv(&self) -> f64 {
+        self.burns.iter().map(|burn| burn.tangent_dv.abs()).sum()
+    }
+
+    pub fn get_departure_time(&self) -> f64 {
+        self.burns.first().unwrap().time
+    }
+}
+
+/// Everything a transfer needs to know about where it starts from - the spacecraft's orbit at the earliest time it's allowed to leave
+struct Departure {
+    orbit: Rc<RefCell<Orbit>>,
+    mu: f64,
+}
+
+impl Departure {
+    fn new(simulation: &Simulation, spacecraft: Entity, earliest_time: f64) -> Option<Self> {
+        let Segment::Orbit(orbit) = get_segment_at_time(simulation, &spacecraft, earliest_time) else {
+            return None;
+        };
+        // Hyperbolic orbits never come back round, so there's nothing to transfer from
+        orbit.borrow().get_period()?;
+        let parent_mass = simulation.components.mass_components.get(&orbit.borrow().get_parent()).unwrap().get_mass();
+        Some(Self { orbit, mu: GRAVITATIONAL_CONSTANT * parent_mass })
+    }
+
+    fn get_parent(&self) -> Entity {
+        self.orbit.borrow().get_parent()
+    }
+
+    fn get_position(&self, time: f64) -> DVec2 {
+        let orbit = self.orbit.borrow();
+        orbit.get_position_from_theta(orbit.get_theta_from_time(time))
+    }
+
+    fn get_velocity(&self, time: f64) -> DVec2 {
+        let orbit = self.orbit.borrow();
+        orbit.get_velocity_from_theta(orbit.get_theta_from_time(time))
+    }
+
+    fn get_speed(&self, time: f64) -> f64 {
+        self.get_velocity(time).magnitude()
+    }
+
+    fn get_apoapsis(&self) -> f64 {
+        let orbit = self.orbit.borrow();
+        orbit.get_semi_major_axis() * (1.0 + orbit.get_eccentricity())
+    }
+
+    /// The first time at or after earliest_time that the spacecraft passes its periapsis (or apoapsis)
+    fn get_next_apsis_time(&self, earliest_time: f64, apoapsis: bool) -> f64 {
+        let orbit = self.orbit.borrow();
+        let period = orbit.get_period().unwrap();
+        let offset = if apoapsis { period / 2.0 } else { 0.0 };
+        let time_since_apsis = (earliest_time - orbit.get_periapsis_time() - offset).rem_euclid(period);
+        if time_since_apsis == 0.0 {
+            earliest_time
+        } else {
+            earliest_time + period - time_since_apsis
+        }
+    }
+}
+
+/// Speed at the given radius on an orbit with the given semi-major axis
+fn vis_viva(mu: f64, radius: f64, semi_major_axis: f64) -> f64 {
+    f64::sqrt(mu * (2.0 / radius - 1.0 / semi_major_axis))
+}
+
+/// Half the period of an ellipse with the given semi-major axis
+fn half_period(mu: f64, semi_major_axis: f64) -> f64 {
+    PI * f64::sqrt(semi_major_axis.powi(3) / mu)
+}
+
+fn get_body_orbit(simulation: &Simulation, body: Entity, time: f64) -> Option<Rc<RefCell<Orbit>>> {
+    simulation.components.trajectory_components.get(&body)?;
+    match get_segment_at_time(simulation, &body, time) {
+        Segment::Orbit(orbit) => Some(orbit),
+        Segment::Burn(_) => None,
+    }
+}
+
+/// The radius the transfer needs to end at, or None if the target isn't something we can transfer to from this departure
+fn get_target_radius(simulation: &Simulation, departure: &Departure, target: TransferTarget, spacecraft: Entity, earliest_time: f64) -> Option<f64> {
+    match target {
+        TransferTarget::Body(body) => {
+            if body == spacecraft {
+                return None;
+            }
+            let orbit = get_body_orbit(simulation, body, earliest_time)?;
+            let orbit = orbit.borrow();
+            (orbit.get_parent() == departure.get_parent()).then(|| orbit.get_semi_major_axis())
+        }
+        TransferTarget::CircularOrbit(radius) => {
+            let sphere_of_influence = get_sphere_of_influence(simulation, departure.get_parent(), earliest_time);
+            (radius > 0.0 && radius < sphere_of_influence).then_some(radius)
+        }
+    }
+}
+
+/// Radius of the sphere of influence of the spacecraft's parent, which transfers have to stay inside
+fn get_sphere_of_influence(simulation: &Simulation, parent: Entity, time: f64) -> f64 {
+    let Some(orbit) = get_body_orbit(simulation, parent, time) else {
+        return f64::MAX;
+    };
+    let orbit = orbit.borrow();
+    let mass = simulation.components.mass_components.get(&parent).unwrap().get_mass();
+    let grandparent_mass = simulation.components.mass_components.get(&orbit.get_parent()).unwrap().get_mass();
+    orbit.get_semi_major_axis() * (mass / grandparent_mass).powf(2.0 / 5.0)
+}
+
+/// For transfers to a body, the spacecraft has to reach the body's orbit at the same time as the body itself
+/// arrival_angle is how far round the parent the spacecraft goes during the transfer (eg half an orbit for Hohmann),
+/// and get_transfer_time gives the length of the transfer when departing from a particular radius
+/// Returns the first departure time after earliest_time that lines up, within one synodic period, or None if the departure orbit
+/// is too eccentric for the departure point not to matter
+fn get_phased_departure_time(simulation: &Simulation, departure: &Departure, body: Entity, earliest_time: f64, arrival_angle: f64, get_transfer_time: impl Fn(f64) -> f64) -> Option<f64> {
+    if departure.orbit.borrow().get_eccentricity() > MAX_PHASED_DEPARTURE_ECCENTRICITY {
+        return None;
+    }
+    let body_orbit = get_body_orbit(simulation, body, earliest_time)?;
+    let body_trajectory = simulation.components.trajectory_components.get(&body).unwrap();
+    let prediction_end_time = body_trajectory.get_final_segment().get_end_time();
+    let spacecraft_period = departure.orbit.borrow().get_period().unwrap();
+    let body_period = body_orbit.borrow().get_period()?;
+    let synodic_period = 1.0 / (1.0 / spacecraft_period - 1.0 / body_period).abs();
+
+    // Positive when the body is ahead of where the spacecraft would arrive
+    let get_phase_error = |time: f64| -> Option<f64> {
+        let position = departure.get_position(time);
+        let arrival_time = time + get_transfer_time(position.magnitude());
+        if arrival_time > prediction_end_time {
+            return None;
+        }
+        let body_position = get_segment_at_time(simulation, &body, arrival_time).get_position_at_time(arrival_time);
+        let direction_sign = if matches!(departure.orbit.borrow().get_direction(), OrbitDirection::Clockwise) { -1.0 } else { 1.0 };
+        let arrival_position_angle = f64::atan2(position.y, position.x) + direction_sign * arrival_angle;
+        Some(direction_sign * normalize_angle(f64::atan2(body_position.y, body_position.x) - arrival_position_angle))
+    };
+
+    let steps = (synodic_period / spacecraft_period * PHASE_SEARCH_STEPS_PER_ORBIT).ceil() as usize;
+    find_first_angle_root(earliest_time, earliest_time + synodic_period, steps, get_phase_error)
+}
+
+/// The first time in [start_time, end_time] where an angle error (wrapped to (-PI, PI]) crosses zero
+/// The range is checked at evenly spaced points before narrowing down with bisection, so steps needs to be enough
+/// that the error can't cross zero and back again between two of them
+/// Gives up if the error can't be computed (eg because it would need a position past the end of prediction)
+fn find_first_angle_root(start_time: f64, end_time: f64, steps: usize, get_error: impl Fn(f64) -> Option<f64>) -> Option<f64> {
+    let steps = usize::min(steps, MAX_PHASE_SEARCH_STEPS).max(1);
+    let step = (end_time - start_time) / steps as f64;
+    let mut previous_time = start_time;
+    let mut previous_error = get_error(previous_time)?;
+    for i in 1..=steps {
+        let time = start_time + i as f64 * step;
+        let error = get_error(time)?;
+        // Wrapping round from -PI to PI also changes sign, but isn't a solution
+        if previous_error.signum() != error.signum() && previous_error.abs() < PI / 2.0 && error.abs() < PI / 2.0 {
+            let (mut low, mut high, mut low_error) = (previous_time, time, previous_error);
+            for _ in 0..PHASE_BISECTION_ITERATIONS {
+                let middle = (low + high) / 2.0;
+                let middle_error = get_error(middle)?;
+                if middle_error.signum() == low_error.signum() {
+                    low = middle;
+                    low_error = middle_error;
+                } else {
+                    high = middle;
+                }
+            }
+            return Some((low + high) / 2.0);
+        }
+        previous_time = time;
+        previous_error = error;
+    }
+    None
+}
+
+/// The manoeuvres that insert_transfer would throw away, so they can be shown before the transfer is chosen
+pub fn get_replaced_manoeuvres(simulation: &Simulation, spacecraft: Entity, transfer_start_time: f64) -> Vec<Manoeuvre> {
+    simulation.components.trajectory_components.get(&spacecraft).unwrap().get_manoeuvres()
+        .iter()
+        .map(|(_, manoeuvre)| *manoeuvre)
+        .filter(|manoeuvre| manoeuvre.time >= transfer_start_time)
+        .collect()
+}
+
+/// Replaces any manoeuvres planned after the start of the transfer (since the transfer was computed without them)
+/// with the transfer's burns - the spacecraft then has to be re-predicted from the transfer's start time
+pub fn insert_transfer(simulation: &mut Simulation, spacecraft: Entity, transfer: &Transfer) {
+    let trajectory_component = simulation.components.trajectory_components.get_mut(&spacecraft).unwrap();
+    trajectory_component.remove_manoeuvres_after(transfer.start_time);
+    for burn in &transfer.burns {
+        trajectory_component.add_manoeuvre(*burn);
+    }
+}
+
+#[cfg(test)]
+mod test_util {
+    use crate::{simulation::{Simulation, test_util::{earth_moon_simulation, add_circular_spacecraft}}, storage::entity_allocator::Entity, components::engine_component::EngineComponent};
+
+    pub const SPACECRAFT_RADIUS: f64 = 7.0e6;
+
+    /// A spacecraft in a circular orbit around the earth, with a strong enough engine that burns are close to impulsive,
+    /// and a moon on a circular orbit further out
+    pub fn transfer_simulation(end_time: f64) -> (Simulation, Entity, Entity) {
+        let (mut simulation, earth, moon) = earth_moon_simulation();
+        let engine = EngineComponent::new(1.0e6, 450.0, 1.0e3, 1.0e4);
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, SPACECRAFT_RADIUS, 1.0e4, Some(engine));
+        simulation.predict_until(end_time);
+        (simulation, spacecraft, moon)
+    }
+}
@@ -0,0 +1,73 @@
+use std::f64::consts::PI;
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::manoeuvre::Manoeuvre};
+
+use super::{Departure, Transfer, TransferTarget, get_target_radius, get_phased_departure_time, get_sphere_of_influence, vis_viva, half_period};
+
+/// Three burns - out to an intermediate radius, then moving the far side of the orbit to the target radius, then circularising
+/// Cheaper than a Hohmann transfer when the target radius is more than about 12 times the starting radius, but takes far longer
+/// The intermediate radius is intermediate_radius_factor times whichever is further out of the current apoapsis and the target,
+/// so the factor has to be at least 1, and the result has to be inside the parent's sphere of influence
+pub fn plan_bi_elliptic(simulation: &Simulation, spacecraft: Entity, target: TransferTarget, earliest_time: f64, intermediate_radius_factor: f64) -> Option<Transfer> {
+    let departure = Departure::new(simulation, spacecraft, earliest_time)?;
+    let target_radius = get_target_radius(simulation, &departure, target, spacecraft, earliest_time)?;
+    let intermediate_radius = intermediate_radius_factor * f64::max(departure.get_apoapsis(), target_radius);
+    let sphere_of_influence = get_sphere_of_influence(simulation, departure.get_parent(), earliest_time);
+    if intermediate_radius_factor < 1.0 || intermediate_radius > sphere_of_influence {
+        return None;
+    }
+    let mu = departure.mu;
+    let second_semi_major_axis = (intermediate_radius + target_radius) / 2.0;
+    let get_transfer_time = |radius: f64| half_period(mu, (radius + intermediate_radius) / 2.0) + half_period(mu, second_semi_major_axis);
+    let departure_time = match target {
+        // The spacecraft goes all the way round the parent, so arrives on the same side it left from
+        TransferTarget::Body(body) => get_phased_departure_time(simulation, &departure, body, earliest_time, 2.0 * PI, get_transfer_time)?,
+        TransferTarget::CircularOrbit(_) => departure.get_next_apsis_time(earliest_time, false),
+    };
+
+    let departure_radius = departure.get_position(departure_time).magnitude();
+    let first_semi_major_axis = (departure_radius + intermediate_radius) / 2.0;
+    let intermediate_time = departure_time + half_period(mu, first_semi_major_axis);
+    let departure_dv = vis_viva(mu, departure_radius, first_semi_major_axis) - departure.get_speed(departure_time);
+    let intermediate_dv = vis_viva(mu, intermediate_radius, second_semi_major_axis) - vis_viva(mu, intermediate_radius, first_semi_major_axis);
+    let mut burns = vec![
+        Manoeuvre { time: departure_time, tangent_dv: departure_dv, normal_dv: 0.0 },
+        Manoeuvre { time: intermediate_time, tangent_dv: intermediate_dv, normal_dv: 0.0 },
+    ];
+    let transfer_time = get_transfer_time(departure_radius);
+    if let TransferTarget::CircularOrbit(_) = target {
+        let arrival_dv = f64::sqrt(mu / target_radius) - vis_viva(mu, target_radius, second_semi_major_axis);
+        burns.push(Manoeuvre { time: departure_time + transfer_time, tangent_dv: arrival_dv, normal_dv: 0.0 });
+    }
+    Some(Transfer { start_time: earliest_time, burns, transfer_time })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{planner::{hohmann::plan_hohmann, test_util::{transfer_simulation, SPACECRAFT_RADIUS}}, simulation::test_util::MOON_DISTANCE};
+
+    use super::*;
+
+    #[test]
+    fn test_cheaper_than_hohmann_for_large_ratio() {
+        let (simulation, spacecraft, _) = transfer_simulation(1000.0);
+        let target = TransferTarget::CircularOrbit(20.0 * SPACECRAFT_RADIUS);
+        let hohmann = plan_hohmann(&simulation, spacecraft, target, 0.0).unwrap();
+        let bi_elliptic = plan_bi_elliptic(&simulation, spacecraft, target, 0.0, 2.5).unwrap();
+        assert_eq!(bi_elliptic.burns.len(), 3);
+        assert!(bi_elliptic.get_total_dv() < hohmann.get_total_dv());
+        assert!(bi_elliptic.transfer_time > hohmann.transfer_time);
+        // Out, then up at the far side, then back down to circularise
+        assert!(bi_elliptic.burns[0].tangent_dv > 0.0);
+        assert!(bi_elliptic.burns[1].tangent_dv > 0.0);
+        assert!(bi_elliptic.burns[2].tangent_dv < 0.0);
+    }
+
+    #[test]
+    fn test_intermediate_radius_must_be_outside_both_orbits() {
+        let (simulation, spacecraft, _) = transfer_simulation(1000.0);
+        let target = TransferTarget::CircularOrbit(MOON_DISTANCE / 2.0);
+        assert!(plan_bi_elliptic(&simulation, spacecraft, target, 0.0, 0.5).is_none());
+        assert!(plan_bi_elliptic(&simulation, spacecraft, target, 0.0, 2.0).is_some());
+    }
+}
@@ -0,0 +1,105 @@
+use std::f64::consts::PI;
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::manoeuvre::Manoeuvre};
+
+use super::{Departure, Transfer, TransferTarget, get_target_radius, get_phased_departure_time, vis_viva, half_period};
+
+/// Two burns - one to raise (or lower) the far side of the orbit to the target radius, and one to circularise when we get there
+/// Starting from an ellipse, going up departs from periapsis and going down departs from apoapsis, since that's where it's cheapest
+/// Burns are treated as instantaneous, so the longer the burns take the less accurate the result
+pub fn plan_hohmann(simulation: &Simulation, spacecraft: Entity, target: TransferTarget, earliest_time: f64) -> Option<Transfer> {
+    let departure = Departure::new(simulation, spacecraft, earliest_time)?;
+    let target_radius = get_target_radius(simulation, &departure, target, spacecraft, earliest_time)?;
+    let mu = departure.mu;
+    let get_transfer_time = |radius: f64| half_period(mu, (radius + target_radius) / 2.0);
+    let departure_time = match target {
+        TransferTarget::Body(body) => get_phased_departure_time(simulation, &departure, body, earliest_time, PI, get_transfer_time)?,
+        TransferTarget::CircularOrbit(_) => {
+            let is_lowering = target_radius < departure.orbit.borrow().get_semi_major_axis();
+            departure.get_next_apsis_time(earliest_time, is_lowering)
+        }
+    };
+
+    let departure_radius = departure.get_position(departure_time).magnitude();
+    let transfer_semi_major_axis = (departure_radius + target_radius) / 2.0;
+    let transfer_time = get_transfer_time(departure_radius);
+    let departure_dv = vis_viva(mu, departure_radius, transfer_semi_major_axis) - departure.get_speed(departure_time);
+    let mut burns = vec![Manoeuvre { time: departure_time, tangent_dv: departure_dv, normal_dv: 0.0 }];
+    if let TransferTarget::CircularOrbit(_) = target {
+        let arrival_dv = f64::sqrt(mu / target_radius) - vis_viva(mu, target_radius, transfer_semi_major_axis);
+        burns.push(Manoeuvre { time: departure_time + transfer_time, tangent_dv: arrival_dv, normal_dv: 0.0 });
+    }
+    Some(Transfer { start_time: earliest_time, burns, transfer_time })
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec2;
+
+    use crate::{planner::{insert_transfer, test_util::{transfer_simulation, SPACECRAFT_RADIUS}}, simulation::test_util::EARTH_MASS, storage::entity_builder::add_child_object, components::trajectory_component::segment::{Segment, orbit::orbit_direction::GRAVITATIONAL_CONSTANT}, systems::{util::get_segment_at_time, trajectory_prediction_system::spacecraft_prediction::predict_spacecraft}};
+
+    use super::*;
+
+    #[test]
+    fn test_hohmann_to_circular_orbit() {
+        let end_time = 100000.0;
+        let (mut simulation, spacecraft, _) = transfer_simulation(end_time);
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let r1 = SPACECRAFT_RADIUS;
+        let r2 = 4.2e7;
+        let transfer = plan_hohmann(&simulation, spacecraft, TransferTarget::CircularOrbit(r2), 100.0).unwrap();
+
+        let expected_departure_dv = f64::sqrt(mu / r1) * (f64::sqrt(2.0 * r2 / (r1 + r2)) - 1.0);
+        let expected_arrival_dv = f64::sqrt(mu / r2) * (1.0 - f64::sqrt(2.0 * r1 / (r1 + r2)));
+        let expected_transfer_time = PI * f64::sqrt(((r1 + r2) / 2.0).powi(3) / mu);
+        assert_eq!(transfer.burns.len(), 2);
+        assert!(transfer.get_departure_time() >= 100.0);
+        assert!((transfer.burns[0].tangent_dv - expected_departure_dv).abs() < 1.0e-3);
+        assert!((transfer.burns[1].tangent_dv - expected_arrival_dv).abs() < 1.0e-3);
+        assert!((transfer.transfer_time - expected_transfer_time).abs() < 1.0e-2);
+
+        // Burns aren't really instantaneous, but with a strong engine the result should still be close to the target orbit
+        insert_transfer(&mut simulation, spacecraft, &transfer);
+        predict_spacecraft(&mut simulation, spacecraft, transfer.start_time, end_time);
+        let arrival_time = transfer.get_departure_time() + transfer.transfer_time;
+        let Segment::Orbit(orbit) = get_segment_at_time(&simulation, &spacecraft, arrival_time + 1000.0) else {
+            panic!("Expected to be coasting after the transfer");
+        };
+        assert!((orbit.borrow().get_semi_major_axis() - r2).abs() / r2 < 1.0e-2);
+        assert!(orbit.borrow().get_eccentricity() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_hohmann_to_body() {
+        let (simulation, spacecraft, moon) = transfer_simulation(600000.0);
+        let transfer = plan_hohmann(&simulation, spacecraft, TransferTarget::Body(moon), 0.0).unwrap();
+        assert_eq!(transfer.burns.len(), 1);
+
+        // The moon should be on the opposite side of the earth to the departure point when the spacecraft gets there
+        let departure_time = transfer.get_departure_time();
+        let departure_position = get_segment_at_time(&simulation, &spacecraft, departure_time).get_position_at_time(departure_time);
+        let arrival_time = departure_time + transfer.transfer_time;
+        let moon_position = get_segment_at_time(&simulation, &moon, arrival_time).get_position_at_time(arrival_time);
+        assert!(departure_time >= 0.0);
+        assert!((moon_position.normalize() + departure_position.normalize()).magnitude() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_no_phased_transfer_from_eccentric_orbit() {
+        let (mut simulation, _, moon) = transfer_simulation(600000.0);
+        let earth = get_segment_at_time(&simulation, &moon, 0.0).get_parent();
+        let speed = 1.2 * f64::sqrt(GRAVITATIONAL_CONSTANT * EARTH_MASS / SPACECRAFT_RADIUS);
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "eccentric".to_string(), earth, vec2(SPACECRAFT_RADIUS, 0.0), vec2(0.0, speed), 1.0e4, None);
+        simulation.predict_until(600000.0);
+        assert!(plan_hohmann(&simulation, spacecraft, TransferTarget::Body(moon), 0.0).is_none());
+        // Transfers to a circular orbit depart from an apsis, so they're still fine
+        assert!(plan_hohmann(&simulation, spacecraft, TransferTarget::CircularOrbit(4.2e7), 0.0).is_some());
+    }
+
+    #[test]
+    fn test_no_transfer_to_self() {
+        let (simulation, spacecraft, _) = transfer_simulation(1000.0);
+        assert!(plan_hohmann(&simulation, spacecraft, TransferTarget::Body(spacecraft), 0.0).is_none());
+        assert!(plan_hohmann(&simulation, spacecraft, TransferTarget::CircularOrbit(-1.0), 0.0).is_none());
+    }
+}
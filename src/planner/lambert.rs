@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+
+use nalgebra_glm::{vec2, DVec2};
+
+use crate::components::trajectory_component::segment::orbit::{orbit_direction::OrbitDirection, conic::{stumpff_c, stumpff_s}};
+
+const BISECTION_ITERATIONS: usize = 200;
+/// Far enough into hyperbolic territory for any transfer we'd actually want, without overflowing cosh
+const MIN_Z: f64 = -1.0e5;
+
+/// Angle swept going from r1 to r2 in the given direction, in [0, 2PI)
+fn get_transfer_angle(r1: DVec2, r2: DVec2, direction: OrbitDirection) -> f64 {
+    let angle = f64::atan2(r1.x * r2.y - r1.y * r2.x, r1.dot(&r2));
+    let angle = match direction {
+        OrbitDirection::AntiClockwise => angle,
+        OrbitDirection::Clockwise => -angle,
+    };
+    angle.rem_euclid(2.0 * PI)
+}
+
+/// Finds the velocities at r1 and r2 of the conic that goes from r1 to r2 in time_of_flight, travelling in the given direction
+/// Uses the universal variable formulation, so works the same for ellipses and hyperbolas, but only finds transfers
+/// of less than one revolution
+/// Returns None for transfers of exactly 0 (or 360) degrees, which would have to go straight out from the parent
+pub fn solve_lambert(mu: f64, r1: DVec2, r2: DVec2, time_of_flight: f64, direction: OrbitDirection) -> Option<(DVec2, DVec2)> {
+    let r1_magnitude = r1.magnitude();
+    let r2_magnitude = r2.magnitude();
+    let transfer_angle = get_transfer_angle(r1, r2, direction);
+    if time_of_flight <= 0.0 || 1.0 - f64::cos(transfer_angle) < 1.0e-12 {
+        return None;
+    }
+    let a = f64::sin(transfer_angle) * f64::sqrt(r1_magnitude * r2_magnitude / (1.0 - f64::cos(transfer_angle)));
+    let get_y = |z: f64| r1_magnitude + r2_magnitude + a * (z * stumpff_s(z) - 1.0) / stumpff_c(z).sqrt();
+
+    // Time of flight increases with z, so bisect between a very hyperbolic transfer and one that takes a full revolution
+    // Where y is negative there's no solution, but it only happens for small z, so it counts as too short
+    let mut low = MIN_Z;
+    let mut high = 4.0 * PI.powi(2);
+    for _ in 0..BISECTION_ITERATIONS {
+        let z = (low + high) / 2.0;
+        let y = get_y(z);
+        if y < 0.0 {
+            low = z;
+            continue;
+        }
+        let time = ((y / stumpff_c(z)).powf(1.5) * stumpff_s(z) + a * y.sqrt()) / mu.sqrt();
+        if time < time_of_flight {
+            low = z;
+        } else {
+            high = z;
+        }
+    }
+
+    let z = (low + high) / 2.0;
+    let y = get_y(z);
+    if y < 0.0 || (z - MIN_Z).abs() < 1.0 {
+        return None;
+    }
+    // The usual f and g functions are 0/0 for 180 degree transfers (where a is 0), so instead the velocities are split into a
+    // transverse part from the angular momentum and a radial part from the universal Kepler equation, neither of which divide by a
+    let chi = f64::sqrt(y / stumpff_c(z));
+    let alpha = z / chi.powi(2);
+    let semi_latus_rectum = r1_magnitude * r2_magnitude * (1.0 - f64::cos(transfer_angle)) / y;
+    let angular_momentum = f64::sqrt(mu * semi_latus_rectum);
+    // r.v / sqrt(mu) at each end, from sqrt(mu) t = sigma1 chi^2 C + (1 - alpha r1) chi^3 S + r1 chi, where chi^2 C is y
+    let sigma1 = (mu.sqrt() * time_of_flight - (1.0 - alpha * r1_magnitude) * chi.powi(3) * stumpff_s(z) - r1_magnitude * chi) / y;
+    let sigma2 = (1.0 - alpha * r1_magnitude) * chi * (1.0 - z * stumpff_s(z)) + sigma1 * (1.0 - z * stumpff_c(z));
+    let get_velocity = |position: DVec2, sigma: f64| {
+        let radial = position.normalize();
+        let transverse = match direction {
+            OrbitDirection::AntiClockwise => vec2(-radial.y, radial.x),
+            OrbitDirection::Clockwise => vec2(radial.y, -radial.x),
+        };
+        (sigma * mu.sqrt() * radial + angular_momentum * transverse) / position.magnitude()
+    };
+    let v1 = get_velocity(r1, sigma1);
+    let v2 = get_velocity(r2, sigma2);
+    Some((v1, v2))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, EARTH_MASS}, components::trajectory_component::segment::orbit::{Orbit, orbit_direction::GRAVITATIONAL_CONSTANT}};
+
+    use super::*;
+
+    #[test]
+    fn test_quarter_circle() {
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let radius = 1.0e7;
+        let speed = f64::sqrt(mu / radius);
+        let quarter_period = PI / 2.0 * f64::sqrt(radius.powi(3) / mu);
+        let (v1, v2) = solve_lambert(mu, vec2(radius, 0.0), vec2(0.0, radius), quarter_period, OrbitDirection::AntiClockwise).unwrap();
+        assert!((v1 - vec2(0.0, speed)).magnitude() < 1.0e-6);
+        assert!((v2 - vec2(-speed, 0.0)).magnitude() < 1.0e-6);
+
+        // Going the other way round takes three quarters of an orbit, so doing it in a quarter needs a hyperbola
+        let (v1, _) = solve_lambert(mu, vec2(radius, 0.0), vec2(0.0, radius), quarter_period, OrbitDirection::Clockwise).unwrap();
+        assert!(v1.y < 0.0);
+        assert!(v1.magnitude() > f64::sqrt(2.0) * speed);
+    }
+
+    /// Lambert should give back the velocity we started with, for points along a known orbit
+    #[test]
+    fn test_recovers_orbit() {
+        let (simulation, earth) = earth_simulation();
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let position = vec2(7.0e6, 1.0e6);
+        for (velocity, direction) in [
+            (vec2(-1000.0, 9000.0), OrbitDirection::AntiClockwise),
+            (vec2(1000.0, -9000.0), OrbitDirection::Clockwise),
+            (vec2(-1000.0, 12000.0), OrbitDirection::AntiClockwise),
+        ] {
+            let orbit = Orbit::new(&simulation.components, earth, position, velocity, 0.0);
+            // Both less and more than half an orbit
+            for time_of_flight in [1000.0, 3000.0, 6000.0] {
+                if orbit.get_period().is_some_and(|period| time_of_flight > period) {
+                    continue;
+                }
+                let theta = orbit.get_theta_from_time(time_of_flight);
+                let end_position = orbit.get_position_from_theta(theta);
+                let end_velocity = orbit.get_velocity_from_theta(theta);
+                let (v1, v2) = solve_lambert(mu, position, end_position, time_of_flight, direction).unwrap();
+                assert!((v1 - velocity).magnitude() < 1.0e-3, "{:?} {:?}", v1, velocity);
+                assert!((v2 - end_velocity).magnitude() < 1.0e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        assert!(solve_lambert(mu, vec2(1.0e7, 0.0), vec2(2.0e7, 0.0), 1000.0, OrbitDirection::AntiClockwise).is_none());
+        assert!(solve_lambert(mu, vec2(1.0e7, 0.0), vec2(0.0, 2.0e7), -1.0, OrbitDirection::AntiClockwise).is_none());
+    }
+
+    /// Half an orbit is a Hohmann transfer, whose velocities are known exactly
+    #[test]
+    fn test_half_orbit() {
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let (r1, r2): (f64, f64) = (1.0e7, 2.0e7);
+        let semi_major_axis = (r1 + r2) / 2.0;
+        let time_of_flight = PI * f64::sqrt(semi_major_axis.powi(3) / mu);
+        let periapsis_speed = f64::sqrt(mu * (2.0 / r1 - 1.0 / semi_major_axis));
+        let apoapsis_speed = f64::sqrt(mu * (2.0 / r2 - 1.0 / semi_major_axis));
+        let (v1, v2) = solve_lambert(mu, vec2(r1, 0.0), vec2(-r2, 0.0), time_of_flight, OrbitDirection::AntiClockwise).unwrap();
+        assert!((v1 - vec2(0.0, periapsis_speed)).magnitude() < 1.0e-6);
+        assert!((v2 - vec2(0.0, -apoapsis_speed)).magnitude() < 1.0e-6);
+        let (v1, v2) = solve_lambert(mu, vec2(r1, 0.0), vec2(-r2, 0.0), time_of_flight, OrbitDirection::Clockwise).unwrap();
+        assert!((v1 - vec2(0.0, -periapsis_speed)).magnitude() < 1.0e-6);
+        assert!((v2 - vec2(0.0, apoapsis_speed)).magnitude() < 1.0e-6);
+    }
+
+    /// Transfers at and either side of 180 degrees along an eccentric orbit, starting away from periapsis so the transfer isn't symmetric
+    #[test]
+    fn test_near_half_orbit() {
+        let (simulation, earth) = earth_simulation();
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let position = vec2(7.0e6, 1.0e6);
+        let velocity = vec2(-1000.0, 9000.0);
+        let orbit = Orbit::new(&simulation.components, earth, position, velocity, 0.0);
+        let period = orbit.get_period().unwrap();
+        let start_theta = f64::atan2(position.y, position.x);
+        for offset in [0.0, 1.0e-12, -1.0e-12, 1.0e-6, -1.0e-6, 1.0e-2, -1.0e-2] {
+            let theta = start_theta + PI + offset;
+            let time_of_flight = (orbit.get_time_since_periapsis(theta) - orbit.get_time_since_periapsis(start_theta)).rem_euclid(period);
+            let end_position = orbit.get_position_from_theta(theta);
+            let end_velocity = orbit.get_velocity_from_theta(theta);
+            let (v1, v2) = solve_lambert(mu, position, end_position, time_of_flight, OrbitDirection::AntiClockwise).unwrap();
+            assert!((v1 - velocity).magnitude() < 1.0e-3, "{} {:?} {:?}", offset, v1, velocity);
+            assert!((v2 - end_velocity).magnitude() < 1.0e-3);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::f64::consts::PI;
+
+use nalgebra_glm::{DVec2, vec2};
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::{manoeuvre::Manoeuvre, segment::orbit::{conic::normalize_angle, orbit_direction::{GRAVITATIONAL_CONSTANT, OrbitDirection}}}, systems::util::get_segment_at_time};
+
+use super::{Departure, Transfer, get_body_orbit, half_period, find_first_angle_root, lambert::solve_lambert, PHASE_SEARCH_STEPS_PER_ORBIT};
+
+/// Number of departure times and arrival times, so the plot has this many squared cells
+pub const PORKCHOP_RESOLUTION: usize = 40;
+/// Flight times from this fraction to (2 - this fraction) of the Hohmann transfer time are covered
+const MIN_FLIGHT_TIME_FRACTION: f64 = 0.5;
+
+/// Where the transfer starts from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PorkchopOrigin {
+    /// The target orbits the same parent as the spacecraft, so the transfer starts from the spacecraft itself
+    Spacecraft,
+    /// The target orbits the same parent as the body the spacecraft is orbiting (eg another planet),
+    /// so the transfer starts from that body and the spacecraft has to escape from it first
+    Parent,
+}
+
+#[derive(Debug, Clone)]
+pub struct PorkchopCell {
+    pub departure_time: f64,
+    pub arrival_time: f64,
+    pub departure_dv: f64,
+    pub arrival_dv: f64,
+    /// Velocity relative to the origin needed at departure, ie the hyperbolic excess velocity when escaping a body
+    excess_velocity: DVec2,
+}
+
+impl PorkchopCell {
+    pub fn get_total_dv(&self) -> f64 {
+        self.departure_dv + self.arrival_dv
+    }
+}
+
+/// A grid of Lambert transfers between the origin and target, indexed by departure time and arrival time
+/// The departure dv is what the spacecraft would actually need to burn, while the arrival dv is just the
+/// speed relative to the target on arrival, since what to do once there is up to the player
+pub struct Porkchop {
+    spacecraft: Entity,
+    target: Entity,
+    origin: PorkchopOrigin,
+    start_time: f64,
+    departure_times: Vec<f64>,
+    arrival_times: Vec<f64>,
+    cells: Vec<Option<PorkchopCell>>,
+}
+
+/// Position and velocity of a body relative to its parent, or None if it hasn't been predicted that far
+fn get_body_state(simulation: &Simulation, body: Entity, time: f64) -> Option<(DVec2, DVec2)> {
+    let trajectory_component = simulation.components.trajectory_components.get(&body)?;
+    if time > trajectory_component.get_final_segment().get_end_time() {
+        return None;
+    }
+    let segment = get_segment_at_time(simulation, &body, time);
+    Some((segment.get_position_at_time(time), segment.get_velocity_at_time(time)))
+}
+
+fn linspace(start: f64, end: f64, count: usize) -> Vec<f64> {
+    (0..count).map(|i| start + (end - start) * i as f64 / (count - 1) as f64).collect()
+}
+
+fn rotate(vector: DVec2, angle: f64) -> DVec2 {
+    vec2(vector.x * f64::cos(angle) - vector.y * f64::sin(angle), vector.x * f64::sin(angle) + vector.y * f64::cos(angle))
+}
+
+impl Porkchop {
+    /// Departures are spread over one synodic period starting at earliest_time, since the pattern repeats after that,
+    /// and arrivals over the range of flight times either side of a Hohmann transfer
+    /// Returns None if the target isn't orbiting the same parent as either the spacecraft or the spacecraft's parent
+    pub fn compute(simulation: &Simulation, spacecraft: Entity, target: Entity, earliest_time: f64) -> Option<Self> {
+        let departure = Departure::new(simulation, spacecraft, earliest_time)?;
+        let target_orbit = get_body_orbit(simulation, target, earliest_time)?;
+        let parent_orbit = get_body_orbit(simulation, departure.get_parent(), earliest_time);
+        let (origin, origin_orbit) = if target_orbit.borrow().get_parent() == departure.get_parent() && target != spacecraft {
+            (PorkchopOrigin::Spacecraft, departure.orbit.clone())
+        } else if parent_orbit.as_ref().is_some_and(|parent_orbit| parent_orbit.borrow().get_parent() == target_orbit.borrow().get_parent()) && target != departure.get_parent() {
+            (PorkchopOrigin::Parent, parent_orbit.unwrap())
+        } else {
+            return None;
+        };
+
+        let central_body = target_orbit.borrow().get_parent();
+        let mu = GRAVITATIONAL_CONSTANT * simulation.components.mass_components.get(&central_body).unwrap().get_mass();
+        let origin_period = origin_orbit.borrow().get_period()?;
+        let target_period = target_orbit.borrow().get_period()?;
+        let synodic_period = f64::min(1.0 / (1.0 / origin_period - 1.0 / target_period).abs(), 10.0 * origin_period);
+        let hohmann_time = half_period(mu, (origin_orbit.borrow().get_semi_major_axis() + target_orbit.borrow().get_semi_major_axis()) / 2.0);
+        let departure_times = linspace(earliest_time, earliest_time + synodic_period, PORKCHOP_RESOLUTION);
+        let arrival_times = linspace(
+            earliest_time + MIN_FLIGHT_TIME_FRACTION * hohmann_time,
+            earliest_time + synodic_period + (2.0 - MIN_FLIGHT_TIME_FRACTION) * hohmann_time,
+            PORKCHOP_RESOLUTION);
+
+        let direction = origin_orbit.borrow().get_direction();
+        let get_origin_state = |time: f64| match origin {
+            PorkchopOrigin::Spacecraft => Some((departure.get_position(time), departure.get_velocity(time))),
+            PorkchopOrigin::Parent => get_body_state(simulation, departure.get_parent(), time),
+        };
+        let mut cells = vec![];
+        for departure_time in &departure_times {
+            for arrival_time in &arrival_times {
+                let cell = (|| {
+                    let (origin_position, origin_velocity) = get_origin_state(*departure_time)?;
+                    let (target_position, target_velocity) = get_body_state(simulation, target, *arrival_time)?;
+                    let (v1, v2) = solve_lambert(mu, origin_position, target_position, arrival_time - departure_time, direction)?;
+                    let excess_velocity = v1 - origin_velocity;
+                    let departure_dv = match origin {
+                        PorkchopOrigin::Spacecraft => excess_velocity.magnitude(),
+                        // Burning deep in the parent's gravity well is much cheaper than the excess velocity (Oberth effect)
+                        PorkchopOrigin::Parent => {
+                            let radius = departure.orbit.borrow().get_semi_major_axis();
+                            f64::sqrt(excess_velocity.magnitude_squared() + 2.0 * departure.mu / radius) - f64::sqrt(departure.mu / radius)
+                        }
+                    };
+                    let arrival_dv = (v2 - target_velocity).magnitude();
+                    Some(PorkchopCell { departure_time: *departure_time, arrival_time: *arrival_time, departure_dv, arrival_dv, excess_velocity })
+                })();
+                cells.push(cell);
+            }
+        }
+        Some(Self { spacecraft, target, origin, start_time: earliest_time, departure_times, arrival_times, cells })
+    }
+
+    pub fn get_start_time(&self) -> f64 {
+        self.start_time
+    }
+
+    pub fn get_spacecraft(&self) -> Entity {
+        self.spacecraft
+    }
+
+    pub fn get_target(&self) -> Entity {
+        self.target
+    }
+
+    pub fn get_cell(&self, departure_index: usize, arrival_index: usize) -> Option<&PorkchopCell> {
+        self.cells[departure_index * self.arrival_times.len() + arrival_index].as_ref()
+    }
+
+    pub fn get_departure_range(&self) -> (f64, f64) {
+        (*self.departure_times.first().unwrap(), *self.departure_times.last().unwrap())
+    }
+
+    pub fn get_arrival_range(&self) -> (f64, f64) {
+        (*self.arrival_times.first().unwrap(), *self.arrival_times.last().unwrap())
+    }
+
+    pub fn get_resolution(&self) -> (usize, usize) {
+        (self.departure_times.len(), self.arrival_times.len())
+    }
+
+    /// Lowest and highest total dv of any cell with a transfer
+    pub fn get_dv_range(&self) -> Option<(f64, f64)> {
+        let total_dvs = self.cells.iter().flatten().map(PorkchopCell::get_total_dv);
+        let min = total_dvs.clone().min_by(f64::total_cmp)?;
+        let max = total_dvs.max_by(f64::total_cmp)?;
+        Some((min, max))
+    }
+
+    pub fn get_cheapest_cell(&self) -> Option<&PorkchopCell> {
+        self.cells.iter()
+            .flatten()
+            .min_by(|a, b| a.get_total_dv().total_cmp(&b.get_total_dv()))
+    }
+
+    /// The burn that puts the spacecraft on the cell's transfer
+    /// Escaping from a parent, the burn is at the point of the (assumed circular) parking orbit where the escape hyperbola
+    /// leaves in the right direction, up to one orbit before the departure time - the time spent escaping is ignored
+    pub fn get_transfer(&self, simulation: &Simulation, cell: &PorkchopCell) -> Option<Transfer> {
+        let departure = Departure::new(simulation, self.spacecraft, self.start_time)?;
+        let (time, tangent_dv, normal_dv) = match self.origin {
+            PorkchopOrigin::Spacecraft => {
+                let tangent = departure.get_velocity(cell.departure_time).normalize();
+                let normal = vec2(-tangent.y, tangent.x);
+                (cell.departure_time, cell.excess_velocity.dot(&tangent), cell.excess_velocity.dot(&normal))
+            }
+            PorkchopOrigin::Parent => {
+                let orbit_period = departure.orbit.borrow().get_period().unwrap();
+                let radius = departure.orbit.borrow().get_semi_major_axis();
+                let excess_speed = cell.excess_velocity.magnitude();
+                let eccentricity = 1.0 + radius * excess_speed.powi(2) / departure.mu;
+                let asymptote_angle = f64::acos(-1.0 / eccentricity);
+                let direction_sign = if matches!(departure.orbit.borrow().get_direction(), OrbitDirection::Clockwise) { -1.0 } else { 1.0 };
+                let burn_direction = rotate(cell.excess_velocity, -direction_sign * (asymptote_angle - PI / 2.0));
+                let get_error = |time: f64| {
+                    let velocity = departure.get_velocity(time);
+                    Some(normalize_angle(f64::atan2(velocity.y, velocity.x) - f64::atan2(burn_direction.y, burn_direction.x)))
+                };
+                let search_start_time = f64::max(self.start_time, cell.departure_time - orbit_period);
+                let time = find_first_angle_root(search_start_time, search_start_time + orbit_period, PHASE_SEARCH_STEPS_PER_ORBIT as usize, get_error)?;
+                let burn_radius = departure.get_position(time).magnitude();
+                let tangent_dv = f64::sqrt(excess_speed.powi(2) + 2.0 * departure.mu / burn_radius) - departure.get_speed(time);
+                (time, tangent_dv, 0.0)
+            }
+        };
+        let burns = vec![Manoeuvre { time, tangent_dv, normal_dv }];
+        Some(Transfer { start_time: self.start_time, burns, transfer_time: cell.arrival_time - time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::epaint::Rgba;
+
+    use crate::{planner::{hohmann::plan_hohmann, insert_transfer, TransferTarget, test_util::transfer_simulation}, simulation::test_util::{sun_earth_simulation, add_circular_spacecraft, get_circular_speed}, systems::trajectory_prediction_system::spacecraft_prediction::predict_spacecraft, storage::entity_builder::add_child_celestial_object, components::engine_component::EngineComponent};
+
+    use super::*;
+
+    #[test]
+    fn test_cheapest_cell_close_to_hohmann() {
+        let (simulation, spacecraft, moon) = transfer_simulation(2.0e6);
+        let porkchop = Porkchop::compute(&simulation, spacecraft, moon, 0.0).unwrap();
+        let cheapest = porkchop.get_cheapest_cell().unwrap();
+        let hohmann = plan_hohmann(&simulation, spacecraft, TransferTarget::Body(moon), 0.0).unwrap();
+        // The grid is coarse, so this can only be roughly the same as the best possible transfer
+        assert!(cheapest.departure_dv < 1.2 * hohmann.get_total_dv());
+        assert!(cheapest.departure_dv > 0.99 * hohmann.get_total_dv());
+    }
+
+    /// Following the departure burn of a cell should get the spacecraft to the target at the cell's arrival time
+    #[test]
+    fn test_transfer_reaches_target() {
+        let (mut simulation, spacecraft, moon) = transfer_simulation(2.0e6);
+        let porkchop = Porkchop::compute(&simulation, spacecraft, moon, 0.0).unwrap();
+        let cell = porkchop.get_cheapest_cell().unwrap().clone();
+        let transfer = porkchop.get_transfer(&simulation, &cell).unwrap();
+        insert_transfer(&mut simulation, spacecraft, &transfer);
+        predict_spacecraft(&mut simulation, spacecraft, transfer.start_time, 2.0e6);
+
+        // By the time it gets there it's in the moon's sphere of influence
+        let segment = get_segment_at_time(&simulation, &spacecraft, cell.arrival_time);
+        assert_eq!(segment.get_parent(), moon);
+    }
+
+    #[test]
+    fn test_escape_from_parent() {
+        let (mut simulation, sun, earth) = sun_earth_simulation();
+        let mars_distance = 2.279e11;
+        let mars_speed = get_circular_speed(&simulation, sun, mars_distance);
+        let mars = add_child_celestial_object(&mut simulation.components, 0.0, "planet".to_string(), "mars".to_string(), sun, vec2(0.0, mars_distance), vec2(-mars_speed, 0.0), 6.417e23, 3.39e6, Rgba::WHITE);
+        let engine = EngineComponent::new(1.0e6, 450.0, 1.0e3, 1.0e4);
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 7.0e6, 1.0e4, Some(engine));
+        simulation.predict_until(1.0e8);
+
+        // Mars isn't orbiting the earth, so it goes via the earth's orbit around the sun
+        let porkchop = Porkchop::compute(&simulation, spacecraft, mars, 0.0).unwrap();
+        assert_eq!(porkchop.origin, PorkchopOrigin::Parent);
+        let cheapest = porkchop.get_cheapest_cell().unwrap();
+        // Hohmann from earth to mars is about 3.6 km/s from low earth orbit
+        assert!(cheapest.departure_dv > 3.0e3 && cheapest.departure_dv < 5.0e3);
+
+        let transfer = porkchop.get_transfer(&simulation, cheapest).unwrap();
+        assert!(transfer.get_departure_time() <= cheapest.departure_time);
+        assert!((transfer.burns[0].tangent_dv - cheapest.departure_dv).abs() < 100.0);
+    }
+}
@@ -6,6 +6,8 @@ use image::GenericImageView;
 
 use crate::rendering::texture;
 
+const TEXTURE_DIRECTORY: &str = "resources/textures";
+
 struct Texture {
     pub size: (i32, i32),
     pub bytes: Vec<u8>,
@@ -20,11 +22,8 @@ pub struct Resources {
 
 impl Resources {
     pub fn new() -> Self {
-        let texture_names = Self::get_entries("resources/textures".to_string())
-            .into_iter()
-            .map(|entry| Self::get_entry_name(&entry))
-            .collect();
-        let textures = Self::get_entries("resources/textures".to_string())
+        let texture_names = Self::find_texture_names();
+        let textures = Self::get_entries(TEXTURE_DIRECTORY.to_string())
             .into_iter()
             .map(|entry| (Self::get_entry_name(&entry), entry))
             .map(|entry| (entry.0, Self::load_texture(entry.1)))
@@ -32,6 +31,14 @@ impl Resources {
         Resources { texture_names, textures }
     }
 
+    /// Lists the textures without loading them, eg to check a scenario's icons before there's a window to load them into
+    pub fn find_texture_names() -> Vec<String> {
+        Self::get_entries(TEXTURE_DIRECTORY.to_string())
+            .into_iter()
+            .map(|entry| Self::get_entry_name(&entry))
+            .collect()
+    }
+
     fn get_entries(directory: String) -> Vec<DirEntry> {
         fs::read_dir(directory)
             .expect("Failed to read directory")
@@ -58,7 +65,7 @@ impl Resources {
         &self.texture_names
     }
 
-    pub fn get_texture_image(&self, name: &str) -> ImageSource {
+    pub fn get_texture_image(&self, name: &str) -> ImageSource<'_> {
         self.textures.get(name).unwrap_or_else(|| panic!("Texture {} does not exist", name)).image.clone()
     }
 
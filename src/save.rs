@@ -0,0 +1,181 @@
+use std::{fmt, fs, io, path::Path};
+
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, systems::trajectory_prediction_system::ephemeris::Ephemeris};
+
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.ron";
+
+/// Bump this whenever a change to the simulation or its components would stop older saves from loading correctly
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Read(String, io::Error),
+    Write(String, io::Error),
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Read(path, error) => write!(f, "Failed to read save file {}: {}", path, error),
+            SaveError::Write(path, error) => write!(f, "Failed to write save file {}: {}", path, error),
+            SaveError::Serialize(error) => write!(f, "Failed to serialize save: {}", error),
+            SaveError::Parse(error) => write!(f, "Failed to parse save: {}", error),
+            SaveError::UnsupportedVersion(version) => write!(f, "Save has version {}, but only version {} is supported", version, SAVE_VERSION),
+        }
+    }
+}
+
+/// Only the version is read at first, so that a save from a different version gives a clear error instead of a confusing parse failure
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct SaveFileRef<'a> {
+    version: u32,
+    selected_entity: Entity,
+    simulation: &'a Simulation,
+}
+
+#[derive(Deserialize)]
+struct SaveFile {
+    selected_entity: Entity,
+    simulation: Simulation,
+}
+
+/// Everything in the simulation is saved, including predicted trajectories and planned burns, so loading
+/// gives back exactly the same state rather than re-predicting (which could drift if prediction changes)
+pub fn save_to_string(simulation: &Simulation, selected_entity: Entity) -> Result<String, SaveError> {
+    let save_file = SaveFileRef { version: SAVE_VERSION, selected_entity, simulation };
+    ron::ser::to_string_pretty(&save_file, PrettyConfig::default()).map_err(SaveError::Serialize)
+}
+
+pub fn load_from_string(source: &str) -> Result<(Simulation, Entity), SaveError> {
+    let header: SaveHeader = ron::from_str(source).map_err(SaveError::Parse)?;
+    if header.version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(header.version));
+    }
+    let mut save_file: SaveFile = ron::from_str(source).map_err(SaveError::Parse)?;
+    save_file.simulation.ephemeris = Ephemeris::new(&save_file.simulation);
+    Ok((save_file.simulation, save_file.selected_entity))
+}
+
+pub fn save(path: &str, simulation: &Simulation, selected_entity: Entity) -> Result<(), SaveError> {
+    let source = save_to_string(simulation, selected_entity)?;
+    if let Some(directory) = Path::new(path).parent() {
+        fs::create_dir_all(directory).map_err(|error| SaveError::Write(path.to_string(), error))?;
+    }
+    fs::write(path, source).map_err(|error| SaveError::Write(path.to_string(), error))
+}
+
+pub fn load(path: &str) -> Result<(Simulation, Entity), SaveError> {
+    let source = fs::read_to_string(path).map_err(|error| SaveError::Read(path.to_string(), error))?;
+    load_from_string(&source)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::DVec2;
+
+    use crate::{scenario::{Scenario, DEFAULT_SCENARIO}, components::trajectory_component::{manoeuvre::Manoeuvre, prediction_horizon::PredictionHorizon, segment::Segment}, systems::trajectory_prediction_system::spacecraft_prediction::predict_spacecraft};
+
+    use super::*;
+
+    const END_TIME: f64 = 1000000.0;
+
+    fn add_burn(simulation: &mut Simulation, entity: Entity, time: f64) {
+        let manoeuvre = Manoeuvre { time, tangent_dv: 1000.0, normal_dv: 0.0 };
+        simulation.components.trajectory_components.get_mut(&entity).unwrap().add_manoeuvre(manoeuvre);
+        predict_spacecraft(simulation, entity, time, END_TIME);
+    }
+
+    /// Every value here should come back bit for bit identical after a round trip
+    fn describe_segment(segment: &Segment) -> (bool, Entity, f64, f64, DVec2, DVec2, DVec2, DVec2) {
+        match segment {
+            Segment::Burn(burn) => {
+                let burn = burn.borrow();
+                (true, burn.get_parent(), burn.get_start_time(), burn.get_end_time(), burn.get_start_position(), burn.get_end_position(), burn.get_start_velocity(), burn.get_end_velocity())
+            }
+            Segment::Orbit(orbit) => {
+                let orbit = orbit.borrow();
+                (false, orbit.get_parent(), orbit.get_start_time(), orbit.get_end_time(), orbit.get_start_position(), orbit.get_end_position(), orbit.get_start_velocity(), orbit.get_end_velocity())
+            }
+        }
+    }
+
+    fn assert_simulations_equal(a: &Simulation, b: &Simulation) {
+        assert_eq!(a.time, b.time);
+        assert_eq!(a.components.entity_allocator.get_entities(), b.components.entity_allocator.get_entities());
+        for entity in a.components.entity_allocator.get_entities() {
+            assert_eq!(a.components.position_components.get(&entity).unwrap().get_absolute_position(), b.components.position_components.get(&entity).unwrap().get_absolute_position());
+            assert_eq!(a.components.velocity_components.get(&entity).unwrap().get_absolute_velocity(), b.components.velocity_components.get(&entity).unwrap().get_absolute_velocity());
+            assert_eq!(a.components.mass_components.get(&entity).map(|mass| mass.get_mass()), b.components.mass_components.get(&entity).map(|mass| mass.get_mass()));
+            assert_eq!(a.components.engine_components.get(&entity), b.components.engine_components.get(&entity));
+            let (Some(trajectory_a), Some(trajectory_b)) = (a.components.trajectory_components.get(&entity), b.components.trajectory_components.get(&entity)) else {
+                assert!(a.components.trajectory_components.get(&entity).is_none() && b.components.trajectory_components.get(&entity).is_none());
+                continue;
+            };
+            // The planned manoeuvres have to come back too, not just the burns already predicted from them
+            assert_eq!(trajectory_a.get_manoeuvres(), trajectory_b.get_manoeuvres());
+            assert_eq!(trajectory_a.get_next_manoeuvre_id(), trajectory_b.get_next_manoeuvre_id());
+            assert_eq!(trajectory_a.get_horizon(), trajectory_b.get_horizon());
+            assert_eq!(trajectory_a.get_segments().len(), trajectory_b.get_segments().len());
+            for (segment_a, segment_b) in trajectory_a.get_segments().iter().zip(trajectory_b.get_segments()) {
+                assert_eq!(describe_segment(segment_a), describe_segment(segment_b));
+                let start_time = segment_a.get_start_time();
+                for i in 0..10 {
+                    let time = start_time + i as f64 * 100.0;
+                    if let Segment::Burn(burn) = segment_a {
+                        if time >= burn.borrow().get_end_time() {
+                            break;
+                        }
+                    }
+                    assert_eq!(segment_a.get_position_at_time(time), segment_b.get_position_at_time(time));
+                    assert_eq!(segment_a.get_velocity_at_time(time), segment_b.get_velocity_at_time(time));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_with_burn() {
+        let mut simulation = Simulation::new();
+        let spacecraft = Scenario::load(DEFAULT_SCENARIO).unwrap().build(&mut simulation).unwrap();
+        simulation.predict_until(END_TIME);
+        simulation.update(1000.0);
+        add_burn(&mut simulation, spacecraft, 3000.0);
+        // Leaves a gap in the ids, so the counter can't just be worked out from the manoeuvres
+        let trajectory_component = simulation.components.trajectory_components.get_mut(&spacecraft).unwrap();
+        let id = trajectory_component.add_manoeuvre(Manoeuvre { time: 8000.0, tangent_dv: 100.0, normal_dv: 0.0 });
+        trajectory_component.remove_manoeuvre(id);
+        trajectory_component.set_horizon(PredictionHorizon::Orbits(2.0));
+        assert!(!trajectory_component.get_manoeuvres().is_empty());
+
+        let source = save_to_string(&simulation, spacecraft).unwrap();
+        let (mut loaded_simulation, loaded_selected) = load_from_string(&source).unwrap();
+        assert_eq!(loaded_selected, spacecraft);
+        assert_simulations_equal(&simulation, &loaded_simulation);
+
+        // Both should carry on identically, including through the burn
+        for _ in 0..20 {
+            simulation.update(500.0);
+            loaded_simulation.update(500.0);
+        }
+        assert_simulations_equal(&simulation, &loaded_simulation);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut simulation = Simulation::new();
+        let selected = Scenario::load(DEFAULT_SCENARIO).unwrap().build(&mut simulation).unwrap();
+        let source = save_to_string(&simulation, selected).unwrap().replacen(&format!("version: {}", SAVE_VERSION), "version: 9999", 1);
+        assert!(matches!(load_from_string(&source), Err(SaveError::UnsupportedVersion(9999))));
+    }
+}
@@ -0,0 +1,344 @@
+use std::{collections::HashMap, fmt, fs, io};
+
+use eframe::epaint::Rgba;
+use nalgebra_glm::{vec2, DVec2};
+use serde::Deserialize;
+
+use crate::{resources::Resources, simulation::Simulation, storage::{entity_allocator::Entity, entity_builder::{add_root_object, add_child_celestial_object, add_child_object, add_child_celestial_object_from_elements, add_child_object_from_elements}}, components::{engine_component::EngineComponent, trajectory_component::segment::orbit::orbital_elements::OrbitalElements}};
+
+pub const DEFAULT_SCENARIO: &str = "resources/scenarios/default.ron";
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Read(String, io::Error),
+    Parse(ron::error::SpannedError),
+    DuplicateName(String),
+    UnknownParent { object: String, parent: String },
+    ParentNotCelestialBody { object: String, parent: String },
+    RootNotCelestialBody(String),
+    ParentCycle(String),
+    UnknownSelected(String),
+    RootHasOrbitalElements(String),
+    InvalidOrbitalElements(String),
+    InvalidEngine(String),
+    UnknownIcon { object: String, icon: String },
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Read(path, error) => write!(f, "Failed to read scenario file {}: {}", path, error),
+            ScenarioError::Parse(error) => write!(f, "Failed to parse scenario: {}", error),
+            ScenarioError::DuplicateName(name) => write!(f, "More than one object is named '{}'", name),
+            ScenarioError::UnknownParent { object, parent } => write!(f, "Object '{}' has parent '{}', but no object with that name exists", object, parent),
+            ScenarioError::ParentNotCelestialBody { object, parent } => write!(f, "Object '{}' has parent '{}', which is not a celestial body", object, parent),
+            ScenarioError::RootNotCelestialBody(name) => write!(f, "Object '{}' has no parent, so it must be a celestial body", name),
+            ScenarioError::ParentCycle(name) => write!(f, "Object '{}' is part of a cycle of parents and never reaches a root object", name),
+            ScenarioError::UnknownSelected(name) => write!(f, "Selected object '{}' does not exist", name),
+            ScenarioError::RootHasOrbitalElements(name) => write!(f, "Object '{}' has no parent to orbit, so it can't be placed with orbital elements", name),
+            ScenarioError::InvalidOrbitalElements(name) => write!(f, "Object '{}' has orbital elements that don't describe a valid ellipse or hyperbola", name),
+            ScenarioError::InvalidEngine(name) => write!(f, "Object '{}' has an engine, but it's a celestial body or its engine's thrust, specific impulse or dry mass are invalid", name),
+            ScenarioError::UnknownIcon { object, icon } => write!(f, "Object '{}' has icon '{}', but there's no texture with that name", object, icon),
+        }
+    }
+}
+
+/// Position and velocity are relative to the object's parent
+#[derive(Debug, Deserialize)]
+enum InitialState {
+    StateVector { position: (f64, f64), velocity: (f64, f64) },
+    OrbitalElements(OrbitalElements),
+}
+
+#[derive(Debug, Deserialize)]
+struct CelestialBodyDescription {
+    radius: f64,
+    color: (f32, f32, f32, f32),
+}
+
+/// The object starts out fully fuelled, so its mass is also the engine's wet mass
+#[derive(Debug, Deserialize)]
+struct EngineDescription {
+    thrust: f64,
+    specific_impulse: f64,
+    dry_mass: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectDescription {
+    name: String,
+    icon: String,
+    #[serde(default)]
+    parent: Option<String>,
+    mass: f64,
+    #[serde(default)]
+    celestial_body: Option<CelestialBodyDescription>,
+    #[serde(default)]
+    initial_state: Option<InitialState>,
+    #[serde(default)]
+    engine: Option<EngineDescription>,
+}
+
+impl ObjectDescription {
+    fn get_position_and_velocity(&self) -> (DVec2, DVec2) {
+        match &self.initial_state {
+            Some(InitialState::StateVector { position, velocity }) => (vec2(position.0, position.1), vec2(velocity.0, velocity.1)),
+            Some(InitialState::OrbitalElements(_)) => unreachable!("Objects with orbital elements are added separately"),
+            None => (vec2(0.0, 0.0), vec2(0.0, 0.0)),
+        }
+    }
+
+    fn get_engine(&self) -> Option<EngineComponent> {
+        self.engine.as_ref().map(|engine| EngineComponent::new(engine.thrust, engine.specific_impulse, engine.dry_mass, self.mass))
+    }
+}
+
+/// Describes the objects in a star system and how they start out
+/// Objects can be listed in any order, as long as every parent is a celestial body that eventually leads back to a root object
+#[derive(Debug, Deserialize)]
+pub struct Scenario {
+    selected: String,
+    objects: Vec<ObjectDescription>,
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self, ScenarioError> {
+        let source = fs::read_to_string(path).map_err(|error| ScenarioError::Read(path.to_string(), error))?;
+        Self::parse(&source)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ScenarioError> {
+        ron::from_str(source).map_err(ScenarioError::Parse)
+    }
+
+    fn validate(&self) -> Result<(), ScenarioError> {
+        let texture_names = Resources::find_texture_names();
+        let mut objects: HashMap<&String, &ObjectDescription> = HashMap::new();
+        for object in &self.objects {
+            if objects.insert(&object.name, object).is_some() {
+                return Err(ScenarioError::DuplicateName(object.name.clone()));
+            }
+        }
+
+        for object in &self.objects {
+            if !texture_names.contains(&object.icon) {
+                return Err(ScenarioError::UnknownIcon { object: object.name.clone(), icon: object.icon.clone() });
+            }
+            if let Some(InitialState::OrbitalElements(elements)) = &object.initial_state {
+                if object.parent.is_none() {
+                    return Err(ScenarioError::RootHasOrbitalElements(object.name.clone()));
+                }
+                if !elements.is_valid() {
+                    return Err(ScenarioError::InvalidOrbitalElements(object.name.clone()));
+                }
+            }
+            if let Some(engine) = &object.engine {
+                let is_valid = engine.thrust > 0.0 && engine.specific_impulse > 0.0 && engine.dry_mass > 0.0 && engine.dry_mass <= object.mass;
+                if object.celestial_body.is_some() || !is_valid {
+                    return Err(ScenarioError::InvalidEngine(object.name.clone()));
+                }
+            }
+            match &object.parent {
+                Some(parent) => {
+                    let Some(parent_object) = objects.get(parent) else {
+                        return Err(ScenarioError::UnknownParent { object: object.name.clone(), parent: parent.clone() });
+                    };
+                    if parent_object.celestial_body.is_none() {
+                        return Err(ScenarioError::ParentNotCelestialBody { object: object.name.clone(), parent: parent.clone() });
+                    }
+                }
+                None => {
+                    if object.celestial_body.is_none() {
+                        return Err(ScenarioError::RootNotCelestialBody(object.name.clone()));
+                    }
+                }
+            }
+        }
+
+        if !objects.contains_key(&self.selected) {
+            return Err(ScenarioError::UnknownSelected(self.selected.clone()));
+        }
+        Ok(())
+    }
+
+    fn add_object(simulation: &mut Simulation, object: &ObjectDescription, parent: Option<Entity>) -> Entity {
+        let name = object.name.clone();
+        let icon = object.icon.clone();
+        if let Some(InitialState::OrbitalElements(elements)) = &object.initial_state {
+            let parent = parent.expect("Root objects are checked not to have orbital elements during validation");
+            return match &object.celestial_body {
+                Some(body) => {
+                    let color = Rgba::from_rgba_unmultiplied(body.color.0, body.color.1, body.color.2, body.color.3);
+                    add_child_celestial_object_from_elements(&mut simulation.components, simulation.time, icon, name, parent, *elements, object.mass, body.radius, color)
+                }
+                None => add_child_object_from_elements(&mut simulation.components, simulation.time, icon, name, parent, *elements, object.mass, object.get_engine()),
+            };
+        }
+        let (position, velocity) = object.get_position_and_velocity();
+        match (parent, &object.celestial_body) {
+            (None, Some(body)) => {
+                let color = Rgba::from_rgba_unmultiplied(body.color.0, body.color.1, body.color.2, body.color.3);
+                add_root_object(&mut simulation.components, icon, name, position, velocity, object.mass, body.radius, color)
+            }
+            (Some(parent), Some(body)) => {
+                let color = Rgba::from_rgba_unmultiplied(body.color.0, body.color.1, body.color.2, body.color.3);
+                add_child_celestial_object(&mut simulation.components, simulation.time, icon, name, parent, position, velocity, object.mass, body.radius, color)
+            }
+            (Some(parent), None) => {
+                add_child_object(&mut simulation.components, simulation.time, icon, name, parent, position, velocity, object.mass, object.get_engine())
+            }
+            (None, None) => unreachable!("Root objects are checked to be celestial bodies during validation"),
+        }
+    }
+
+    /// Adds every object in the scenario to the simulation, and returns the entity that should start out selected
+    /// Parents are always added before their children, since a child's absolute position depends on its parent
+    pub fn build(&self, simulation: &mut Simulation) -> Result<Entity, ScenarioError> {
+        self.validate()?;
+        let mut entities: HashMap<&String, Entity> = HashMap::new();
+        loop {
+            let mut added_any = false;
+            for object in &self.objects {
+                if entities.contains_key(&object.name) {
+                    continue;
+                }
+                let parent = match &object.parent {
+                    Some(parent) => match entities.get(parent) {
+                        Some(parent) => Some(*parent),
+                        None => continue,
+                    },
+                    None => None,
+                };
+                entities.insert(&object.name, Self::add_object(simulation, object, parent));
+                added_any = true;
+            }
+            if !added_any {
+                break;
+            }
+        }
+
+        // Anything that still hasn't been added must have parents which never lead back to a root
+        if let Some(object) = self.objects.iter().find(|object| !entities.contains_key(&object.name)) {
+            return Err(ScenarioError::ParentCycle(object.name.clone()));
+        }
+        Ok(entities[&self.selected])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUN: &str = r#"(name: "sun", icon: "star", mass: 1.9885e30, celestial_body: Some((radius: 6.957e8, color: (1.0, 1.0, 0.3, 1.0))))"#;
+
+    #[test]
+    fn test_default_scenario() {
+        let mut simulation = Simulation::new();
+        let selected = Scenario::load(DEFAULT_SCENARIO).unwrap().build(&mut simulation).unwrap();
+        assert_eq!(simulation.components.name_components.get(&selected).unwrap().get_name(), "spacecraft");
+        assert_eq!(simulation.components.entity_allocator.get_entities().len(), 4);
+    }
+
+    #[test]
+    fn test_children_listed_before_parents() {
+        let source = format!(r#"(selected: "earth", objects: [
+            (name: "earth", icon: "planet", parent: Some("sun"), mass: 5.9722e24, celestial_body: Some((radius: 6.378e6, color: (0.1, 0.4, 1.0, 1.0))),
+                initial_state: Some(StateVector(position: (1.521e11, 0.0), velocity: (0.0, -2.729e4)))),
+            {},
+        ])"#, SUN);
+        let mut simulation = Simulation::new();
+        let earth = Scenario::parse(&source).unwrap().build(&mut simulation).unwrap();
+        let parent = simulation.components.parent_components.get(&earth).unwrap().get_parent();
+        assert_eq!(simulation.components.name_components.get(&parent).unwrap().get_name(), "sun");
+        let position = simulation.components.position_components.get(&earth).unwrap().get_absolute_position();
+        assert!((position - vec2(1.521e11, 0.0)).magnitude() < 1.0);
+    }
+
+    #[test]
+    fn test_unknown_parent() {
+        let source = format!(r#"(selected: "sun", objects: [
+            {},
+            (name: "spacecraft", icon: "spacecraft", parent: Some("earth"), mass: 1.0e3,
+                initial_state: Some(StateVector(position: (0.0, 8.0e6), velocity: (-0.987e4, 0.0)))),
+        ])"#, SUN);
+        let result = Scenario::parse(&source).unwrap().build(&mut Simulation::new());
+        assert!(matches!(result, Err(ScenarioError::UnknownParent { object, parent }) if object == "spacecraft" && parent == "earth"));
+    }
+
+    #[test]
+    fn test_parent_not_celestial_body() {
+        let source = format!(r#"(selected: "sun", objects: [
+            {},
+            (name: "spacecraft", icon: "spacecraft", parent: Some("sun"), mass: 1.0e3,
+                initial_state: Some(StateVector(position: (0.0, 8.0e9), velocity: (-1.0e5, 0.0)))),
+            (name: "probe", icon: "spacecraft", parent: Some("spacecraft"), mass: 1.0e2,
+                initial_state: Some(StateVector(position: (0.0, 1.0e3), velocity: (-1.0, 0.0)))),
+        ])"#, SUN);
+        let result = Scenario::parse(&source).unwrap().build(&mut Simulation::new());
+        assert!(matches!(result, Err(ScenarioError::ParentNotCelestialBody { object, parent }) if object == "probe" && parent == "spacecraft"));
+    }
+
+    #[test]
+    fn test_orbital_elements() {
+        let source = format!(r#"(selected: "spacecraft", objects: [
+            {},
+            (name: "spacecraft", icon: "spacecraft", parent: Some("sun"), mass: 1.0e3,
+                initial_state: Some(OrbitalElements((semi_major_axis: 1.0e11, eccentricity: 0.5, argument_of_periapsis: 1.0, direction: AntiClockwise, anomaly: True(0.0))))),
+        ])"#, SUN);
+        let mut simulation = Simulation::new();
+        let spacecraft = Scenario::parse(&source).unwrap().build(&mut simulation).unwrap();
+        // Starting at periapsis, which is a(1 - e) from the sun in the direction of the argument of periapsis
+        let position = simulation.components.position_components.get(&spacecraft).unwrap().get_absolute_position();
+        let expected_position = vec2(f64::cos(1.0), f64::sin(1.0)) * 0.5e11;
+        assert!((position - expected_position).magnitude() < 1.0);
+    }
+
+    #[test]
+    fn test_invalid_orbital_elements() {
+        let source = format!(r#"(selected: "sun", objects: [
+            {},
+            (name: "spacecraft", icon: "spacecraft", parent: Some("sun"), mass: 1.0e3,
+                initial_state: Some(OrbitalElements((semi_major_axis: 1.0e11, eccentricity: 1.5, argument_of_periapsis: 0.0, direction: Clockwise, anomaly: True(0.0))))),
+        ])"#, SUN);
+        let result = Scenario::parse(&source).unwrap().build(&mut Simulation::new());
+        assert!(matches!(result, Err(ScenarioError::InvalidOrbitalElements(name)) if name == "spacecraft"));
+    }
+
+    #[test]
+    fn test_engine() {
+        let engine = r#"engine: Some((thrust: 2.0e4, specific_impulse: 350.0, dry_mass: 4.0e3))"#;
+        let source = format!(r#"(selected: "spacecraft", objects: [
+            {},
+            (name: "spacecraft", icon: "spacecraft", parent: Some("sun"), mass: 1.0e4, {},
+                initial_state: Some(StateVector(position: (0.0, 8.0e9), velocity: (-1.0e5, 0.0)))),
+        ])"#, SUN, engine);
+        let mut simulation = Simulation::new();
+        let spacecraft = Scenario::parse(&source).unwrap().build(&mut simulation).unwrap();
+        let engine_component = simulation.components.engine_components.get(&spacecraft).unwrap();
+        assert_eq!(engine_component.get_dry_mass(), 4.0e3);
+        assert_eq!(engine_component.get_wet_mass(), 1.0e4);
+
+        // Dry mass can't be more than the starting mass
+        let source = source.replace("mass: 1.0e4", "mass: 1.0e3");
+        let result = Scenario::parse(&source).unwrap().build(&mut Simulation::new());
+        assert!(matches!(result, Err(ScenarioError::InvalidEngine(name)) if name == "spacecraft"));
+    }
+
+    #[test]
+    fn test_unknown_icon() {
+        let source = format!(r#"(selected: "sun", objects: [
+            {},
+            (name: "spacecraft", icon: "spacecarft", parent: Some("sun"), mass: 1.0e3,
+                initial_state: Some(StateVector(position: (0.0, 8.0e9), velocity: (-1.0e5, 0.0)))),
+        ])"#, SUN);
+        let result = Scenario::parse(&source).unwrap().build(&mut Simulation::new());
+        assert!(matches!(result, Err(ScenarioError::UnknownIcon { object, icon }) if object == "spacecraft" && icon == "spacecarft"));
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let source = r#"(selected: "sun", objects: [(name: "sun", icon: "star", celestial_body: Some((radius: 6.957e8, color: (1.0, 1.0, 0.3, 1.0))))])"#;
+        let error = Scenario::parse(source).unwrap_err();
+        assert!(error.to_string().contains("mass"));
+    }
+}
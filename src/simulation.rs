@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{components::{Components, trajectory_component::segment::burn::integrator::Integrator}, storage::entity_allocator::Entity, systems::{trajectory_update_system::trajectory_update_system, trajectory_prediction_system::{horizon::{extend_predictions, extend_predictions_to, invalidate_prediction}, ephemeris::Ephemeris}}};
+
+#[cfg(test)]
+pub mod test_util;
+
+/// Owns everything needed to run the orbital simulation - entities, their components, and the current time
+/// Nothing in here depends on egui or a GL context, so it can be created and stepped without a window
+/// (eg in tests or command line tools), while State wraps it with everything needed for the UI
+#[derive(Serialize, Deserialize)]
+pub struct Simulation {
+    pub components: Components,
+    pub time: f64,
+    /// Used for any new burns - existing burns keep whatever they were computed with
+    pub burn_integrator: Integrator,
+    /// Not saved, since it can be rebuilt from the celestial bodies' trajectories
+    #[serde(skip)]
+    pub ephemeris: Ephemeris,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self { components: Components::new(), time: 0.0, burn_integrator: Integrator::default(), ephemeris: Ephemeris::default() }
+    }
+
+    /// Extends every trajectory that's getting close to the end of its prediction up to its horizon - so from scratch, predicts everything
+    /// This is the slow part, so the UI does it on a background prediction rather than calling it directly
+    pub fn predict(&mut self) {
+        extend_predictions(self, self.time);
+    }
+
+    /// Re-predicts the entity from time up to its horizon, eg after its manoeuvres have been edited, along with anything whose
+    /// trajectory depends on it (eg spacecraft near a celestial body)
+    pub fn invalidate_prediction(&mut self, entity: Entity, time: f64) {
+        invalidate_prediction(self, entity, time);
+    }
+
+    /// Samples any celestial body orbits that have been predicted since it was last called, and drops any that have been removed
+    pub fn update_ephemeris(&mut self) {
+        let mut ephemeris = std::mem::take(&mut self.ephemeris);
+        ephemeris.update(self);
+        self.ephemeris = ephemeris;
+    }
+
+    /// Predicts the trajectories of all celestial bodies, followed by all spacecraft, up to end_time rather than their horizons
+    /// Spacecraft need to be predicted second, since their prediction depends on the positions of celestial bodies
+    #[cfg(test)]
+    pub fn predict_until(&mut self, end_time: f64) {
+        use crate::systems::{util::is_spacecraft_with_trajectory, trajectory_prediction_system::{celestial_body_prediction::predict_celestial_bodies, spacecraft_prediction::predict_spacecraft}};
+        predict_celestial_bodies(self, end_time);
+        for entity in self.components.entity_allocator.get_entities() {
+            if is_spacecraft_with_trajectory(self, entity) {
+                predict_spacecraft(self, entity, self.time, end_time);
+            }
+        }
+    }
+
+    /// Advances time by delta_time and moves every entity along its trajectory accordingly
+    /// Predictions are only extended as far as the new time, so that no trajectory ends before it - extending them to their horizons
+    /// can take a while, so it's left to extend_predictions (eg on a background prediction)
+    pub fn update(&mut self, delta_time: f64) {
+        extend_predictions_to(self, self.time + delta_time);
+        self.time += delta_time;
+        trajectory_update_system(self, delta_time);
+    }
+
+    /// Sorted from highest to lowest mass, leaving out anything without a mass (eg manoeuvre nodes)
+    pub fn get_entities_sorted_by_mass(&self) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.components.entity_allocator.get_entities()
+            .into_iter()
+            .filter(|entity| self.components.mass_components.get(entity).is_some())
+            .collect();
+        entities.sort_by(|a, b| {
+            let mass_a = self.components.mass_components.get(a).unwrap().get_mass();
+            let mass_b = self.components.mass_components.get(b).unwrap().get_mass();
+            if mass_a > mass_b {
+                Ordering::Less
+            } else if mass_a < mass_b {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        });
+        entities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::{sun_earth_simulation, add_circular_spacecraft, EARTH_DISTANCE};
+
+    #[test]
+    fn test_predict_and_update_without_window() {
+        let (mut simulation, sun, earth) = sun_earth_simulation();
+        let spacecraft_distance = 8.0e6;
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, spacecraft_distance, 1.0e3, None);
+
+        simulation.predict();
+        for _ in 0..100 {
+            simulation.update(500.0);
+        }
+
+        assert!((simulation.time - 50000.0).abs() < 1.0e-6);
+        let sun_position = simulation.components.position_components.get(&sun).unwrap().get_absolute_position();
+        let earth_position = simulation.components.position_components.get(&earth).unwrap().get_absolute_position();
+        let spacecraft_position = simulation.components.position_components.get(&spacecraft).unwrap().get_absolute_position();
+        assert!(((earth_position - sun_position).magnitude() - EARTH_DISTANCE).abs() < 1.0e4);
+        assert!(((spacecraft_position - earth_position).magnitude() - spacecraft_distance).abs() < 10.0);
+    }
+}
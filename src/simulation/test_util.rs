@@ -0,0 +1,51 @@
+use eframe::epaint::Rgba;
+use nalgebra_glm::vec2;
+
+use crate::{storage::{entity_allocator::Entity, entity_builder::{add_root_object, add_child_celestial_object, add_child_object}}, components::{engine_component::EngineComponent, trajectory_component::segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT}};
+
+use super::Simulation;
+
+pub const SUN_MASS: f64 = 1.9885e30;
+pub const SUN_RADIUS: f64 = 6.957e8;
+pub const EARTH_MASS: f64 = 5.9722e24;
+pub const EARTH_RADIUS: f64 = 6.378e6;
+pub const EARTH_DISTANCE: f64 = 1.521e11;
+pub const MOON_MASS: f64 = 7.346e22;
+pub const MOON_RADIUS: f64 = 1.738e6;
+pub const MOON_DISTANCE: f64 = 3.844e8;
+
+/// Speed of a circular orbit of the given radius around the parent
+pub fn get_circular_speed(simulation: &Simulation, parent: Entity, radius: f64) -> f64 {
+    let mass = simulation.components.mass_components.get(&parent).unwrap().get_mass();
+    f64::sqrt(GRAVITATIONAL_CONSTANT * mass / radius)
+}
+
+/// Starts on the positive x axis and goes anticlockwise, like everything else added here
+pub fn add_circular_spacecraft(simulation: &mut Simulation, parent: Entity, radius: f64, mass: f64, engine: Option<EngineComponent>) -> Entity {
+    let speed = get_circular_speed(simulation, parent, radius);
+    add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), parent, vec2(radius, 0.0), vec2(0.0, speed), mass, engine)
+}
+
+/// Just the earth, sitting still at the origin
+pub fn earth_simulation() -> (Simulation, Entity) {
+    let mut simulation = Simulation::new();
+    let earth = add_root_object(&mut simulation.components, "planet".to_string(), "earth".to_string(), vec2(0.0, 0.0), vec2(0.0, 0.0), EARTH_MASS, EARTH_RADIUS, Rgba::WHITE);
+    (simulation, earth)
+}
+
+/// The earth at the origin, with the moon on a circular orbit
+pub fn earth_moon_simulation() -> (Simulation, Entity, Entity) {
+    let (mut simulation, earth) = earth_simulation();
+    let speed = get_circular_speed(&simulation, earth, MOON_DISTANCE);
+    let moon = add_child_celestial_object(&mut simulation.components, 0.0, "moon".to_string(), "moon".to_string(), earth, vec2(MOON_DISTANCE, 0.0), vec2(0.0, speed), MOON_MASS, MOON_RADIUS, Rgba::WHITE);
+    (simulation, earth, moon)
+}
+
+/// The sun at the origin, with the earth on a circular orbit
+pub fn sun_earth_simulation() -> (Simulation, Entity, Entity) {
+    let mut simulation = Simulation::new();
+    let sun = add_root_object(&mut simulation.components, "star".to_string(), "sun".to_string(), vec2(0.0, 0.0), vec2(0.0, 0.0), SUN_MASS, SUN_RADIUS, Rgba::WHITE);
+    let speed = get_circular_speed(&simulation, sun, EARTH_DISTANCE);
+    let earth = add_child_celestial_object(&mut simulation.components, 0.0, "planet".to_string(), "earth".to_string(), sun, vec2(EARTH_DISTANCE, 0.0), vec2(0.0, speed), EARTH_MASS, EARTH_RADIUS, Rgba::WHITE);
+    (simulation, sun, earth)
+}
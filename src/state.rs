@@ -1,23 +1,39 @@
-use std::{sync::{Arc, Mutex}, time::Instant, collections::HashMap, f64::consts::PI, cmp::Ordering};
+use std::{sync::{Arc, Mutex}, time::Instant, collections::HashMap};
 
-use eframe::{egui::{Context, Ui}, epaint::Rgba, Frame, CreationContext};
-use nalgebra_glm::vec2;
+use eframe::{egui::{Context, Ui}, Frame, CreationContext};
 
-use crate::{camera::Camera, storage::{entity_allocator::Entity, entity_builder::{add_root_object, add_child_celestial_object, add_child_object}}, systems::{camera_update_system::camera_update_system, time_step_update_system::{time_step_update_system, TimeStepDescription}, icon_click_system::icon_click_system, trajectory_update_system::trajectory_update_system, underlay_render_system::underlay_render_system, icon_precedence_system::icon_precedence_system, orbit_point_selection_system::{orbit_click_system, OrbitClickPoint}, orbit_point_toolbar_system::orbit_point_toolbar_system, mouse_over_any_element_system::was_mouse_over_any_element_last_frame_system, warp_update_system::{warp_update_system, WarpDescription}, delta_time_update_system::delta_time_update_system, trajectory_prediction_system::{celestial_body_prediction::predict_celestial_bodies, spacecraft_prediction::predict_all_spacecraft}, debug_system::debug_system, icon_position_update_system::icon_position_update_system}, components::Components, resources::Resources, rendering::{geometry_renderer::GeometryRenderer, texture_renderer::TextureRenderer}};
+use crate::{camera::Camera, storage::entity_allocator::Entity, systems::{camera_update_system::camera_update_system, time_step_update_system::{time_step_update_system, TimeStepDescription}, icon_click_system::icon_click_system, underlay_render_system::underlay_render_system, icon_precedence_system::icon_precedence_system, orbit_point_selection_system::{orbit_click_system, OrbitClickPoint}, orbit_point_toolbar_system::{orbit_point_toolbar_system, TransferPlans}, mouse_over_any_element_system::was_mouse_over_any_element_last_frame_system, warp_update_system::{warp_update_system, WarpDescription}, delta_time_update_system::delta_time_update_system, debug_system::debug_system, icon_position_update_system::icon_position_update_system, save_load_system::save_load_system, burn_toolbar_system::burn_toolbar_system, porkchop_system::porkchop_system, closest_approach_system::{closest_approach_system, ClosestApproaches}, target_hud_system::target_hud_system, orbit_marker_system::orbit_marker_system, trajectory_prediction_system::background::BackgroundPrediction, background_prediction_system::background_prediction_system, manoeuvre_node_system::{manoeuvre_node_system, handle::ManoeuvreHandle}, notification_system::{notification_system, Notification}}, resources::Resources, simulation::Simulation, planner::porkchop::Porkchop, rendering::{geometry_renderer::GeometryRenderer, texture_renderer::TextureRenderer}};
 
 pub struct State {
+    pub simulation: Simulation,
     pub resources: Resources,
-    pub components: Components,
     pub mouse_over_any_element_cache: bool,
     pub mouse_over_any_element: bool,
     pub time_step_description: TimeStepDescription,
+    /// Overrides the time step while a burn is underway or coming up
+    pub time_step_limit: Option<f64>,
     pub debug_mode: bool,
-    pub time: f64,
     pub delta_time: f64,
     pub last_frame: Instant,
     pub selected_entity: Entity,
+    /// Whatever the selected entity is being compared against, eg for closest approaches and relative velocity
+    pub target: Option<Entity>,
     pub orbit_click_point: Option<OrbitClickPoint>,
+    pub selected_manoeuvre_node: Option<Entity>,
+    pub dragged_manoeuvre_handle: Option<ManoeuvreHandle>,
+    /// The entity and time to re-predict from after an edit that's made by dragging (eg a handle), if that hasn't been requested yet
+    pub unpredicted_edit: Option<(Entity, f64)>,
+    pub last_edit_prediction: Instant,
+    pub transfer_orbit_radius: f64,
+    pub transfer_plans: Option<TransferPlans>,
+    pub porkchop: Option<Porkchop>,
+    pub closest_approaches: Option<ClosestApproaches>,
+    /// Trajectories being re-predicted on a worker thread - time carries on along the old ones until the new ones are swapped in
+    pub background_prediction: BackgroundPrediction,
     pub current_warp: Option<WarpDescription>,
+    pub notification: Option<Notification>,
+    /// How long before a burn to stop when warping to it
+    pub burn_lead_time: f64,
     pub camera: Arc<Mutex<Camera>>,
     pub orbit_renderer: Arc<Mutex<GeometryRenderer>>,
     pub object_renderer: Arc<Mutex<GeometryRenderer>>,
@@ -25,41 +41,43 @@ pub struct State {
 }
 
 impl State {
-    pub fn new(creation_context: &CreationContext) -> Self {
+    pub fn new(creation_context: &CreationContext, simulation: Simulation, selected_entity: Entity) -> Self {
         egui_extras::install_image_loaders(&creation_context.egui_ctx);
         let mut resources = Resources::new();
-        let mut components = Components::new();
-        let sun = Self::init_root_object(&mut components);
         let gl = creation_context.gl.as_ref().unwrap().clone();
         let orbit_renderer = Arc::new(Mutex::new(GeometryRenderer::new(gl.clone())));
         let object_renderer = Arc::new(Mutex::new(GeometryRenderer::new(gl.clone())));
         let icon_renderers = Self::init_texture_renderers(&gl, &mut resources);
-        let mut state = Self {
+        Self {
+            simulation,
             resources,
-            components,
             mouse_over_any_element_cache: false,
             mouse_over_any_element: false,
             time_step_description: TimeStepDescription::Level(1),
+            time_step_limit: None,
             debug_mode: false,
-            time: 0.0,
             delta_time: 0.0,
             last_frame: Instant::now(),
-            selected_entity: sun,
+            selected_entity,
+            target: None,
             orbit_click_point: None,
+            selected_manoeuvre_node: None,
+            dragged_manoeuvre_handle: None,
+            unpredicted_edit: None,
+            last_edit_prediction: Instant::now(),
+            transfer_orbit_radius: 4.2164e7,
+            transfer_plans: None,
+            porkchop: None,
+            closest_approaches: None,
+            background_prediction: BackgroundPrediction::default(),
             current_warp: None,
+            notification: None,
+            burn_lead_time: 30.0,
             camera: Arc::new(Mutex::new(Camera::new())),
             orbit_renderer,
             object_renderer,
             texture_renderers: icon_renderers,
-        };
-        state.init_objects(sun);
-        predict_celestial_bodies(&mut state, 10000000.0);
-        predict_all_spacecraft(&mut state, 10000000.0);
-        state
-    }
-
-    fn init_root_object(components: &mut Components) -> Entity {
-        add_root_object(components, "star".to_string(), "sun".to_string(), vec2(0.0, 0.0), vec2(0.0, 0.0), 1.9885e30, 6.957e8, Rgba::from_rgba_unmultiplied(1.0, 1.0, 0.3, 1.0))
+        }
     }
 
     fn init_texture_renderers(gl: &Arc<glow::Context>, resources: &mut Resources) -> Arc<Mutex<HashMap<String, TextureRenderer>>> {
@@ -70,20 +88,14 @@ impl State {
         Arc::new(Mutex::new(texture_renderers))
     }
 
-    fn init_objects(&mut self, sun: Entity) {
-        let earth = add_child_celestial_object(&mut self.components, 0.0, "planet".to_string(), "earth".to_string(), sun, vec2(1.521e11, 0.0), vec2(0.0, -2.729e4), 5.9722e24, 6.378e6, Rgba::from_rgba_unmultiplied(0.1, 0.4, 1.0, 1.0));
-        add_child_celestial_object(&mut self.components, 0.0, "moon".to_string(), "moon".to_string(), earth, 
-            vec2(0.4055e9 * f64::cos(2.0), 0.4055e9 * f64::sin(2.0)), vec2(0.970e3 * f64::cos(2.0 + PI / 2.0), 0.970e3 * f64::sin(2.0 + PI / 2.0)), 
-            //vec2(0.4055e9 * f64::cos(30.0), 0.4055e9 * f64::sin(30.0)), vec2(-1.303e3 * f64::cos(30.0 + PI / 2.0), -1.303e3 * f64::sin(30.0 + PI / 2.0)), 
-            7.346e22, 1.738e6, Rgba::from_rgba_unmultiplied(0.3, 0.3, 0.3, 1.0));
-        let spacecraft = add_child_object(&mut self.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), earth, vec2(0.0, 8.0e6), vec2(-0.987e4, 0.0), 1.0e3);
-        self.selected_entity = spacecraft;
-    }
-
     pub fn get_time_step(&self) -> f64 {
-        match self.time_step_description {
+        let time_step = match self.time_step_description {
             TimeStepDescription::Level(level) => 5.0_f64.powi(level-1),
             TimeStepDescription::Raw(raw) => raw,
+        };
+        match self.time_step_limit {
+            Some(limit) => f64::min(time_step, limit),
+            None => time_step,
         }
     }
 
@@ -92,38 +104,30 @@ impl State {
             self.mouse_over_any_element_cache = true;
         }
     }
-
-    /// Sorted from highest to lowest mass
-    pub fn get_entities_sorted_by_mass(&self) -> Vec<Entity> {
-        let mut entities: Vec<Entity> = self.components.entity_allocator.get_entities().into_iter().collect();
-        entities.sort_by(|a, b| {
-            let mass_a = self.components.mass_components.get(a).unwrap().get_mass();
-            let mass_b = self.components.mass_components.get(b).unwrap().get_mass();
-            if mass_a > mass_b {
-                Ordering::Less
-            } else if mass_a < mass_b {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
-            }
-        });
-        entities
-    }
 }
 
 impl eframe::App for State {
     fn update(&mut self, context: &Context, _frame: &mut Frame) {
         delta_time_update_system(self);
-        warp_update_system(self);
+        warp_update_system(self, context);
         time_step_update_system(self, context);
-        trajectory_update_system(self);
+        save_load_system(self, context);
+        background_prediction_system(self, context);
+        notification_system(self, context);
+        self.simulation.update(self.delta_time * self.get_time_step());
         camera_update_system(self, context);
+        manoeuvre_node_system(self, context);
         orbit_click_system(self, context);
         debug_system(self, context);
         icon_position_update_system(self);
         icon_precedence_system(self);
         icon_click_system(self, context);
         orbit_point_toolbar_system(self, context);
+        burn_toolbar_system(self, context);
+        porkchop_system(self, context);
+        closest_approach_system(self, context);
+        target_hud_system(self, context);
+        orbit_marker_system(self, context);
         underlay_render_system(self, context);
         was_mouse_over_any_element_last_frame_system(self);
         context.request_repaint(); // Update as soon as possible, otherwise it'll only update when some input changes
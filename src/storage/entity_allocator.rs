@@ -1,8 +1,10 @@
 use std::collections::HashSet;
 
+use serde::{Deserialize, Serialize};
+
 use crate::components::Components;
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub struct Entity {
     index: usize,
     generation: usize,
@@ -22,11 +24,13 @@ impl Entity {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AllocatorEntry {
     is_allocated: bool,
     generation: usize,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct EntityAllocator {
     entities: HashSet<Entity>,
     entries: Vec<AllocatorEntry>,
@@ -39,14 +43,15 @@ impl EntityAllocator {
     }
 
     pub fn allocate(&mut self) -> Entity {
-        if let Some(index) = self.free.first() {
-            let index = *index;
+        if let Some(index) = self.free.pop() {
             if self.entries[index].is_allocated {
                 panic!("Attempt to allocate to an index that was already allocated");
             }
             self.entries[index].is_allocated = true;
             let generation = self.entries[index].generation;
-            return Entity { index, generation };
+            let entity = Entity { index, generation };
+            self.entities.insert(entity);
+            return entity;
         }
         let index = self.entries.len();
         let is_allocated = true;
@@ -57,8 +62,9 @@ impl EntityAllocator {
         entity
     }
 
+    /// Components of the entity are left in their storages, but can't be accessed any more since the generation no longer matches
     pub fn deallocate(&mut self, entity: Entity) {
-        if self.entries[entity.index].is_allocated {
+        if !self.entries[entity.index].is_allocated {
             panic!("Attempt to deallocate an entity that was already deallocated");
         }
         self.entries[entity.index].is_allocated = false;
@@ -71,3 +77,24 @@ impl EntityAllocator {
         self.entities.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse_after_deallocate() {
+        let mut allocator = EntityAllocator::new();
+        let a = allocator.allocate();
+        let b = allocator.allocate();
+        allocator.deallocate(a);
+        assert_eq!(allocator.get_entities(), HashSet::from([b]));
+
+        // The freed index is reused, but with a new generation so the old entity can't be mistaken for the new one
+        let c = allocator.allocate();
+        assert_eq!(c.get_index(), a.get_index());
+        assert_ne!(c, a);
+        assert_eq!(allocator.get_entities(), HashSet::from([b, c]));
+        assert_eq!(allocator.allocate().get_index(), 2);
+    }
+}
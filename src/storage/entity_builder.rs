@@ -1,11 +1,13 @@
 use eframe::epaint::Rgba;
 use nalgebra_glm::DVec2;
 
-use crate::{components::{celestial_body_component::CelestialBodyComponent, mass_component::MassComponent, parent_component::ParentComponent, position_component::PositionComponent, trajectory_component::TrajectoryComponent, velocity_component::VelocityComponent, name_component::NameComponent, Components, icon_component::{IconComponent, IconType}}, storage::entity_allocator::Entity};
+use crate::{components::{celestial_body_component::CelestialBodyComponent, engine_component::EngineComponent, mass_component::MassComponent, parent_component::ParentComponent, position_component::PositionComponent, trajectory_component::{TrajectoryComponent, manoeuvre::ManoeuvreId, segment::orbit::{Orbit, orbital_elements::OrbitalElements}}, velocity_component::VelocityComponent, name_component::NameComponent, Components, icon_component::{IconComponent, IconType}, manoeuvre_node_component::ManoeuvreNodeComponent}, storage::entity_allocator::Entity};
 
 struct EntityBuilder {
     celestial_body_component: Option<CelestialBodyComponent>,
+    engine_component: Option<EngineComponent>,
     icon_component: Option<IconComponent>,
+    manoeuvre_node_component: Option<ManoeuvreNodeComponent>,
     mass_component: Option<MassComponent>,
     name_component: Option<NameComponent>,
     parent_component: Option<ParentComponent>,
@@ -18,7 +20,9 @@ impl EntityBuilder {
     pub fn new() -> Self {
         Self { 
             celestial_body_component: None, 
+            engine_component: None,
             icon_component: None,
+            manoeuvre_node_component: None,
             mass_component: None, 
             name_component: None,
             parent_component: None, 
@@ -33,11 +37,21 @@ impl EntityBuilder {
         self
     }
 
+    pub fn with_engine_component(mut self, component: EngineComponent) -> Self {
+        self.engine_component = Some(component);
+        self
+    }
+
     pub fn with_icon_component(mut self, component: IconComponent) -> Self {
         self.icon_component = Some(component);
         self
     }
 
+    pub fn with_manoeuvre_node_component(mut self, component: ManoeuvreNodeComponent) -> Self {
+        self.manoeuvre_node_component = Some(component);
+        self
+    }
+
     pub fn with_mass_component(mut self, component: MassComponent) -> Self {
         self.mass_component = Some(component);
         self
@@ -71,7 +85,9 @@ impl EntityBuilder {
     pub fn build(self, components: &mut Components) -> Entity {
         let EntityBuilder {
             celestial_body_component,
+            engine_component,
             icon_component,
+            manoeuvre_node_component,
             mass_component,
             name_component,
             parent_component,
@@ -82,7 +98,9 @@ impl EntityBuilder {
 
         let entity = components.entity_allocator.allocate();
         components.celestial_body_components.set(entity, celestial_body_component);
+        components.engine_components.set(entity, engine_component);
         components.icon_components.set(entity, icon_component);
+        components.manoeuvre_node_components.set(entity, manoeuvre_node_component);
         components.mass_components.set(entity, mass_component);
         components.name_components.set(entity, name_component);
         components.parent_components.set(entity, parent_component);
@@ -103,20 +121,17 @@ fn base_object_builder(type_name: String, name: String, absolute_position: DVec2
         .with_mass_component(MassComponent::new(mass))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn add_root_object(components: &mut Components, type_name: String, name: String, position: DVec2, velocity: DVec2, mass: f64, radius: f64, color: Rgba) -> Entity {
     base_object_builder(type_name, name, position, velocity, mass)
         .with_celestial_body_component(CelestialBodyComponent::new(radius, color))
         .build(components)
 }
 
-pub fn add_child_celestial_object(components: &mut Components, time: f64, type_name: String, name: String, parent: Entity, position: DVec2, velocity: DVec2, mass: f64, radius: f64, color: Rgba) -> Entity {
-    let absolute_position = components.position_components.get(&parent).unwrap().get_absolute_position() + position;
-    let entity = base_object_builder(type_name, name, absolute_position, velocity, mass)
+fn add_child(components: &mut Components, builder: EntityBuilder, parent: Entity) -> Entity {
+    let entity = builder
         .with_parent_component(ParentComponent::new(parent))
-        .with_celestial_body_component(CelestialBodyComponent::new(radius, color))
-        .with_trajectory_component(TrajectoryComponent::new(components, parent, position, velocity, time))
         .build(components);
-    components.parent_components.set(entity, Some(ParentComponent::new(parent)));
     components.celestial_body_components
         .get_mut(&parent)
         .expect("Object's parent must be a celestial body")
@@ -124,16 +139,56 @@ pub fn add_child_celestial_object(components: &mut Components, time: f64, type_n
     entity
 }
 
-pub fn add_child_object(components: &mut Components, time: f64, type_name: String, name: String, parent: Entity, position: DVec2, velocity: DVec2, mass: f64) -> Entity {
-    let absolute_position = components.position_components.get(&parent).unwrap().get_absolute_position() + position;
-    let entity = base_object_builder(type_name, name, absolute_position, velocity, mass)
-        .with_parent_component(ParentComponent::new(parent))
-        .with_trajectory_component(TrajectoryComponent::new(components, parent, position, velocity, time))
-        .build(components);
-    components.parent_components.set(entity, Some(ParentComponent::new(parent)));
-    components.celestial_body_components
-        .get_mut(&parent)
-        .expect("Object's parent must be a celestial body")
-        .add_child(entity);
-    entity
+fn child_object_builder(components: &Components, type_name: String, name: String, parent: Entity, orbit: Orbit, velocity: DVec2, mass: f64) -> EntityBuilder {
+    let absolute_position = components.position_components.get(&parent).unwrap().get_absolute_position() + orbit.get_start_position();
+    base_object_builder(type_name, name, absolute_position, velocity, mass)
+        .with_trajectory_component(TrajectoryComponent::new(orbit))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_child_celestial_object(components: &mut Components, time: f64, type_name: String, name: String, parent: Entity, position: DVec2, velocity: DVec2, mass: f64, radius: f64, color: Rgba) -> Entity {
+    let orbit = Orbit::new(components, parent, position, velocity, time);
+    let builder = child_object_builder(components, type_name, name, parent, orbit, velocity, mass)
+        .with_celestial_body_component(CelestialBodyComponent::new(radius, color));
+    add_child(components, builder, parent)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_child_object(components: &mut Components, time: f64, type_name: String, name: String, parent: Entity, position: DVec2, velocity: DVec2, mass: f64, engine: Option<EngineComponent>) -> Entity {
+    let orbit = Orbit::new(components, parent, position, velocity, time);
+    let mut builder = child_object_builder(components, type_name, name, parent, orbit, velocity, mass);
+    if let Some(engine) = engine {
+        builder = builder.with_engine_component(engine);
+    }
+    add_child(components, builder, parent)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_child_celestial_object_from_elements(components: &mut Components, time: f64, type_name: String, name: String, parent: Entity, elements: OrbitalElements, mass: f64, radius: f64, color: Rgba) -> Entity {
+    let orbit = Orbit::from_elements(components, parent, elements, time);
+    let velocity = orbit.get_start_velocity();
+    let builder = child_object_builder(components, type_name, name, parent, orbit, velocity, mass)
+        .with_celestial_body_component(CelestialBodyComponent::new(radius, color));
+    add_child(components, builder, parent)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_child_object_from_elements(components: &mut Components, time: f64, type_name: String, name: String, parent: Entity, elements: OrbitalElements, mass: f64, engine: Option<EngineComponent>) -> Entity {
+    let orbit = Orbit::from_elements(components, parent, elements, time);
+    let velocity = orbit.get_start_velocity();
+    let mut builder = child_object_builder(components, type_name, name, parent, orbit, velocity, mass);
+    if let Some(engine) = engine {
+        builder = builder.with_engine_component(engine);
+    }
+    add_child(components, builder, parent)
+}
+
+/// Manoeuvre nodes aren't part of the celestial body tree, so they don't get a parent or mass
+pub fn add_manoeuvre_node(components: &mut Components, spacecraft: Entity, manoeuvre: ManoeuvreId, absolute_position: DVec2) -> Entity {
+    let icon_size = 0.01;
+    EntityBuilder::new()
+        .with_icon_component(IconComponent::new(absolute_position, IconType::BurnIcon, "burn".to_string(), icon_size))
+        .with_position_component(PositionComponent::new(absolute_position))
+        .with_manoeuvre_node_component(ManoeuvreNodeComponent::new(spacecraft, manoeuvre))
+        .build(components)
 }
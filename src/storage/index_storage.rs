@@ -1,10 +1,14 @@
+use serde::{Deserialize, Serialize};
+
 use super::entity_allocator::Entity;
 
+#[derive(Serialize, Deserialize)]
 struct StorageEntry<T> {
     value: T,
     generation: usize,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct ComponentStorage<T> {
     entries: Vec<Option<StorageEntry<T>>>,
 }
@@ -27,6 +31,7 @@ impl<T> ComponentStorage<T> {
         }
     }
 
+    #[allow(dead_code)]
     pub fn remove(&mut self, id: Entity) {
         let entry = self.entries
             .get_mut(id.get_index())
@@ -1,15 +1,24 @@
+pub mod background_prediction_system;
+pub mod burn_toolbar_system;
 pub mod camera_update_system;
+pub mod closest_approach_system;
 pub mod debug_system;
 pub mod delta_time_update_system;
 pub mod icon_position_update_system;
 pub mod icon_precedence_system;
 pub mod mouse_over_any_element_system;
+pub mod notification_system;
 pub mod icon_click_system;
+pub mod manoeuvre_node_system;
+pub mod orbit_marker_system;
 pub mod orbit_point_selection_system;
 pub mod orbit_point_toolbar_system;
+pub mod porkchop_system;
+pub mod save_load_system;
+pub mod target_hud_system;
 pub mod time_step_update_system;
 pub mod trajectory_prediction_system;
 pub mod trajectory_update_system;
 pub mod underlay_render_system;
-mod util;
+pub mod util;
 pub mod warp_update_system;
\ No newline at end of file
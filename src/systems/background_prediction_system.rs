@@ -0,0 +1,67 @@
+use std::time::Instant;
+
+use eframe::{egui::{Context, Window, Id, Label}, emath::Align2, epaint};
+
+use crate::{state::State, storage::entity_allocator::Entity};
+
+use super::{trajectory_prediction_system::{background::PredictionStatus, horizon::needs_extending}, notification_system::notify};
+
+/// Re-predicts the entity from the time on a worker thread, along with anything that was already being predicted
+/// The current trajectories are kept (and drawn) until the new ones are ready
+pub fn request_prediction(state: &mut State, entity: Entity, time: f64) {
+    state.background_prediction.invalidate(entity, time);
+}
+
+/// Re-predicting every frame while something is being dragged (eg a handle or a dv value) would keep superseding the prediction
+/// before it could finish, so edits like that are only sent off this often (in real seconds)
+const EDIT_PREDICTION_INTERVAL: f64 = 0.2;
+
+/// Like request_prediction, but for edits that change every frame while they're being made - the prediction is requested once
+/// EDIT_PREDICTION_INTERVAL has passed since the last one, or straight away by flush_edit_prediction once the edit is finished
+pub fn request_edit_prediction(state: &mut State, entity: Entity, time: f64) {
+    state.unpredicted_edit = match state.unpredicted_edit {
+        Some((other_entity, other_time)) if other_entity == entity => Some((entity, f64::min(time, other_time))),
+        Some((other_entity, other_time)) => {
+            request_prediction(state, other_entity, other_time);
+            Some((entity, time))
+        }
+        None => Some((entity, time)),
+    };
+}
+
+/// Called every frame, and with is_finished once the edit has been let go of so that it doesn't have to wait
+pub fn flush_edit_prediction(state: &mut State, is_finished: bool) {
+    if !is_finished && state.last_edit_prediction.elapsed().as_secs_f64() < EDIT_PREDICTION_INTERVAL {
+        return;
+    }
+    if let Some((entity, time)) = state.unpredicted_edit.take() {
+        request_prediction(state, entity, time);
+        state.last_edit_prediction = Instant::now();
+    }
+}
+
+/// Predictions are extended to their horizons on the worker thread too, so the simulation only ever has to predict as far as the
+/// next frame
+pub fn background_prediction_system(state: &mut State, context: &Context) {
+    flush_edit_prediction(state, false);
+    if needs_extending(&state.simulation, state.simulation.time) {
+        state.background_prediction.extend();
+    }
+    match state.background_prediction.poll(&mut state.simulation) {
+        PredictionStatus::Idle => return,
+        PredictionStatus::Failed => {
+            notify(state, "Background prediction failed, so it was done on the main thread instead".to_string());
+            return;
+        }
+        PredictionStatus::Running => (),
+    }
+    Window::new("Predicting")
+        .id(Id::new("background_prediction"))
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, epaint::vec2(0.0, 0.0))
+        .show(context, |ui| {
+            ui.add(Label::new("Predicting…"));
+            state.register_ui(ui);
+        });
+}
@@ -0,0 +1,97 @@
+use eframe::{egui::{Context, Window, Ui, Layout, Label, Button, DragValue, Id}, emath::{Align2, Align}, epaint};
+
+use crate::{state::State, storage::entity_allocator::Entity};
+
+use super::{orbit_point_toolbar_system::apply_toolbar_style, util::format_time, manoeuvre_node_system::nodes::{get_node_manoeuvre, get_node_burn, get_node_delay, delete_node_manoeuvre, move_node_manoeuvre, set_node_manoeuvre_dv}, background_prediction_system::{request_prediction, request_edit_prediction, flush_edit_prediction}};
+
+const SMALL_MOVE_STEP: f64 = 60.0;
+const LARGE_MOVE_STEP: f64 = 600.0;
+
+fn delete_burn(state: &mut State, node: Entity) {
+    if let Some((spacecraft, time)) = delete_node_manoeuvre(&mut state.simulation, node) {
+        request_prediction(state, spacecraft, time);
+    }
+    state.selected_manoeuvre_node = None;
+    state.dragged_manoeuvre_handle = None;
+}
+
+fn move_burn(state: &mut State, node: Entity, time_change: f64) {
+    let Some(manoeuvre) = get_node_manoeuvre(&state.simulation, node) else {
+        return;
+    };
+    if let Some((spacecraft, time)) = move_node_manoeuvre(&mut state.simulation, node, manoeuvre.time + time_change) {
+        request_prediction(state, spacecraft, time);
+    }
+}
+
+fn draw_move_buttons(state: &mut State, ui: &mut Ui, node: Entity) {
+    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+        for (text, time_change) in [("<<", -LARGE_MOVE_STEP), ("<", -SMALL_MOVE_STEP), (">", SMALL_MOVE_STEP), (">>", LARGE_MOVE_STEP)] {
+            if ui.add(Button::new(text)).on_hover_text(format!("Move by {}", format_time(time_change.abs()))).clicked() {
+                move_burn(state, node, time_change);
+            }
+        }
+    });
+}
+
+fn draw_dv_editor(state: &mut State, ui: &mut Ui, node: Entity) {
+    let Some(manoeuvre) = get_node_manoeuvre(&state.simulation, node) else {
+        return;
+    };
+    let mut tangent_dv = manoeuvre.tangent_dv;
+    let mut normal_dv = manoeuvre.normal_dv;
+    let tangent_response = ui.add(DragValue::new(&mut tangent_dv).speed(1.0).prefix("Tangent: ").suffix(" m/s"));
+    let normal_response = ui.add(DragValue::new(&mut normal_dv).speed(1.0).prefix("Normal: ").suffix(" m/s"));
+    if tangent_dv != manoeuvre.tangent_dv || normal_dv != manoeuvre.normal_dv {
+        if let Some((spacecraft, time)) = set_node_manoeuvre_dv(&mut state.simulation, node, tangent_dv, normal_dv) {
+            request_edit_prediction(state, spacecraft, time);
+        }
+    }
+    // Dragging the value changes it every frame, so the prediction is only sent off straight away once it's been let go of
+    if [tangent_response, normal_response].iter().any(|response| response.drag_released() || response.lost_focus()) {
+        flush_edit_prediction(state, true);
+    }
+}
+
+fn draw(state: &mut State, ui: &mut Ui, node: Entity) {
+    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+        if ui.add(Button::new("Delete")).clicked() {
+            delete_burn(state, node);
+        }
+    });
+    if state.selected_manoeuvre_node.is_none() {
+        state.register_ui(ui);
+        return;
+    }
+
+    draw_move_buttons(state, ui, node);
+    draw_dv_editor(state, ui, node);
+
+    // The burn is what the countdown is to, which might not be at the manoeuvre's time
+    if let Some(burn) = get_node_burn(&state.simulation, node) {
+        let remaining_time = burn.borrow().get_start_time() - state.simulation.time;
+        ui.add(Label::new("T-".to_string() + format_time(remaining_time).as_str()));
+    }
+    let delay = get_node_delay(&state.simulation, node);
+    if delay > 0.0 {
+        ui.add(Label::new(format!("Delayed {} by an earlier burn", format_time(delay))));
+    }
+
+    state.register_ui(ui);
+}
+
+/// Shown while a burn is selected, for changes that can't be made with the handles
+/// Every change truncates the trajectory at the burn and re-predicts from there
+pub fn burn_toolbar_system(state: &mut State, context: &Context) {
+    let Some(node) = state.selected_manoeuvre_node else {
+        return;
+    };
+
+    apply_toolbar_style(context);
+    let window = Window::new("")
+        .id(Id::new("burn_toolbar"))
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::LEFT_TOP, epaint::vec2(0.0, 0.0));
+    window.show(context, |ui| draw(state, ui, node));
+}
@@ -0,0 +1,227 @@
+use eframe::{egui::{Context, Area, Id, Label, RichText}, epaint::{self, Color32}};
+
+use crate::{state::State, simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::{manoeuvre::{Manoeuvre, ManoeuvreId}, segment::Segment}};
+
+use super::util::{get_absolute_position_at_time, get_segment_at_time, get_rendered_position_at_time, is_spacecraft_with_trajectory, format_time, format_distance};
+
+/// How many times per orbit (of whichever of the two is orbiting faster) the separation is checked before narrowing down
+const SAMPLES_PER_ORBIT: f64 = 64.0;
+/// Stops the search crawling along through very short segments
+const MIN_SAMPLE_STEP: f64 = 1.0;
+const REFINEMENT_ITERATIONS: usize = 60;
+/// A spacecraft orbiting underneath its target comes close to it once per orbit, so only the closest few are shown
+const MAX_MARKERS: usize = 2;
+const LABEL_OFFSET: f32 = 12.0;
+pub const MARKER_COLOR: Color32 = Color32::from_rgb(255, 150, 40);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClosestApproach {
+    pub time: f64,
+    pub distance: f64,
+}
+
+/// What the approaches were found from, so that they're only searched for again once the trajectories change
+/// The versions change whenever either trajectory is re-predicted (including when a celestial body target moves), and the
+/// manoeuvres as soon as they're edited, before the re-prediction is ready
+#[derive(PartialEq)]
+struct SearchKey {
+    spacecraft: Entity,
+    target: Entity,
+    spacecraft_manoeuvres: Vec<(ManoeuvreId, Manoeuvre)>,
+    target_manoeuvres: Vec<(ManoeuvreId, Manoeuvre)>,
+    spacecraft_version: u64,
+    target_version: Option<u64>,
+    prediction_end_time: f64,
+}
+
+/// Every approach found in the predicted trajectories, including ones that have since gone past
+pub struct ClosestApproaches {
+    key: SearchKey,
+    approaches: Vec<ClosestApproach>,
+}
+
+fn get_sample_step(simulation: &Simulation, entity: &Entity, time: f64) -> f64 {
+    if simulation.components.trajectory_components.get(entity).is_none() {
+        return f64::MAX;
+    }
+    let duration = match get_segment_at_time(simulation, entity, time) {
+        Segment::Orbit(orbit) => {
+            let orbit = orbit.borrow();
+            orbit.get_period().unwrap_or(orbit.get_end_time() - orbit.get_start_time())
+        }
+        Segment::Burn(burn) => burn.borrow().get_duration(),
+    };
+    f64::max(duration / SAMPLES_PER_ORBIT, MIN_SAMPLE_STEP)
+}
+
+/// Golden section search for the minimum between start_time and end_time
+fn refine_minimum(start_time: f64, end_time: f64, get_distance: impl Fn(f64) -> f64) -> ClosestApproach {
+    let ratio = (f64::sqrt(5.0) - 1.0) / 2.0;
+    let (mut low, mut high) = (start_time, end_time);
+    for _ in 0..REFINEMENT_ITERATIONS {
+        let left = high - ratio * (high - low);
+        let right = low + ratio * (high - low);
+        if get_distance(left) < get_distance(right) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+    let time = (low + high) / 2.0;
+    ClosestApproach { time, distance: get_distance(time) }
+}
+
+fn get_prediction_end_time(simulation: &Simulation, entity: &Entity) -> f64 {
+    match simulation.components.trajectory_components.get(entity) {
+        Some(trajectory_component) => trajectory_component.get_final_segment().get_end_time(),
+        None => f64::MAX,
+    }
+}
+
+/// Every local minimum of the distance between the entity and the target from start_time until either of their predictions end,
+/// in time order - minima at the very start or end of the range are left out, since they're just where the search was cut off
+pub fn find_closest_approaches(simulation: &Simulation, entity: Entity, target: Entity, start_time: f64) -> Vec<ClosestApproach> {
+    let end_time = f64::min(get_prediction_end_time(simulation, &entity), get_prediction_end_time(simulation, &target));
+    let get_distance = |time: f64| (get_absolute_position_at_time(simulation, &entity, time) - get_absolute_position_at_time(simulation, &target, time)).magnitude();
+    let get_step = |time: f64| f64::min(get_sample_step(simulation, &entity, time), get_sample_step(simulation, &target, time));
+
+    let mut approaches = vec![];
+    if end_time <= start_time {
+        return approaches;
+    }
+    let mut previous = (start_time, get_distance(start_time));
+    let mut current_time = f64::min(start_time + get_step(start_time), end_time);
+    let mut current = (current_time, get_distance(current_time));
+    while current_time < end_time {
+        let next_time = f64::min(current_time + get_step(current_time), end_time);
+        let next = (next_time, get_distance(next_time));
+        if current.1 <= previous.1 && current.1 < next.1 {
+            approaches.push(refine_minimum(previous.0, next.0, get_distance));
+        }
+        previous = current;
+        current = next;
+        current_time = next_time;
+    }
+    approaches
+}
+
+/// The closest few approaches that haven't happened yet, in time order
+pub fn get_closest_approach_markers(state: &State) -> Vec<ClosestApproach> {
+    let Some(closest_approaches) = &state.closest_approaches else {
+        return vec![];
+    };
+    let mut markers: Vec<ClosestApproach> = closest_approaches.approaches.iter()
+        .filter(|approach| approach.time > state.simulation.time)
+        .copied()
+        .collect();
+    markers.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    markers.truncate(MAX_MARKERS);
+    markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+    markers
+}
+
+fn get_search_key(state: &State) -> Option<SearchKey> {
+    let spacecraft = state.selected_entity;
+    let target = state.target?;
+    if !is_spacecraft_with_trajectory(&state.simulation, spacecraft) || target == spacecraft {
+        return None;
+    }
+    let trajectory_component = state.simulation.components.trajectory_components.get(&spacecraft).unwrap();
+    let target_trajectory_component = state.simulation.components.trajectory_components.get(&target);
+    let spacecraft_manoeuvres = trajectory_component.get_manoeuvres().clone();
+    let target_manoeuvres = target_trajectory_component.map(|component| component.get_manoeuvres().clone()).unwrap_or_default();
+    let spacecraft_version = trajectory_component.get_version();
+    let target_version = target_trajectory_component.map(|component| component.get_version());
+    let prediction_end_time = f64::min(get_prediction_end_time(&state.simulation, &spacecraft), get_prediction_end_time(&state.simulation, &target));
+    Some(SearchKey { spacecraft, target, spacecraft_manoeuvres, target_manoeuvres, spacecraft_version, target_version, prediction_end_time })
+}
+
+fn update_closest_approaches(state: &mut State) {
+    let Some(key) = get_search_key(state) else {
+        state.closest_approaches = None;
+        return;
+    };
+    if state.closest_approaches.as_ref().is_some_and(|closest_approaches| closest_approaches.key == key) {
+        return;
+    }
+    let approaches = find_closest_approaches(&state.simulation, key.spacecraft, key.target, state.simulation.time);
+    state.closest_approaches = Some(ClosestApproaches { key, approaches });
+}
+
+fn draw_labels(state: &State, context: &Context) {
+    let spacecraft = state.selected_entity;
+    let screen_rect = context.screen_rect();
+    for (i, approach) in get_closest_approach_markers(state).iter().enumerate() {
+        let position = get_rendered_position_at_time(&state.simulation, &spacecraft, approach.time);
+        let screen_position = state.camera.lock().unwrap().world_space_to_window_space(position, screen_rect) + epaint::vec2(LABEL_OFFSET, LABEL_OFFSET);
+        let text = format!("Closest approach\n{}\nT-{}", format_distance(approach.distance), format_time(approach.time - state.simulation.time));
+        Area::new(Id::new("closest_approach").with(i))
+            .fixed_pos(screen_position)
+            .interactable(false)
+            .show(context, |ui| ui.add(Label::new(RichText::new(text).color(MARKER_COLOR).small())));
+    }
+}
+
+/// Searches for closest approaches between the selected spacecraft and the target whenever either's
+/// trajectory changes, and labels the closest ones
+pub fn closest_approach_system(state: &mut State, context: &Context) {
+    update_closest_approaches(state);
+    draw_labels(state, context);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, add_circular_spacecraft, get_circular_speed, EARTH_MASS}, storage::entity_builder::add_child_object, components::trajectory_component::segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT};
+
+    use super::*;
+
+    /// Two spacecraft on circular orbits of different radii are closest whenever the inner one laps the outer one
+    #[test]
+    fn test_circular_orbits() {
+        let (mut simulation, earth) = earth_simulation();
+        let inner_radius = 7.0e6;
+        let outer_radius = 9.0e6;
+        let outer_speed = get_circular_speed(&simulation, earth, outer_radius);
+        let inner = add_circular_spacecraft(&mut simulation, earth, inner_radius, 1.0e3, None);
+        let outer = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "outer".to_string(), earth, vec2(0.0, outer_radius), vec2(-outer_speed, 0.0), 1.0e3, None);
+        simulation.predict_until(200000.0);
+
+        let approaches = find_closest_approaches(&simulation, inner, outer, 0.0);
+        let inner_period = 2.0 * PI * f64::sqrt(inner_radius.powi(3) / (GRAVITATIONAL_CONSTANT * EARTH_MASS));
+        let outer_period = 2.0 * PI * f64::sqrt(outer_radius.powi(3) / (GRAVITATIONAL_CONSTANT * EARTH_MASS));
+        let synodic_period = 1.0 / (1.0 / inner_period - 1.0 / outer_period);
+        // The outer spacecraft starts a quarter of an orbit ahead
+        let first_time = 0.25 * synodic_period;
+        assert_eq!(approaches.len(), ((200000.0 - first_time) / synodic_period).ceil() as usize);
+        for (i, approach) in approaches.iter().enumerate() {
+            assert!((approach.distance - (outer_radius - inner_radius)).abs() < 1.0);
+            assert!((approach.time - (first_time + i as f64 * synodic_period)).abs() < 1.0);
+        }
+    }
+
+    /// The closest approaches to the parent are just the periapses
+    #[test]
+    fn test_elliptical_orbit_periapses() {
+        let (mut simulation, earth) = earth_simulation();
+        let periapsis = 7.0e6;
+        let apoapsis = 2.0e7;
+        let semi_major_axis = (periapsis + apoapsis) / 2.0;
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let speed = f64::sqrt(mu * (2.0 / apoapsis - 1.0 / semi_major_axis));
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), earth, vec2(apoapsis, 0.0), vec2(0.0, speed), 1.0e3, None);
+        simulation.predict_until(100000.0);
+
+        let period = 2.0 * PI * f64::sqrt(semi_major_axis.powi(3) / mu);
+        let approaches = find_closest_approaches(&simulation, spacecraft, earth, 0.0);
+        assert_eq!(approaches.len(), ((100000.0 - period / 2.0) / period).ceil() as usize);
+        for (i, approach) in approaches.iter().enumerate() {
+            assert!((approach.distance - periapsis).abs() < 1.0);
+            assert!((approach.time - (period / 2.0 + i as f64 * period)).abs() < 1.0);
+        }
+        assert!(find_closest_approaches(&simulation, spacecraft, earth, 100000.0).is_empty());
+    }
+}
@@ -1,7 +1,15 @@
 use eframe::egui::Ui;
 
-use crate::{state::State, systems::util::format_time};
+use crate::{state::State, systems::util::format_time, components::trajectory_component::segment::burn::integrator::Integrator};
+
+const RK4_TIME_STEP: f64 = 0.1;
 
 pub fn general(state: &mut State, ui: &mut Ui) {
-    ui.label(format!("Time: {}", format_time(state.time)));
-}
\ No newline at end of file
+    ui.label(format!("Time: {}", format_time(state.simulation.time)));
+    ui.horizontal(|ui| {
+        ui.label("Burn integrator:");
+        let integrator = &mut state.simulation.burn_integrator;
+        ui.radio_value(integrator, Integrator::Rk4 { time_step: RK4_TIME_STEP }, "RK4");
+        ui.radio_value(integrator, Integrator::default(), "Dormand-Prince");
+    });
+}
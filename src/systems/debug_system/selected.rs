@@ -3,25 +3,25 @@ use std::collections::VecDeque;
 use eframe::egui::Ui;
 use nalgebra_glm::DVec2;
 
-use crate::{state::State, components::trajectory_component::segment::{Segment, orbit::Orbit, burn::Burn}, systems::util::{format_time, get_segment_at_time}, storage::entity_allocator::Entity};
+use crate::{state::State, components::{trajectory_component::{manoeuvre::{Manoeuvre, ManoeuvreId}, segment::{Segment, orbit::{Orbit, orbital_elements::{OrbitalElements, Anomaly}}, burn::Burn}}, engine_component::EngineComponent}, systems::util::{format_time, get_segment_at_time}, storage::entity_allocator::Entity};
 
 fn get_absolute_parent_position(state: &State, entity: Entity, time: f64) -> DVec2 {
-    match state.components.parent_components.get(&entity) {
+    match state.simulation.components.parent_components.get(&entity) {
         Some(parent_component) => {
-            let position = get_segment_at_time(state, &entity, time).get_position_at_time(time);
+            let position = get_segment_at_time(&state.simulation, &entity, time).get_position_at_time(time);
             position + get_absolute_parent_position(state, parent_component.get_parent(), time)
         }
-        None => state.components.position_components.get(&entity).unwrap().get_absolute_position()
+        None => state.simulation.components.position_components.get(&entity).unwrap().get_absolute_position()
     }
 }
 
 fn get_absolute_parent_velocity(state: &State, entity: Entity, time: f64) -> DVec2 {
-    match state.components.parent_components.get(&entity) {
+    match state.simulation.components.parent_components.get(&entity) {
         Some(parent_component) => {
-            let velocity = get_segment_at_time(state, &entity, time).get_velocity_at_time(time);
+            let velocity = get_segment_at_time(&state.simulation, &entity, time).get_velocity_at_time(time);
             velocity + get_absolute_parent_velocity(state, parent_component.get_parent(), time)
         }
-        None => state.components.velocity_components.get(&entity).unwrap().get_absolute_velocity()
+        None => state.simulation.components.velocity_components.get(&entity).unwrap().get_absolute_velocity()
     }
 }
 
@@ -40,28 +40,31 @@ fn draw_absolute_point(state: &State, ui: &mut Ui, entity: Entity, time: f64, po
 }
 
 fn draw_burn(state: &mut State, ui: &mut Ui, burn: &Burn) {
-    let parent_name = state.components.name_components.get(&burn.get_parent()).unwrap().get_name();
+    let parent_name = state.simulation.components.name_components.get(&burn.get_parent()).unwrap().get_name();
     ui.label(format!("Parent: {}", parent_name));
     ui.label(format!("Duration: {}", format_time(burn.get_duration())));
-    ui.label(format!("Delta-V: {:.1}", burn.get_total_dv()));
+    ui.label(format!("Delta-V: {:.1} ({:.1} tangent, {:.1} normal)", burn.get_total_dv(), burn.get_tangent_dv(), burn.get_normal_dv()));
+    ui.label(format!("Mass: {:.1} -> {:.1}", burn.get_start_mass(), burn.get_end_mass()));
+    ui.label(format!("Integrator: {:?} ({} points)", burn.get_integrator(), burn.get_point_count()));
+    ui.label(format!("Tangent direction: [{:.5} {:.5}]", burn.get_tangent_direction().x, burn.get_tangent_direction().y));
     ui.collapsing("Start", |ui| draw_absolute_point(state, ui, burn.get_parent(), burn.get_start_time(), burn.get_start_position(), burn.get_start_velocity()));
     ui.collapsing("Current", |ui| draw_absolute_point(state, ui, burn.get_parent(), burn.get_current_time(), burn.get_current_position(), burn.get_current_velocity()));
     ui.collapsing("End", |ui| draw_absolute_point(state, ui, burn.get_parent(), burn.get_end_time(), burn.get_end_position(), burn.get_end_velocity()));
 }
 
 fn draw_orbit(state: &mut State, ui: &mut Ui, orbit: &Orbit) {
-    let parent_name = state.components.name_components.get(&orbit.get_parent()).unwrap().get_name();
+    let parent_name = state.simulation.components.name_components.get(&orbit.get_parent()).unwrap().get_name();
     ui.label(format!("Parent: {}", parent_name));
     ui.label(format!("Duration: {}", format_time(orbit.get_end_time() - orbit.get_start_time())));
     ui.label(format!("Remaining orbits: {}", orbit.get_remaining_orbits()));
     ui.label(format!("Direction: {:?}", orbit.get_direction()));
     match orbit.get_period() {
         Some(period) => {
-            ui.label(format!("Type: ellipse"));
+            ui.label("Type: ellipse");
             ui.label(format!("Period: {}", format_time(period)));
         }
         None => {
-            ui.label(format!("Type: hyperbola"));
+            ui.label("Type: hyperbola");
         }
     }
     ui.label(format!("Semi-major axis: {:.5e}", orbit.get_semi_major_axis()));
@@ -73,26 +76,50 @@ fn draw_orbit(state: &mut State, ui: &mut Ui, orbit: &Orbit) {
     ui.collapsing("End", |ui| draw_absolute_point(state, ui, orbit.get_parent(), orbit.get_end_time(), orbit.get_end_position(), orbit.get_end_velocity()));
 }
 
+/// Osculating elements, ie the orbit the object would follow from here if nothing else acted on it
+fn draw_elements(ui: &mut Ui, elements: OrbitalElements) {
+    ui.label(format!("Semi-major axis: {:.5e}", elements.semi_major_axis));
+    ui.label(format!("Eccentricity: {:.5}", elements.eccentricity));
+    ui.label(format!("Argument of periapsis: {:.5}", elements.argument_of_periapsis));
+    ui.label(format!("Direction: {:?}", elements.direction));
+    if let Anomaly::True(true_anomaly) = elements.anomaly {
+        ui.label(format!("True anomaly: {:.5}", true_anomaly));
+    }
+}
+
+fn draw_engine(ui: &mut Ui, engine: &EngineComponent, mass: f64) {
+    ui.label(format!("Thrust: {:.5e}", engine.get_thrust()));
+    ui.label(format!("Specific impulse: {:.1}", engine.get_specific_impulse()));
+    ui.label(format!("Fuel: {:.1} / {:.1}", mass - engine.get_dry_mass(), engine.get_wet_mass() - engine.get_dry_mass()));
+    ui.label(format!("Remaining delta-V: {:.1}", engine.get_remaining_dv(mass)));
+}
+
+fn draw_manoeuvres(ui: &mut Ui, manoeuvres: &Vec<(ManoeuvreId, Manoeuvre)>) {
+    for (_, manoeuvre) in manoeuvres {
+        ui.label(format!("{}: {:.1} tangent, {:.1} normal", format_time(manoeuvre.time), manoeuvre.tangent_dv, manoeuvre.normal_dv));
+    }
+}
+
 fn draw_trajectory(state: &mut State, ui: &mut Ui, segments: VecDeque<Segment>) {
     let segment_count = segments.len();
     for (i, segment) in segments.iter().enumerate() {
         match segment {
             Segment::Burn(burn) => {
-                ui.collapsing(format!("({}) Burn", segment_count - i), |ui| draw_burn(state, ui, &*burn.borrow()));
+                ui.collapsing(format!("({}) Burn", segment_count - i), |ui| draw_burn(state, ui, &burn.borrow()));
             },
             Segment::Orbit(orbit) => {
-                ui.collapsing(format!("({}) Orbit", segment_count - i), |ui| draw_orbit(state, ui, &*orbit.borrow()));
+                ui.collapsing(format!("({}) Orbit", segment_count - i), |ui| draw_orbit(state, ui, &orbit.borrow()));
             },
         }
     }
 }
 
 pub fn selected(state: &mut State, ui: &mut Ui) {
-    let entity = state.selected_entity.clone();
-    let absolute_position = state.components.position_components.get(&entity).unwrap().get_absolute_position();
-    let absolute_velocity = state.components.velocity_components.get(&entity).unwrap().get_absolute_velocity();
-    let relative_position = state.components.trajectory_components.get(&entity).unwrap().get_current_segment().get_current_position();
-    let relative_velocity = state.components.trajectory_components.get(&entity).unwrap().get_current_segment().get_current_velocity();
+    let entity = state.selected_entity;
+    let absolute_position = state.simulation.components.position_components.get(&entity).unwrap().get_absolute_position();
+    let absolute_velocity = state.simulation.components.velocity_components.get(&entity).unwrap().get_absolute_velocity();
+    let relative_position = state.simulation.components.trajectory_components.get(&entity).unwrap().get_current_segment().get_current_position();
+    let relative_velocity = state.simulation.components.trajectory_components.get(&entity).unwrap().get_current_segment().get_current_velocity();
     ui.collapsing("Absolute", |ui| {
         ui.label(format!("Position: [{:.5e} {:.5e}]", absolute_position.x, absolute_position.y));
         ui.label(format!("Velocity: [{:.5e} {:.5e}]", absolute_velocity.x, absolute_velocity.y));
@@ -101,12 +128,21 @@ pub fn selected(state: &mut State, ui: &mut Ui) {
         ui.label(format!("Position: [{:.5e} {:.5e}]", relative_position.x, relative_position.y));
         ui.label(format!("Velocity: [{:.5e} {:.5e}]", relative_velocity.x, relative_velocity.y));
     });
-    if let Some(parent_component) = state.components.parent_components.get(&entity) {
-        let parent_name = state.components.name_components.get(&parent_component.get_parent()).unwrap().get_name();
+    if let Some(parent_component) = state.simulation.components.parent_components.get(&entity) {
+        let parent_name = state.simulation.components.name_components.get(&parent_component.get_parent()).unwrap().get_name();
+        let parent_mass = state.simulation.components.mass_components.get(&parent_component.get_parent()).unwrap().get_mass();
         ui.label(format!("Parent: {}", parent_name));
+        ui.collapsing("Elements", |ui| draw_elements(ui, OrbitalElements::from_state_vector(parent_mass, relative_position, relative_velocity)));
     }
-    if let Some(trajectory_component) = state.components.trajectory_components.get(&entity) {
+    if let Some(engine) = state.simulation.components.engine_components.get(&entity) {
+        let mass = state.simulation.components.mass_components.get(&entity).unwrap().get_mass();
+        ui.collapsing("Engine", |ui| draw_engine(ui, engine, mass));
+    }
+    if let Some(trajectory_component) = state.simulation.components.trajectory_components.get(&entity) {
         let segments = trajectory_component.get_segments().clone();
+        if !trajectory_component.get_manoeuvres().is_empty() {
+            ui.collapsing("Manoeuvres", |ui| draw_manoeuvres(ui, trajectory_component.get_manoeuvres()));
+        }
         ui.collapsing("Trajectory", |ui| draw_trajectory(state, ui, segments));
     }
 }
\ No newline at end of file
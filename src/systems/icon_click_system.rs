@@ -1,19 +1,23 @@
-use eframe::egui::{PointerButton, Context};
+use eframe::egui::{PointerButton, Context, Key};
 use nalgebra_glm::DVec2;
 
 use crate::{state::State, storage::entity_allocator::Entity, util::get_root_entities, components::icon_component::IconState};
 
-use super::util::get_all_entity_children;
+use super::{util::{get_all_entity_children, get_manoeuvre_nodes}, manoeuvre_node_system::is_using_manoeuvre_handle};
+
+/// Burn icons can be picked from further away than object icons, so that clicking near a burn on an orbit
+/// snaps to the burn rather than the orbit point next to it
+const BURN_ICON_SNAP_FACTOR: f64 = 2.0;
 
 fn get_closest_entity_to_point(state: &State, position: DVec2, entities: &Vec<Entity>) -> (Option<Entity>, f64) {
     let mut closest_distance_squared = f64::MAX;
     let mut closest_object = None;
     for child in entities {
-        let icon_visible = state.components.icon_components.get(&child).unwrap().is_visible();
+        let icon_visible = state.simulation.components.icon_components.get(child).unwrap().is_visible();
         if !icon_visible {
             continue;
         }
-        let child_position = state.components.position_components.get(child).unwrap().get_absolute_position();
+        let child_position = state.simulation.components.position_components.get(child).unwrap().get_absolute_position();
         let distance_squared = (child_position - position).magnitude_squared();
         if closest_distance_squared > distance_squared {
             closest_distance_squared = distance_squared;
@@ -23,15 +27,23 @@ fn get_closest_entity_to_point(state: &State, position: DVec2, entities: &Vec<En
     (closest_object, closest_distance_squared)
 }
 
+/// The closest visible burn icon that the position snaps to, if any
+pub fn get_burn_icon_at_position(state: &State, position: DVec2) -> Option<Entity> {
+    let max_distance_to_snap = state.camera.lock().unwrap().get_max_distance_to_select() * BURN_ICON_SNAP_FACTOR;
+    let (closest_node, closest_distance_squared) = get_closest_entity_to_point(state, position, &get_manoeuvre_nodes(&state.simulation));
+    closest_node.filter(|_| closest_distance_squared < max_distance_to_snap.powi(2))
+}
+
 /// Search all entities to find if any are close enough to the clicked position to be selected
 /// This is done in a breadth-first way - ie, we first check all root objects, then all their children, etc
 /// This is so we don't end up, for example, selecting the moon when we double click what looks like the Earth at a distance
+/// Burn icons are checked last, since they're never allowed to overlap visible object icons anyway
 fn breadth_first_radius_search(state: &State, position: DVec2, max_distance_to_select_squared: f64) -> Option<Entity> {
-    let mut entities = get_root_entities(state);
+    let mut entities = get_root_entities(&state.simulation);
     loop {
         if entities.is_empty() {
-            // We've reached the final layer, since it contains no more entities, so no selected entity has been found
-            return None;
+            // We've reached the final layer, since it contains no more entities, so the only thing left to check is burns
+            return get_burn_icon_at_position(state, position);
         }
 
         let (closest_entity, closest_distance_squared) = get_closest_entity_to_point(state, position, &entities);
@@ -41,13 +53,13 @@ fn breadth_first_radius_search(state: &State, position: DVec2, max_distance_to_s
             }
         }
 
-        entities = get_all_entity_children(state, &entities);
+        entities = get_all_entity_children(&state.simulation, &entities);
     }
 }
 
 fn update_icons(state: &mut State, selected: &Option<Entity>) {
-    for entity in &state.components.entity_allocator.get_entities() {
-        if let Some(icon_component) = state.components.icon_components.get_mut(entity) {
+    for entity in &state.simulation.components.entity_allocator.get_entities() {
+        if let Some(icon_component) = state.simulation.components.icon_components.get_mut(entity) {
             // If entity is being hovered
             if let Some(selected) = selected {
                 if *entity == *selected {
@@ -57,23 +69,38 @@ fn update_icons(state: &mut State, selected: &Option<Entity>) {
             }
 
             // If entity is actively selected
-            if *entity == state.selected_entity {
+            if *entity == state.selected_entity || Some(*entity) == state.selected_manoeuvre_node {
                 icon_component.set_state(IconState::Selected);
                 continue;
             }
 
-            // If entity is not being hovered and is not actively selected
+            if Some(*entity) == state.target {
+                icon_component.set_state(IconState::Targeted);
+                continue;
+            }
+
+            // If entity is not being hovered, selected, or targeted
             icon_component.set_state(IconState::None)
         }
     }
 }
 
+/// Burns and the selected entity itself can't be targeted, and targeting the current target again clears it
+fn toggle_target(state: &mut State, hovered: Entity) {
+    if hovered == state.selected_entity || state.simulation.components.manoeuvre_node_components.get(&hovered).is_some() {
+        return;
+    }
+    state.target = if state.target == Some(hovered) { None } else { Some(hovered) };
+}
+
 pub fn icon_click_system(state: &mut State, context: &Context) {
     if state.mouse_over_any_element {
         return;
     }
 
     let screen_rect = context.screen_rect();
+    // T is left alone while typing into a text box (eg a dv value), just like the warp keys
+    let is_typing = context.wants_keyboard_input();
     context.input(|input| {
         let Some(screen_position) = input.pointer.latest_pos() else {
             return;
@@ -83,12 +110,31 @@ pub fn icon_click_system(state: &mut State, context: &Context) {
         let max_distance_to_select = state.camera.lock().unwrap().get_max_distance_to_select();
         let selected = breadth_first_radius_search(state, world_position, max_distance_to_select.powi(2));
 
+        // Right clicking an icon or pressing T while hovering over it toggles the target, and pressing T over nothing clears it
+        let target_key_pressed = !is_typing && input.key_pressed(Key::T);
+        match selected {
+            Some(selected) if input.pointer.secondary_clicked() || target_key_pressed => toggle_target(state, selected),
+            None if target_key_pressed => state.target = None,
+            _ => (),
+        }
+
         update_icons(state, &selected);
 
+        // Clicking anywhere other than a burn icon (or the selected burn's handles) deselects the burn
+        if input.pointer.primary_clicked() && !is_using_manoeuvre_handle(state, world_position) {
+            state.selected_manoeuvre_node = selected.filter(|selected| state.simulation.components.manoeuvre_node_components.get(selected).is_some());
+        }
+
         if let Some(selected) = selected {
+            if state.simulation.components.manoeuvre_node_components.get(&selected).is_some() {
+                return;
+            }
             // If we're changing the selected object, recenter the camera to focus on that object
             if input.pointer.button_double_clicked(PointerButton::Primary) && selected != state.selected_entity {
                 state.selected_entity = selected;
+                if state.target == Some(selected) {
+                    state.target = None;
+                }
                 state.camera.lock().unwrap().recenter();
             }
         };        
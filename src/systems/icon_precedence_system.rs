@@ -1,20 +1,23 @@
 use crate::{state::State, storage::entity_allocator::Entity, util::get_root_entities};
 
-use super::util::get_all_entity_children;
+use super::util::{get_all_entity_children, get_manoeuvre_nodes};
 
-fn entities_overlap(state: &mut State, entity: &Entity, other_entity: &Entity) -> bool {
+fn positions_overlap(state: &State, entity: &Entity, other_entity: &Entity) -> bool {
     let closest_allowed_distance = state.camera.lock().unwrap().get_max_distance_to_select() * 2.0;
-    let entity_position = state.components.position_components.get(entity).unwrap().get_absolute_position();
-    let entity_mass = state.components.mass_components.get(entity).unwrap().get_mass();
-    let other_entity_position = state.components.position_components.get(other_entity).unwrap().get_absolute_position();
-    let other_entity_mass = state.components.mass_components.get(other_entity).unwrap().get_mass();
-    let distance = (entity_position - other_entity_position).magnitude();
-    distance < closest_allowed_distance && entity_mass < other_entity_mass
+    let entity_position = state.simulation.components.position_components.get(entity).unwrap().get_absolute_position();
+    let other_entity_position = state.simulation.components.position_components.get(other_entity).unwrap().get_absolute_position();
+    (entity_position - other_entity_position).magnitude() < closest_allowed_distance
+}
+
+fn entities_overlap(state: &mut State, entity: &Entity, other_entity: &Entity) -> bool {
+    let entity_mass = state.simulation.components.mass_components.get(entity).unwrap().get_mass();
+    let other_entity_mass = state.simulation.components.mass_components.get(other_entity).unwrap().get_mass();
+    positions_overlap(state, entity, other_entity) && entity_mass < other_entity_mass
 }
 
 fn is_icon_overlapping(state: &mut State, entity: &Entity, entities_at_layer: &Vec<Entity>) -> bool {
     // Check proximity to parent icon
-    if let Some(parent_component) = state.components.parent_components.get(entity) {
+    if let Some(parent_component) = state.simulation.components.parent_components.get(entity) {
         if entities_overlap(state, entity, &parent_component.get_parent()) {
             return true;
         }
@@ -34,29 +37,48 @@ fn is_icon_overlapping(state: &mut State, entity: &Entity, entities_at_layer: &V
 
 fn do_icon_precedence_for_layer(state: &mut State, entities_at_layer: &Vec<Entity>) {
     for entity in entities_at_layer {
-        if let Some(parent_component) = state.components.parent_components.get(entity) {
-            let parent_visible = state.components.icon_components.get(&parent_component.get_parent()).unwrap().is_visible();
+        if let Some(parent_component) = state.simulation.components.parent_components.get(entity) {
+            let parent_visible = state.simulation.components.icon_components.get(&parent_component.get_parent()).unwrap().is_visible();
             let is_visible = parent_visible && !is_icon_overlapping(state, entity, entities_at_layer);
-            state.components.icon_components.get_mut(entity).unwrap().set_visible(is_visible);
+            state.simulation.components.icon_components.get_mut(entity).unwrap().set_visible(is_visible);
         }
     }
 }
 
+/// Burn icons aren't part of the hierarchy, so they come after every object icon and are hidden if they overlap
+/// any visible icon, including the icons of earlier burns
+fn do_burn_icon_precedence(state: &mut State) {
+    let mut visible_entities: Vec<Entity> = state.simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter(|entity| state.simulation.components.manoeuvre_node_components.get(entity).is_none())
+        .filter(|entity| state.simulation.components.icon_components.get(entity).is_some_and(|icon_component| icon_component.is_visible()))
+        .collect();
+    for node in get_manoeuvre_nodes(&state.simulation) {
+        let is_visible = !visible_entities.iter().any(|other_entity| positions_overlap(state, &node, other_entity));
+        state.simulation.components.icon_components.get_mut(&node).unwrap().set_visible(is_visible);
+        if is_visible {
+            visible_entities.push(node);
+        }
+    }
+}
 
 /// Hides icons that overlap other icons using two rules
 /// 1) Icons that are higher in the hierarchy take precedence (eg, earth takes precedence over moon)
 /// 2) If there are multiple icons at the same layer in the hierarchy, the one with the greatest mass takes precedence
+///
 /// To do this, we traverse each layer and compute whether the children should be shown or not
 /// In each layer, if the parent is hidden, all the children are hidden
 /// Otherwise, we check the distance of the entity to all other children of its parent to determine whether it should be hidden
+/// Burn icons are done last, since objects always take precedence over them
 pub fn icon_precedence_system(state: &mut State) {
-    let mut entities = get_root_entities(state);
+    let mut entities = get_root_entities(&state.simulation);
     loop {
         if entities.is_empty() {
-            // We've reached the final layer, since it contains no more entities, so no selected entity has been found
-            return;
+            // We've reached the final layer, since it contains no more entities
+            break;
         }
         do_icon_precedence_for_layer(state, &entities);
-        entities = get_all_entity_children(state, &entities);
+        entities = get_all_entity_children(&state.simulation, &entities);
     }
+    do_burn_icon_precedence(state);
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use eframe::egui::Context;
+use nalgebra_glm::DVec2;
+
+use crate::{state::State, storage::entity_allocator::Entity, camera::SCALE_FACTOR};
+
+use super::background_prediction_system::{request_edit_prediction, flush_edit_prediction};
+
+use self::{nodes::{sync_manoeuvre_nodes, adjust_node_manoeuvre}, handle::{ManoeuvreHandle, get_handles, get_handle_at_position}};
+
+pub mod handle;
+pub mod nodes;
+
+/// Dragging a handle changes the dv continuously rather than setting it directly, so both small and large burns
+/// can be made without the pointer leaving the screen - this is the rate in m/s per second for each pixel past the handle
+const DV_RATE_PER_PIXEL: f64 = 2.0;
+
+/// Whether the pointer is busy with a handle of the selected node, in which case other systems shouldn't act on clicks
+pub fn is_using_manoeuvre_handle(state: &State, position: DVec2) -> bool {
+    if state.dragged_manoeuvre_handle.is_some() {
+        return true;
+    }
+    let Some(node) = state.selected_manoeuvre_node else {
+        return false;
+    };
+    let zoom = state.camera.lock().unwrap().get_zoom();
+    get_handle_at_position(&state.simulation, node, zoom, position).is_some()
+}
+
+fn drag_handle(state: &mut State, node: Entity, handle: ManoeuvreHandle, position: DVec2) {
+    let zoom = state.camera.lock().unwrap().get_zoom();
+    let Some((_, handle_position, direction)) = get_handles(&state.simulation, node, zoom).into_iter().find(|(other, _, _)| *other == handle) else {
+        return;
+    };
+    let pixels_past_handle = (position - handle_position).dot(&direction) * zoom * SCALE_FACTOR;
+    let (tangent_dv_change, normal_dv_change) = handle.get_dv_change(pixels_past_handle * DV_RATE_PER_PIXEL * state.delta_time);
+    if tangent_dv_change == 0.0 && normal_dv_change == 0.0 {
+        return;
+    }
+    if let Some((spacecraft, time)) = adjust_node_manoeuvre(&mut state.simulation, node, tangent_dv_change, normal_dv_change) {
+        request_edit_prediction(state, spacecraft, time);
+    }
+}
+
+fn deselect_if_removed(state: &mut State) {
+    if let Some(node) = state.selected_manoeuvre_node {
+        if !state.simulation.components.entity_allocator.get_entities().contains(&node) {
+            state.selected_manoeuvre_node = None;
+            state.dragged_manoeuvre_handle = None;
+            flush_edit_prediction(state, true);
+        }
+    }
+}
+
+/// Keeps a node entity (with a burn icon) for each planned manoeuvre, and lets the selected node be edited by dragging its handles
+/// The trajectory is re-predicted in the background every so often while dragging, so the old one is shown until the new one is ready
+pub fn manoeuvre_node_system(state: &mut State, context: &Context) {
+    sync_manoeuvre_nodes(&mut state.simulation);
+    deselect_if_removed(state);
+
+    let screen_rect = context.screen_rect();
+    let (pointer, pressed, down) = context.input(|input| (input.pointer.latest_pos(), input.pointer.primary_pressed(), input.pointer.primary_down()));
+    let position = pointer.map(|pointer| state.camera.lock().unwrap().window_space_to_world_space(pointer, screen_rect));
+
+    if let (Some(node), Some(handle)) = (state.selected_manoeuvre_node, state.dragged_manoeuvre_handle) {
+        if !down {
+            state.dragged_manoeuvre_handle = None;
+            flush_edit_prediction(state, true);
+            return;
+        }
+        if let Some(position) = position {
+            drag_handle(state, node, handle, position);
+        }
+        return;
+    }
+
+    if state.mouse_over_any_element {
+        return;
+    }
+    let Some(position) = position else {
+        return;
+    };
+
+    // Selecting nodes is done by icon_click_system along with all the other icons
+    if pressed {
+        if let Some(node) = state.selected_manoeuvre_node {
+            let zoom = state.camera.lock().unwrap().get_zoom();
+            state.dragged_manoeuvre_handle = get_handle_at_position(&state.simulation, node, zoom, position);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use nalgebra_glm::{DVec2, vec2};
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, camera::SCALE_FACTOR};
+
+use super::nodes::get_node_burn;
+
+/// How far each handle sits from its node, in pixels
+const HANDLE_DISTANCE: f64 = 30.0;
+/// How close the pointer needs to be to a handle to grab it, in pixels
+const HANDLE_GRAB_DISTANCE: f64 = 8.0;
+
+/// Normal here is the in-plane direction perpendicular to the tangent, ie what's usually called radial,
+/// and matches the normal that burns use for their normal dv
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManoeuvreHandle {
+    Prograde,
+    Retrograde,
+    Normal,
+    AntiNormal,
+}
+
+impl ManoeuvreHandle {
+    pub const ALL: [ManoeuvreHandle; 4] = [ManoeuvreHandle::Prograde, ManoeuvreHandle::Retrograde, ManoeuvreHandle::Normal, ManoeuvreHandle::AntiNormal];
+
+    pub fn get_direction(&self, tangent_direction: DVec2) -> DVec2 {
+        let normal_direction = vec2(-tangent_direction.y, tangent_direction.x);
+        match self {
+            ManoeuvreHandle::Prograde => tangent_direction,
+            ManoeuvreHandle::Retrograde => -tangent_direction,
+            ManoeuvreHandle::Normal => normal_direction,
+            ManoeuvreHandle::AntiNormal => -normal_direction,
+        }
+    }
+
+    /// Splits a dv change along this handle into (tangent, normal) components
+    pub fn get_dv_change(&self, amount: f64) -> (f64, f64) {
+        match self {
+            ManoeuvreHandle::Prograde => (amount, 0.0),
+            ManoeuvreHandle::Retrograde => (-amount, 0.0),
+            ManoeuvreHandle::Normal => (0.0, amount),
+            ManoeuvreHandle::AntiNormal => (0.0, -amount),
+        }
+    }
+}
+
+/// World space position and direction of each of the node's handles
+/// The distance from the node depends on zoom, so that the handles stay the same size on screen
+pub fn get_handles(simulation: &Simulation, node: Entity, zoom: f64) -> Vec<(ManoeuvreHandle, DVec2, DVec2)> {
+    let Some(burn) = get_node_burn(simulation, node) else {
+        return vec![];
+    };
+    let node_position = simulation.components.position_components.get(&node).unwrap().get_absolute_position();
+    let tangent_direction = burn.borrow().get_tangent_direction();
+    let distance = HANDLE_DISTANCE / (zoom * SCALE_FACTOR);
+    ManoeuvreHandle::ALL.iter()
+        .map(|handle| {
+            let direction = handle.get_direction(tangent_direction);
+            (*handle, node_position + direction * distance, direction)
+        })
+        .collect()
+}
+
+pub fn get_handle_at_position(simulation: &Simulation, node: Entity, zoom: f64, position: DVec2) -> Option<ManoeuvreHandle> {
+    let max_distance = HANDLE_GRAB_DISTANCE / (zoom * SCALE_FACTOR);
+    get_handles(simulation, node, zoom).into_iter()
+        .find(|(_, handle_position, _)| (handle_position - position).magnitude() < max_distance)
+        .map(|(handle, _, _)| handle)
+}
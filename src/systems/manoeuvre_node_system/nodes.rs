@@ -0,0 +1,252 @@
+use std::{rc::Rc, cell::RefCell};
+
+use nalgebra_glm::DVec2;
+
+use crate::{simulation::Simulation, storage::{entity_allocator::Entity, entity_builder::add_manoeuvre_node}, components::trajectory_component::{segment::burn::Burn, manoeuvre::Manoeuvre}};
+
+/// None if prediction hasn't reached the node's manoeuvre (eg because it's past the end of the prediction horizon)
+pub fn get_node_burn(simulation: &Simulation, node: Entity) -> Option<Rc<RefCell<Burn>>> {
+    let node_component = simulation.components.manoeuvre_node_components.get(&node)?;
+    let trajectory_component = simulation.components.trajectory_components.get(&node_component.get_spacecraft())?;
+    trajectory_component.get_manoeuvre_burn(node_component.get_manoeuvre())
+}
+
+pub fn get_node_manoeuvre(simulation: &Simulation, node: Entity) -> Option<Manoeuvre> {
+    let node_component = simulation.components.manoeuvre_node_components.get(&node)?;
+    let trajectory_component = simulation.components.trajectory_components.get(&node_component.get_spacecraft())?;
+    trajectory_component.get_manoeuvre(node_component.get_manoeuvre())
+}
+
+/// How long after its planned time the node's burn starts, which is only more than zero if it had to wait for an earlier burn to finish
+pub fn get_node_delay(simulation: &Simulation, node: Entity) -> f64 {
+    match (get_node_manoeuvre(simulation, node), get_node_burn(simulation, node)) {
+        (Some(manoeuvre), Some(burn)) => burn.borrow().get_start_time() - manoeuvre.time,
+        _ => 0.0,
+    }
+}
+
+/// Drawn relative to where the parent is now rather than where it will be, the same as the trajectory itself
+fn get_node_position(simulation: &Simulation, burn: &Burn) -> DVec2 {
+    let parent_position = simulation.components.position_components.get(&burn.get_parent()).unwrap().get_absolute_position();
+    parent_position + burn.get_start_position()
+}
+
+/// Makes sure there's exactly one node for each manoeuvre that has a burn, and moves the nodes to their burns
+/// Nodes for manoeuvres that no longer exist (eg because the burn has started) are deallocated
+pub fn sync_manoeuvre_nodes(simulation: &mut Simulation) {
+    let mut existing_nodes = vec![];
+    for entity in simulation.components.entity_allocator.get_entities() {
+        let Some(node_component) = simulation.components.manoeuvre_node_components.get(&entity) else {
+            continue;
+        };
+        let key = (node_component.get_spacecraft(), node_component.get_manoeuvre());
+        let burn = get_node_burn(simulation, entity);
+        match burn {
+            Some(burn) if get_node_manoeuvre(simulation, entity).is_some() => {
+                let position = get_node_position(simulation, &burn.borrow());
+                simulation.components.position_components.get_mut(&entity).unwrap().set_absolute_position(position);
+                existing_nodes.push(key);
+            }
+            _ => entity.deallocate(&mut simulation.components),
+        }
+    }
+
+    for spacecraft in simulation.components.entity_allocator.get_entities() {
+        let Some(trajectory_component) = simulation.components.trajectory_components.get(&spacecraft) else {
+            continue;
+        };
+        for (id, _) in trajectory_component.get_manoeuvres().clone() {
+            if existing_nodes.contains(&(spacecraft, id)) {
+                continue;
+            }
+            let burn = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_manoeuvre_burn(id);
+            if let Some(burn) = burn {
+                let position = get_node_position(simulation, &burn.borrow());
+                add_manoeuvre_node(&mut simulation.components, spacecraft, id, position);
+            }
+        }
+    }
+}
+
+// None of the edits below re-predict anything - instead they return the spacecraft and the time it has to be re-predicted from,
+// so that the caller can decide how to do it (eg on a background prediction)
+
+/// The total dv is capped at whatever the spacecraft will have left when the burn starts
+pub fn set_node_manoeuvre_dv(simulation: &mut Simulation, node: Entity, tangent_dv: f64, normal_dv: f64) -> Option<(Entity, f64)> {
+    let mut manoeuvre = get_node_manoeuvre(simulation, node)?;
+    let node_component = simulation.components.manoeuvre_node_components.get(&node).unwrap();
+    let (spacecraft, id) = (node_component.get_spacecraft(), node_component.get_manoeuvre());
+    manoeuvre.tangent_dv = tangent_dv;
+    manoeuvre.normal_dv = normal_dv;
+    let engine = simulation.components.engine_components.get(&spacecraft);
+    let burn = get_node_burn(simulation, node);
+    if let (Some(engine), Some(burn)) = (engine, burn) {
+        let max_dv = engine.get_remaining_dv(burn.borrow().get_start_mass());
+        let total_dv = f64::sqrt(manoeuvre.tangent_dv.powi(2) + manoeuvre.normal_dv.powi(2));
+        if total_dv > max_dv {
+            manoeuvre.tangent_dv *= max_dv / total_dv;
+            manoeuvre.normal_dv *= max_dv / total_dv;
+        }
+    }
+    simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().set_manoeuvre(id, manoeuvre);
+    // From now if the manoeuvre's time has already passed while it waits for an earlier burn
+    Some((spacecraft, f64::max(manoeuvre.time, simulation.time)))
+}
+
+pub fn adjust_node_manoeuvre(simulation: &mut Simulation, node: Entity, tangent_dv_change: f64, normal_dv_change: f64) -> Option<(Entity, f64)> {
+    let manoeuvre = get_node_manoeuvre(simulation, node)?;
+    set_node_manoeuvre_dv(simulation, node, manoeuvre.tangent_dv + tangent_dv_change, manoeuvre.normal_dv + normal_dv_change)
+}
+
+/// Prediction starts from where the burn was, so deleting the last burn leaves the spacecraft coasting from there on
+/// The node itself is left for the next sync to clean up
+pub fn delete_node_manoeuvre(simulation: &mut Simulation, node: Entity) -> Option<(Entity, f64)> {
+    let manoeuvre = get_node_manoeuvre(simulation, node)?;
+    let node_component = simulation.components.manoeuvre_node_components.get(&node).unwrap();
+    let (spacecraft, id) = (node_component.get_spacecraft(), node_component.get_manoeuvre());
+    simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().remove_manoeuvre(id);
+    Some((spacecraft, f64::max(manoeuvre.time, simulation.time)))
+}
+
+/// Keeps the dv the same, and refuses (returning None) to move the manoeuvre into the past
+/// Moving it onto or into another burn is allowed, in which case it waits for that burn to finish
+/// The node keeps referring to the manoeuvre, so it stays selected
+pub fn move_node_manoeuvre(simulation: &mut Simulation, node: Entity, new_time: f64) -> Option<(Entity, f64)> {
+    let manoeuvre = get_node_manoeuvre(simulation, node)?;
+    if new_time <= simulation.time {
+        return None;
+    }
+    let node_component = simulation.components.manoeuvre_node_components.get(&node).unwrap();
+    let (spacecraft, id) = (node_component.get_spacecraft(), node_component.get_manoeuvre());
+    simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().set_manoeuvre(id, Manoeuvre { time: new_time, ..manoeuvre });
+    Some((spacecraft, f64::max(f64::min(manoeuvre.time, new_time), simulation.time)))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, add_circular_spacecraft}, components::{engine_component::EngineComponent, trajectory_component::segment::Segment}, systems::{util::get_manoeuvre_nodes, trajectory_prediction_system::spacecraft_prediction::predict_spacecraft}};
+
+    use super::*;
+
+    const END_TIME: f64 = 20000.0;
+
+    /// A spacecraft in a circular orbit around a lone earth, with the given manoeuvres already predicted
+    fn simulation_with_manoeuvres(manoeuvres: &[Manoeuvre]) -> (Simulation, Entity) {
+        let (mut simulation, earth) = earth_simulation();
+        // Away from the origin, so the nodes' positions have to include the earth's
+        simulation.components.position_components.get_mut(&earth).unwrap().set_absolute_position(vec2(1.0e9, 0.0));
+        let engine = EngineComponent::new(2.0e4, 350.0, 4.0e3, 1.0e4);
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 8.0e6, 1.0e4, Some(engine));
+        for manoeuvre in manoeuvres {
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(*manoeuvre);
+        }
+        simulation.predict_until(END_TIME);
+        sync_manoeuvre_nodes(&mut simulation);
+        (simulation, spacecraft)
+    }
+
+    /// What the UI does on a worker thread, but only up to END_TIME
+    fn predict(simulation: &mut Simulation, invalidation: Option<(Entity, f64)>) {
+        let (spacecraft, time) = invalidation.unwrap();
+        predict_spacecraft(simulation, spacecraft, time, END_TIME);
+    }
+
+    fn get_node_at_time(simulation: &Simulation, time: f64) -> Entity {
+        get_manoeuvre_nodes(simulation).into_iter()
+            .find(|node| get_node_manoeuvre(simulation, *node).unwrap().time == time)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_nodes_follow_manoeuvres() {
+        let time = 1000.0;
+        let (mut simulation, _) = simulation_with_manoeuvres(&[Manoeuvre { time, tangent_dv: 100.0, normal_dv: 0.0 }]);
+        let nodes = get_manoeuvre_nodes(&simulation);
+        assert_eq!(nodes.len(), 1);
+        let node = nodes[0];
+        let burn = get_node_burn(&simulation, node).unwrap();
+        assert_eq!(burn.borrow().get_start_time(), time);
+        let expected_position = vec2(1.0e9, 0.0) + burn.borrow().get_start_position();
+        assert!((simulation.components.position_components.get(&node).unwrap().get_absolute_position() - expected_position).magnitude() < 1.0e-3);
+
+        // Syncing again shouldn't create a second node for the same manoeuvre
+        sync_manoeuvre_nodes(&mut simulation);
+        assert_eq!(get_manoeuvre_nodes(&simulation), vec![node]);
+
+        let invalidation = adjust_node_manoeuvre(&mut simulation, node, 50.0, -20.0);
+        predict(&mut simulation, invalidation);
+        let burn = get_node_burn(&simulation, node).unwrap();
+        assert!((burn.borrow().get_tangent_dv() - 150.0).abs() < 1.0e-9);
+        assert!((burn.borrow().get_normal_dv() + 20.0).abs() < 1.0e-9);
+
+        // Asking for more than the fuel allows is capped, keeping the direction
+        let remaining_dv = EngineComponent::new(2.0e4, 350.0, 4.0e3, 1.0e4).get_remaining_dv(1.0e4);
+        let invalidation = adjust_node_manoeuvre(&mut simulation, node, 1.0e5, 0.0);
+        predict(&mut simulation, invalidation);
+        let manoeuvre = get_node_manoeuvre(&simulation, node).unwrap();
+        assert!((f64::sqrt(manoeuvre.tangent_dv.powi(2) + manoeuvre.normal_dv.powi(2)) - remaining_dv).abs() < 1.0e-6);
+        assert!(manoeuvre.normal_dv < 0.0);
+
+        // Once the burn starts, the manoeuvre is no longer planned so its node goes away
+        simulation.update(time + 10.0);
+        sync_manoeuvre_nodes(&mut simulation);
+        assert!(get_manoeuvre_nodes(&simulation).is_empty());
+    }
+
+    #[test]
+    fn test_delete_last_burn() {
+        let time = 1000.0;
+        let (mut simulation, spacecraft) = simulation_with_manoeuvres(&[Manoeuvre { time, tangent_dv: 300.0, normal_dv: 0.0 }]);
+        let (coast_simulation, coast_spacecraft) = simulation_with_manoeuvres(&[]);
+
+        let node = get_node_at_time(&simulation, time);
+        let invalidation = delete_node_manoeuvre(&mut simulation, node);
+        predict(&mut simulation, invalidation);
+        sync_manoeuvre_nodes(&mut simulation);
+        assert!(get_manoeuvre_nodes(&simulation).is_empty());
+
+        // Should be exactly the same orbit as if the burn had never been planned
+        let segments = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_segments();
+        let coast_segments = coast_simulation.components.trajectory_components.get(&coast_spacecraft).unwrap().get_segments();
+        assert!(segments.iter().all(|segment| matches!(segment, Segment::Orbit(_))));
+        assert_eq!(segments.len(), coast_segments.len());
+        let end_position = segments.back().unwrap().get_position_at_time(END_TIME - 100.0);
+        let coast_end_position = coast_segments.back().unwrap().get_position_at_time(END_TIME - 100.0);
+        assert!((end_position - coast_end_position).magnitude() < 1.0e-3);
+    }
+
+    #[test]
+    fn test_move_manoeuvre() {
+        let first = Manoeuvre { time: 1000.0, tangent_dv: 100.0, normal_dv: 50.0 };
+        let second = Manoeuvre { time: 5000.0, tangent_dv: -100.0, normal_dv: 0.0 };
+        let (mut simulation, _) = simulation_with_manoeuvres(&[first, second]);
+        let node = get_node_at_time(&simulation, first.time);
+        let second_node = get_node_at_time(&simulation, second.time);
+
+        assert_eq!(move_node_manoeuvre(&mut simulation, node, -10.0), None);
+        let invalidation = move_node_manoeuvre(&mut simulation, node, 2000.0);
+        predict(&mut simulation, invalidation);
+
+        // The same node now refers to the moved manoeuvre, and its burn has moved with it
+        sync_manoeuvre_nodes(&mut simulation);
+        assert_eq!(get_node_at_time(&simulation, 2000.0), node);
+        assert_eq!(get_node_manoeuvre(&simulation, node), Some(Manoeuvre { time: 2000.0, ..first }));
+        let burn = get_node_burn(&simulation, node).unwrap();
+        assert_eq!(burn.borrow().get_start_time(), 2000.0);
+        assert!((burn.borrow().get_normal_dv() - 50.0).abs() < 1.0e-9);
+        assert_eq!(get_node_delay(&simulation, node), 0.0);
+
+        // Moving it on top of the other manoeuvre makes it wait until the other burn is done, and both nodes are kept
+        let invalidation = move_node_manoeuvre(&mut simulation, node, second.time);
+        predict(&mut simulation, invalidation);
+        sync_manoeuvre_nodes(&mut simulation);
+        assert_eq!(get_manoeuvre_nodes(&simulation), vec![second_node, node]);
+        let second_burn = get_node_burn(&simulation, second_node).unwrap();
+        let burn = get_node_burn(&simulation, node).unwrap();
+        assert_eq!(second_burn.borrow().get_start_time(), second.time);
+        assert_eq!(burn.borrow().get_start_time(), second_burn.borrow().get_end_time());
+        assert!(get_node_delay(&simulation, node) > 0.0);
+    }
+}
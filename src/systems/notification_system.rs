@@ -0,0 +1,40 @@
+use std::time::Instant;
+
+use eframe::{egui::{Context, Window, Id, Label}, emath::Align2, epaint};
+
+use crate::state::State;
+
+/// How long a notification stays on screen, in real seconds
+const NOTIFICATION_DURATION: f64 = 3.0;
+
+/// A short message for the player, eg to say a quicksave worked or why it didn't
+pub struct Notification {
+    message: String,
+    created: Instant,
+}
+
+/// Replaces whatever notification is currently showing
+pub fn notify(state: &mut State, message: String) {
+    state.notification = Some(Notification { message, created: Instant::now() });
+}
+
+pub fn notification_system(state: &mut State, context: &Context) {
+    let Some(notification) = &state.notification else {
+        return;
+    };
+    if notification.created.elapsed().as_secs_f64() > NOTIFICATION_DURATION {
+        state.notification = None;
+        return;
+    }
+    let message = notification.message.clone();
+    // Below the background prediction window, so they can both be shown at once
+    Window::new("Notification")
+        .id(Id::new("notification"))
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, epaint::vec2(0.0, 40.0))
+        .show(context, |ui| {
+            ui.add(Label::new(message));
+            state.register_ui(ui);
+        });
+}
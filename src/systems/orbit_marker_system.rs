@@ -0,0 +1,202 @@
+use std::f64::consts::PI;
+
+use eframe::{egui::{Context, Area, Id, Label, RichText}, epaint::{self, Color32}};
+use nalgebra_glm::DVec2;
+
+use crate::{state::State, simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::segment::{Segment, orbit::Orbit}};
+
+use super::util::{format_time, format_distance};
+
+/// How close the mouse needs to be to a marker to show its details, in pixels
+const HOVER_DISTANCE: f32 = 8.0;
+const LABEL_OFFSET: f32 = 12.0;
+pub const APSIS_MARKER_COLOR: Color32 = Color32::from_rgb(120, 200, 255);
+pub const SOI_MARKER_COLOR: Color32 = Color32::from_rgb(255, 230, 90);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrbitMarkerType {
+    Periapsis,
+    Apoapsis,
+    SoiEntry,
+    SoiExit,
+}
+
+impl OrbitMarkerType {
+    pub fn get_color(&self) -> Color32 {
+        match self {
+            OrbitMarkerType::Periapsis | OrbitMarkerType::Apoapsis => APSIS_MARKER_COLOR,
+            OrbitMarkerType::SoiEntry | OrbitMarkerType::SoiExit => SOI_MARKER_COLOR,
+        }
+    }
+}
+
+/// Something happening at a point on a trajectory - the position is relative to the parent at the time
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitMarker {
+    pub marker_type: OrbitMarkerType,
+    pub time: f64,
+    pub parent: Entity,
+    pub position: DVec2,
+}
+
+impl OrbitMarker {
+    /// Drawn around the parent's current position, like the trajectory it's on
+    pub fn get_rendered_position(&self, simulation: &Simulation) -> DVec2 {
+        simulation.components.position_components.get(&self.parent).unwrap().get_absolute_position() + self.position
+    }
+
+    pub fn get_altitude(&self, simulation: &Simulation) -> f64 {
+        let radius = simulation.components.celestial_body_components.get(&self.parent).unwrap().get_radius();
+        self.position.magnitude() - radius
+    }
+}
+
+/// The first time at or after from_time that the orbit passes the given angle past periapsis, if that's before the orbit ends
+/// Hyperbolas only pass periapsis once, and never reach apoapsis
+fn get_next_apsis_time(orbit: &Orbit, from_time: f64, offset_angle: f64) -> Option<f64> {
+    let periapsis_time = orbit.get_periapsis_time();
+    let time = match orbit.get_period() {
+        Some(period) => {
+            let apsis_time = periapsis_time + period * offset_angle / (2.0 * PI);
+            apsis_time + ((from_time - apsis_time) / period).ceil() * period
+        }
+        None => {
+            if offset_angle != 0.0 || periapsis_time < from_time {
+                return None;
+            }
+            periapsis_time
+        }
+    };
+    (time <= orbit.get_end_time()).then_some(time)
+}
+
+fn add_apsis_markers(markers: &mut Vec<OrbitMarker>, orbit: &Orbit, time: f64) {
+    let from_time = f64::max(time, orbit.get_start_time());
+    let argument_of_periapsis = orbit.get_arugment_of_periapsis();
+    for (marker_type, offset_angle) in [(OrbitMarkerType::Periapsis, 0.0), (OrbitMarkerType::Apoapsis, PI)] {
+        if let Some(apsis_time) = get_next_apsis_time(orbit, from_time, offset_angle) {
+            let position = orbit.get_position_from_theta(argument_of_periapsis + offset_angle);
+            markers.push(OrbitMarker { marker_type, time: apsis_time, parent: orbit.get_parent(), position });
+        }
+    }
+}
+
+/// The next periapsis and apoapsis of every orbit segment that hasn't finished yet, plus both sides of every SOI change
+/// Orbits that go round several times only get a marker for the next pass, since the rest would be in the same place
+pub fn get_orbit_markers(simulation: &Simulation, entity: Entity) -> Vec<OrbitMarker> {
+    let mut markers = vec![];
+    let Some(trajectory_component) = simulation.components.trajectory_components.get(&entity) else {
+        return markers;
+    };
+    let segments = trajectory_component.get_segments();
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.get_end_time() < simulation.time {
+            continue;
+        }
+        if let Segment::Orbit(orbit) = segment {
+            add_apsis_markers(&mut markers, &orbit.borrow(), simulation.time);
+        }
+        let Some(next_segment) = segments.get(i + 1) else {
+            continue;
+        };
+        if next_segment.get_parent() != segment.get_parent() {
+            let time = segment.get_end_time();
+            markers.push(OrbitMarker { marker_type: OrbitMarkerType::SoiExit, time, parent: segment.get_parent(), position: segment.get_position_at_time(time) });
+            markers.push(OrbitMarker { marker_type: OrbitMarkerType::SoiEntry, time, parent: next_segment.get_parent(), position: next_segment.get_position_at_time(time) });
+        }
+    }
+    markers
+}
+
+fn get_marker_text(state: &State, marker: &OrbitMarker) -> String {
+    let parent_name = state.simulation.components.name_components.get(&marker.parent).unwrap().get_name();
+    let title = match marker.marker_type {
+        OrbitMarkerType::Periapsis => "Periapsis".to_string(),
+        OrbitMarkerType::Apoapsis => "Apoapsis".to_string(),
+        OrbitMarkerType::SoiEntry => format!("Entering {}", parent_name),
+        OrbitMarkerType::SoiExit => format!("Leaving {}", parent_name),
+    };
+    format!("{}\nAltitude: {}\nT-{}", title, format_distance(marker.get_altitude(&state.simulation)), format_time(marker.time - state.simulation.time))
+}
+
+/// Shows the details of whichever of the selected entity's markers the mouse is over
+pub fn orbit_marker_system(state: &mut State, context: &Context) {
+    let Some(mouse_position) = context.input(|input| input.pointer.latest_pos()) else {
+        return;
+    };
+    let screen_rect = context.screen_rect();
+    let hovered = get_orbit_markers(&state.simulation, state.selected_entity)
+        .into_iter()
+        .map(|marker| {
+            let position = marker.get_rendered_position(&state.simulation);
+            (marker, state.camera.lock().unwrap().world_space_to_window_space(position, screen_rect))
+        })
+        .filter(|(_, screen_position)| screen_position.distance(mouse_position) < HOVER_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.distance(mouse_position).total_cmp(&b.distance(mouse_position)));
+    let Some((marker, screen_position)) = hovered else {
+        return;
+    };
+    let text = get_marker_text(state, &marker);
+    Area::new(Id::new("orbit_marker"))
+        .fixed_pos(screen_position + epaint::vec2(LABEL_OFFSET, LABEL_OFFSET))
+        .interactable(false)
+        .show(context, |ui| ui.add(Label::new(RichText::new(text).color(marker.marker_type.get_color()).small())));
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, earth_moon_simulation, EARTH_MASS, EARTH_RADIUS, MOON_MASS, MOON_DISTANCE}, storage::entity_builder::add_child_object, components::trajectory_component::segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT};
+
+    use super::*;
+
+    #[test]
+    fn test_apsis_markers() {
+        let (mut simulation, earth) = earth_simulation();
+        let periapsis = 7.0e6;
+        let apoapsis = 2.0e7;
+        let semi_major_axis = (periapsis + apoapsis) / 2.0;
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let speed = f64::sqrt(mu * (2.0 / periapsis - 1.0 / semi_major_axis));
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), earth, vec2(periapsis, 0.0), vec2(0.0, speed), 1.0e3, None);
+        simulation.predict_until(100000.0);
+        let period = 2.0 * PI * f64::sqrt(semi_major_axis.powi(3) / mu);
+
+        // Starting at periapsis, so that's the first marker
+        let markers = get_orbit_markers(&simulation, spacecraft);
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].marker_type, OrbitMarkerType::Periapsis);
+        assert!(markers[0].time.abs() < 1.0e-3);
+        assert!((markers[0].position - vec2(periapsis, 0.0)).magnitude() < 1.0);
+        assert!((markers[0].get_altitude(&simulation) - (periapsis - EARTH_RADIUS)).abs() < 1.0);
+        assert_eq!(markers[1].marker_type, OrbitMarkerType::Apoapsis);
+        assert!((markers[1].time - period / 2.0).abs() < 1.0e-3);
+        assert!((markers[1].position - vec2(-apoapsis, 0.0)).magnitude() < 1.0);
+
+        // Once periapsis has gone past, the marker moves on to the next pass
+        simulation.update(period / 4.0);
+        let markers = get_orbit_markers(&simulation, spacecraft);
+        assert!((markers[0].time - period).abs() < 1.0e-3);
+        assert!((markers[1].time - period / 2.0).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn test_soi_markers() {
+        let (mut simulation, earth, moon) = earth_moon_simulation();
+        // Fast enough to escape the moon
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), moon, vec2(2.0e6, 0.0), vec2(0.0, 3.0e3), 1.0e3, None);
+        simulation.predict_until(1.0e6);
+
+        let markers = get_orbit_markers(&simulation, spacecraft);
+        let exit = markers.iter().find(|marker| marker.marker_type == OrbitMarkerType::SoiExit).unwrap();
+        let entry = markers.iter().find(|marker| marker.marker_type == OrbitMarkerType::SoiEntry).unwrap();
+        assert_eq!(exit.parent, moon);
+        assert_eq!(entry.parent, earth);
+        assert_eq!(exit.time, entry.time);
+        let sphere_of_influence = MOON_DISTANCE * (MOON_MASS / EARTH_MASS).powf(2.0 / 5.0);
+        assert!((exit.position.magnitude() - sphere_of_influence).abs() / sphere_of_influence < 0.01);
+        // Hyperbolas never reach apoapsis
+        assert!(!markers.iter().any(|marker| marker.parent == moon && marker.marker_type == OrbitMarkerType::Apoapsis));
+    }
+}
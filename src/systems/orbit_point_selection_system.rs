@@ -3,7 +3,7 @@ use std::{rc::Rc, cell::RefCell};
 use eframe::{egui::{Context, InputState}, epaint::{Pos2, Rect, Rgba}};
 use nalgebra_glm::{vec2, DVec2};
 
-use crate::{state::State, util::add_textured_square, camera::SCALE_FACTOR, storage::entity_allocator::Entity, components::trajectory_component::segment::{orbit::Orbit, Segment}};
+use crate::{state::State, systems::{manoeuvre_node_system::is_using_manoeuvre_handle, icon_click_system::get_burn_icon_at_position}, util::add_textured_square, camera::SCALE_FACTOR, storage::entity_allocator::Entity, components::trajectory_component::segment::{orbit::Orbit, Segment}};
 
 const SELECTION_CIRCLE_SIZE: f64 = 5.0;
 
@@ -17,7 +17,7 @@ pub struct OrbitClickPoint {
 
 impl OrbitClickPoint {
     pub fn get_absolute_position(&self, state: &State) -> DVec2 {
-        let parent_position = state.components.position_components.get(&self.orbit.borrow().get_parent()).unwrap().get_absolute_position();
+        let parent_position = state.simulation.components.position_components.get(&self.orbit.borrow().get_parent()).unwrap().get_absolute_position();
         let relative_position = self.orbit.borrow().get_position_from_time_since_periapsis(self.time_since_periapsis);
         parent_position + relative_position
     }
@@ -32,11 +32,11 @@ impl OrbitClickPoint {
 }
 
 fn create_new_click_point(entity: &Entity, orbit: &Rc<RefCell<Orbit>>, click_distance: f64, max_distance_to_select: f64, mut time_since_periapsis: f64) -> Option<OrbitClickPoint> {
-    let mut click_point = OrbitClickPoint { entity: entity.clone(), click_distance, orbit: orbit.clone(), time_since_periapsis };
+    let mut click_point = OrbitClickPoint { entity: *entity, click_distance, orbit: orbit.clone(), time_since_periapsis };
     if let Some(period) = orbit.borrow().get_period() {
         while !orbit.borrow().is_time_within_orbit(click_point.get_time()) && click_point.get_time() < orbit.borrow().get_end_time() {
             time_since_periapsis += period;
-            click_point = OrbitClickPoint { entity: entity.clone(), click_distance, orbit: orbit.clone(), time_since_periapsis }
+            click_point = OrbitClickPoint { entity: *entity, click_distance, orbit: orbit.clone(), time_since_periapsis }
         }
     }
     if orbit.borrow().is_time_within_orbit(click_point.get_time()) && click_distance < max_distance_to_select {
@@ -48,20 +48,17 @@ fn create_new_click_point(entity: &Entity, orbit: &Rc<RefCell<Orbit>>, click_dis
 
 fn test_orbit_clicked(state: &State, entity: &Entity, orbit: &Rc<RefCell<Orbit>>, click_position: DVec2, max_distance_to_select: f64) -> Option<OrbitClickPoint> {
     let parent = orbit.borrow().get_parent();
-    let absolute_parent_position = state.components.position_components.get(&parent).unwrap().get_absolute_position();
+    let absolute_parent_position = state.simulation.components.position_components.get(&parent).unwrap().get_absolute_position();
     let argument_of_periapsis = orbit.borrow().get_arugment_of_periapsis();
-    let relative_nominal_position = orbit.borrow().get_position_from_theta(argument_of_periapsis);
-    let nominal_position_to_center_vector = -orbit.borrow().get_semi_major_axis() * vec2(f64::cos(argument_of_periapsis), f64::sin(argument_of_periapsis));
-    let center = absolute_parent_position + relative_nominal_position + nominal_position_to_center_vector;
-    let click_position_relative_to_center = click_position - center;
+    // Relative to the parent rather than the center, which is infinitely far away for a parabola
+    let click_position_relative_to_parent = click_position - absolute_parent_position;
     let adjusted_click_point = vec2(
-        click_position_relative_to_center.x * f64::cos(-argument_of_periapsis) - click_position_relative_to_center.y * f64::sin(-argument_of_periapsis),
-        click_position_relative_to_center.x * f64::sin(-argument_of_periapsis) + click_position_relative_to_center.y * f64::cos(-argument_of_periapsis));
-    let closest_point_relative_to_center = orbit.borrow().solve_for_closest_point(adjusted_click_point);
-    let adjusted_closest_point_relative_to_center = vec2(
-        closest_point_relative_to_center.x * f64::cos(argument_of_periapsis) - closest_point_relative_to_center.y * f64::sin(argument_of_periapsis),
-        closest_point_relative_to_center.x * f64::sin(argument_of_periapsis) + closest_point_relative_to_center.y * f64::cos(argument_of_periapsis));
-    let closest_point_relative_to_parent = (center + adjusted_closest_point_relative_to_center) - absolute_parent_position;
+        click_position_relative_to_parent.x * f64::cos(-argument_of_periapsis) - click_position_relative_to_parent.y * f64::sin(-argument_of_periapsis),
+        click_position_relative_to_parent.x * f64::sin(-argument_of_periapsis) + click_position_relative_to_parent.y * f64::cos(-argument_of_periapsis));
+    let adjusted_closest_point = orbit.borrow().solve_for_closest_point(adjusted_click_point);
+    let closest_point_relative_to_parent = vec2(
+        adjusted_closest_point.x * f64::cos(argument_of_periapsis) - adjusted_closest_point.y * f64::sin(argument_of_periapsis),
+        adjusted_closest_point.x * f64::sin(argument_of_periapsis) + adjusted_closest_point.y * f64::cos(argument_of_periapsis));
     let theta_relative_to_parent = f64::atan2(closest_point_relative_to_parent.y, closest_point_relative_to_parent.x);
     let time_since_periapsis = orbit.borrow().get_time_since_periapsis(theta_relative_to_parent);
     let closest_point_relative_to_parent = orbit.borrow().get_position_from_theta(theta_relative_to_parent);
@@ -83,9 +80,9 @@ fn get_closest_click_point(click_points: Vec<OrbitClickPoint>) -> Option<OrbitCl
 }
 
 fn click_point_overlaps_any_icon(state: &State, click_point: &OrbitClickPoint) -> bool {
-    for entity in state.components.entity_allocator.get_entities() {
-        if state.components.icon_components.get(&entity).is_some() {
-            let position_component = state.components.position_components.get(&entity).unwrap();
+    for entity in state.simulation.components.entity_allocator.get_entities() {
+        if state.simulation.components.icon_components.get(&entity).is_some() {
+            let position_component = state.simulation.components.position_components.get(&entity).unwrap();
             let distance = (position_component.get_absolute_position() - click_point.get_absolute_position(state)).magnitude();
             let max_distance = state.camera.lock().unwrap().get_max_distance_to_select();
             if distance < max_distance {
@@ -98,10 +95,14 @@ fn click_point_overlaps_any_icon(state: &State, click_point: &OrbitClickPoint) -
 
 fn get_click_point(state: &mut State, screen_size: Rect, position: Pos2) -> Option<OrbitClickPoint> {
     let position = state.camera.lock().unwrap().window_space_to_world_space(position, screen_size);
+    // Burn icons snap from further away than the orbit, so clicking near one selects the burn instead
+    if is_using_manoeuvre_handle(state, position) || get_burn_icon_at_position(state, position).is_some() {
+        return None;
+    }
     let max_distance_to_select = state.camera.lock().unwrap().get_max_distance_to_select();
     let mut click_points = vec![];
-    for entity in &state.components.entity_allocator.get_entities() {
-        if let Some(trajectory_component) = state.components.trajectory_components.get(&entity) {
+    for entity in &state.simulation.components.entity_allocator.get_entities() {
+        if let Some(trajectory_component) = state.simulation.components.trajectory_components.get(entity) {
             for segment in trajectory_component.get_segments() {
                 if let Segment::Orbit(orbit) = segment {
                     let click_point = test_orbit_clicked(state, entity, orbit, position, max_distance_to_select);
@@ -134,7 +135,7 @@ fn on_new_click_point_exists(state: &mut State, input: &InputState, click_point:
         state.orbit_click_point = Some(click_point.clone());
     } else {
         if state.orbit_click_point.is_none() {
-            render_click_point(state, &click_point, 0.6);
+            render_click_point(state, click_point, 0.6);
         }
     }
 }
@@ -145,9 +146,11 @@ fn on_new_click_point_no_exists(state: &mut State, input: &InputState) {
     }
 }
 
+/// The orbit can also have been removed from the trajectory altogether, eg when the prediction horizon is shortened
 fn invalidate_click_point(state: &mut State) {
     if let Some(click_point) = &state.orbit_click_point {
-        if click_point.orbit.borrow().is_finished() || !click_point.orbit.borrow().is_time_within_orbit(click_point.get_time()) {
+        let final_time = state.simulation.components.trajectory_components.get(&click_point.entity).unwrap().get_final_segment().get_end_time();
+        if click_point.orbit.borrow().is_finished() || !click_point.orbit.borrow().is_time_within_orbit(click_point.get_time()) || click_point.get_time() > final_time {
             state.orbit_click_point = None;
         }
     }
@@ -1,35 +1,206 @@
-use std::{cell::RefCell, rc::Rc};
+use eframe::{egui::{Context, Window, Image, ImageButton, Ui, Layout, Label, Button, DragValue}, emath::{Align2, Align}, epaint::{self, Color32, Rounding, Shadow, Stroke}};
 
-use eframe::{egui::{Context, Window, Image, ImageButton, Ui, Layout, Label}, emath::{Align2, Align}, epaint::{self, Color32, Rounding, Shadow, Stroke}};
+use crate::{state::State, components::trajectory_component::{manoeuvre::Manoeuvre, prediction_horizon::{PredictionHorizon, DEFAULT_PREDICTION_DURATION}}, planner::{TransferTarget, Transfer, insert_transfer, get_replaced_manoeuvres, hohmann::plan_hohmann, bi_elliptic::plan_bi_elliptic, porkchop::Porkchop}, storage::entity_allocator::Entity};
 
-use crate::{state::State, components::trajectory_component::segment::{Segment, burn::Burn, orbit::Orbit}, systems::util::get_segment_at_time};
+use super::{warp_update_system::{WarpDescription, WarpEvent, get_warp_event_time, warp_to_event}, util::{format_time, get_segment_at_time}, trajectory_prediction_system::horizon::{set_prediction_horizon, is_horizon_capped, MAX_PREDICTION_DURATION}, background_prediction_system::{request_prediction, request_edit_prediction, flush_edit_prediction}};
 
-use super::{warp_update_system::WarpDescription, util::format_time, trajectory_prediction_system::spacecraft_prediction::predict_spacecraft};
+/// New burns are all prograde with a fixed dv, which can then be adjusted with the manoeuvre node's handles
+const NEW_BURN_DV: f64 = 1000.0;
+/// Bi-elliptic transfers go out to this many times the radius of whichever orbit is bigger
+const BI_ELLIPTIC_RADIUS_FACTOR: f64 = 2.0;
+
+#[derive(PartialEq)]
+struct TransferPlansKey {
+    entity: Entity,
+    time: f64,
+    transfer_orbit_radius: f64,
+    /// Of the spacecraft's trajectory and every target body's, so the plans follow any re-prediction
+    versions: Vec<u64>,
+}
+
+/// Planning transfers to every target is too slow to do every frame, so they're only planned again when the click point,
+/// the circular orbit radius or any of the trajectories they depend on change
+pub struct TransferPlans {
+    key: TransferPlansKey,
+    /// The target's name, then its Hohmann and bi-elliptic transfers
+    plans: Vec<(String, Option<Transfer>, Option<Transfer>)>,
+}
 
 fn warp_to_point(state: &mut State) {
     let click_point = state.orbit_click_point.as_ref().unwrap();
-    state.current_warp = Some(WarpDescription { start_time: state.time, end_time: click_point.get_time() });
+    state.current_warp = Some(WarpDescription { start_time: state.simulation.time, end_time: click_point.get_time() });
 }
 
 fn create_burn(state: &mut State) {
     let time = state.orbit_click_point.as_ref().unwrap().get_time();
     let entity = state.orbit_click_point.as_ref().unwrap().get_entity();
-    let segment_containing_burn = get_segment_at_time(state, &entity, time);
-    let orbit_containing_burn = segment_containing_burn.as_orbit();
-    let parent = orbit_containing_burn.borrow().get_parent();
-    let velocity_direction = orbit_containing_burn.borrow().get_end_velocity().normalize();
-    state.components.trajectory_components.get_mut(&entity).unwrap().remove_segments_after(time);
-    let burn = Burn::new(&state, entity, parent, velocity_direction, time);
-    let orbit_start_time = burn.get_end_time();
-    let orbit = Orbit::new(&state.components, parent, burn.get_end_position(), burn.get_end_velocity(), orbit_start_time);
+    let manoeuvre = Manoeuvre { time, tangent_dv: NEW_BURN_DV, normal_dv: 0.0 };
+    state.simulation.components.trajectory_components.get_mut(&entity).unwrap().add_manoeuvre(manoeuvre);
+    request_prediction(state, entity, time);
+}
+
+/// Every body orbiting the same parent as the spacecraft, by name so the list doesn't jump around between frames
+fn get_transfer_targets(state: &State, entity: Entity, time: f64) -> Vec<(String, TransferTarget)> {
+    let parent = get_segment_at_time(&state.simulation, &entity, time).get_parent();
+    let mut targets: Vec<(String, TransferTarget)> = state.simulation.components.celestial_body_components.get(&parent).unwrap().get_children()
+        .iter()
+        .filter(|child| **child != entity && state.simulation.components.celestial_body_components.get(child).is_some())
+        .map(|child| (state.simulation.components.name_components.get(child).unwrap().get_name(), TransferTarget::Body(*child)))
+        .collect();
+    targets.sort_by(|a, b| a.0.cmp(&b.0));
+    targets.push(("Circular orbit".to_string(), TransferTarget::CircularOrbit(state.transfer_orbit_radius)));
+    targets
+}
+
+fn get_celestial_children(state: &State, parent: Entity, excluded: Entity) -> Vec<Entity> {
+    state.simulation.components.celestial_body_components.get(&parent).unwrap().get_children()
+        .iter()
+        .filter(|child| **child != excluded && state.simulation.components.celestial_body_components.get(child).is_some())
+        .copied()
+        .collect()
+}
+
+/// Bodies orbiting the spacecraft's parent, plus bodies orbiting the parent's parent (eg other planets), sorted by name
+fn get_porkchop_targets(state: &State, entity: Entity, time: f64) -> Vec<(String, Entity)> {
+    let parent = get_segment_at_time(&state.simulation, &entity, time).get_parent();
+    let mut targets = get_celestial_children(state, parent, entity);
+    if let Some(parent_component) = state.simulation.components.parent_components.get(&parent) {
+        targets.extend(get_celestial_children(state, parent_component.get_parent(), parent));
+    }
+    let mut targets: Vec<(String, Entity)> = targets.into_iter()
+        .map(|target| (state.simulation.components.name_components.get(&target).unwrap().get_name(), target))
+        .collect();
+    targets.sort_by(|a, b| a.0.cmp(&b.0));
+    targets
+}
+
+fn draw_launch_window_buttons(state: &mut State, ui: &mut Ui, entity: Entity, time: f64) {
+    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+        ui.add(Label::new("Launch windows"));
+        for (name, target) in get_porkchop_targets(state, entity, time) {
+            if ui.add(Button::new(name)).clicked() {
+                state.porkchop = Porkchop::compute(&state.simulation, entity, target, time);
+            }
+        }
+    });
+}
 
-    state.components.trajectory_components.get_mut(&entity).unwrap().add_segment(Segment::Burn(Rc::new(RefCell::new(burn))));
-    state.components.trajectory_components.get_mut(&entity).unwrap().add_segment(Segment::Orbit(Rc::new(RefCell::new(orbit))));
+/// Choosing a transfer throws away any burns planned after it starts, so they're listed before it's chosen
+pub fn describe_replaced_burns(state: &State, entity: Entity, transfer_start_time: f64) -> String {
+    get_replaced_manoeuvres(&state.simulation, entity, transfer_start_time)
+        .iter()
+        .map(|manoeuvre| format!("\nReplaces the {:.0} m/s burn at T-{}", f64::sqrt(manoeuvre.tangent_dv.powi(2) + manoeuvre.normal_dv.powi(2)), format_time(manoeuvre.time - state.simulation.time)))
+        .collect()
+}
 
-    predict_spacecraft(state, entity, orbit_start_time, 10000000.0)
+fn draw_transfer_button(state: &mut State, ui: &mut Ui, entity: Entity, name: &str, transfer: Option<Transfer>) {
+    let Some(transfer) = transfer else {
+        return;
+    };
+    let text = format!("{}: {:.0} m/s, {}", name, transfer.get_total_dv(), format_time(transfer.transfer_time));
+    let hover_text = format!("Departs in {}{}", format_time(transfer.get_departure_time() - state.simulation.time), describe_replaced_burns(state, entity, transfer.start_time));
+    if ui.add(Button::new(text)).on_hover_text(hover_text).clicked() {
+        insert_transfer(&mut state.simulation, entity, &transfer);
+        request_prediction(state, entity, transfer.start_time);
+        // The clicked orbit is about to be replaced by prediction
+        state.orbit_click_point = None;
+    }
 }
 
+fn update_transfer_plans(state: &mut State, entity: Entity, time: f64) {
+    let targets = get_transfer_targets(state, entity, time);
+    let versions = targets.iter()
+        .filter_map(|(_, target)| match target {
+            TransferTarget::Body(body) => Some(*body),
+            TransferTarget::CircularOrbit(_) => None,
+        })
+        .chain([entity])
+        .filter_map(|entity| state.simulation.components.trajectory_components.get(&entity).map(|trajectory_component| trajectory_component.get_version()))
+        .collect();
+    let key = TransferPlansKey { entity, time, transfer_orbit_radius: state.transfer_orbit_radius, versions };
+    if state.transfer_plans.as_ref().is_some_and(|transfer_plans| transfer_plans.key == key) {
+        return;
+    }
+    let plans = targets.into_iter()
+        .map(|(name, target)| {
+            let hohmann = plan_hohmann(&state.simulation, entity, target, time);
+            let bi_elliptic = plan_bi_elliptic(&state.simulation, entity, target, time, BI_ELLIPTIC_RADIUS_FACTOR);
+            (name, hohmann, bi_elliptic)
+        })
+        .collect();
+    state.transfer_plans = Some(TransferPlans { key, plans });
+}
 
+/// Transfers depart at the earliest from the clicked point, since that's the orbit they're computed from
+fn draw_transfers(state: &mut State, ui: &mut Ui) {
+    let time = state.orbit_click_point.as_ref().unwrap().get_time();
+    let entity = state.orbit_click_point.as_ref().unwrap().get_entity();
+    ui.add(DragValue::new(&mut state.transfer_orbit_radius).speed(1.0e5).prefix("Circular orbit radius: ").suffix(" m"));
+    update_transfer_plans(state, entity, time);
+    for (name, hohmann, bi_elliptic) in state.transfer_plans.as_ref().unwrap().plans.clone() {
+        ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+            ui.add(Label::new(name));
+            draw_transfer_button(state, ui, entity, "Hohmann", hohmann);
+            if state.orbit_click_point.is_none() {
+                return;
+            }
+            draw_transfer_button(state, ui, entity, "Bi-elliptic", bi_elliptic);
+        });
+        if state.orbit_click_point.is_none() {
+            return;
+        }
+    }
+    draw_launch_window_buttons(state, ui, entity, time);
+}
+
+/// Only events that actually happen get a button
+fn draw_warp_events(state: &mut State, ui: &mut Ui, entity: Entity) {
+    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+        for event in WarpEvent::ALL {
+            let Some(time) = get_warp_event_time(&state.simulation, entity, event, state.burn_lead_time) else {
+                continue;
+            };
+            if ui.add(Button::new(event.get_name())).on_hover_text(format!("T-{}", format_time(time - state.simulation.time))).clicked() {
+                warp_to_event(state, entity, event);
+            }
+        }
+    });
+    ui.add(DragValue::new(&mut state.burn_lead_time).speed(1.0).clamp_range(0.0..=3600.0).prefix("Burn lead time: ").suffix(" s"));
+}
+
+/// Switching between kinds of horizon starts from a sensible amount rather than converting the old one
+fn draw_prediction_horizon(state: &mut State, ui: &mut Ui, entity: Entity) {
+    let old_horizon = state.simulation.components.trajectory_components.get(&entity).unwrap().get_horizon();
+    let mut horizon = old_horizon;
+    ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
+        if ui.selectable_label(matches!(horizon, PredictionHorizon::Duration(_)), "Time").clicked() {
+            horizon = PredictionHorizon::Duration(DEFAULT_PREDICTION_DURATION);
+        }
+        if ui.selectable_label(matches!(horizon, PredictionHorizon::Orbits(_)), "Orbits").clicked() {
+            horizon = PredictionHorizon::Orbits(3.0);
+        }
+        if ui.selectable_label(matches!(horizon, PredictionHorizon::SoiChanges(_)), "SOI changes").clicked() {
+            horizon = PredictionHorizon::SoiChanges(2);
+        }
+    });
+    let response = match &mut horizon {
+        PredictionHorizon::Duration(duration) => ui.add(DragValue::new(duration).speed(1.0e4).clamp_range(1.0e3..=MAX_PREDICTION_DURATION).suffix(" s")),
+        PredictionHorizon::Orbits(orbits) => ui.add(DragValue::new(orbits).speed(0.05).clamp_range(0.1..=100.0).suffix(" orbits")),
+        PredictionHorizon::SoiChanges(soi_changes) => ui.add(DragValue::new(soi_changes).speed(0.05).clamp_range(0..=10).suffix(" SOI changes")),
+    };
+    // Dragging the value changes it every frame, so the prediction is only sent off straight away once it's been let go of
+    let is_finished = std::mem::discriminant(&horizon) != std::mem::discriminant(&old_horizon) || response.drag_released() || response.lost_focus();
+    if horizon != old_horizon {
+        let time = set_prediction_horizon(&mut state.simulation, entity, horizon);
+        request_edit_prediction(state, entity, time);
+    }
+    if is_finished {
+        flush_edit_prediction(state, true);
+    }
+    if is_horizon_capped(&state.simulation, entity) {
+        ui.add(Label::new(format!("Not reached within {}, so stopping there", format_time(MAX_PREDICTION_DURATION))));
+    }
+}
 
 fn draw(state: &mut State, ui: &mut Ui) {
     ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
@@ -42,7 +213,7 @@ fn draw(state: &mut State, ui: &mut Ui) {
         }
 
         let entity = state.orbit_click_point.as_ref().unwrap().get_entity();
-        if state.components.celestial_body_components.get(&entity).is_none() {
+        if state.simulation.components.engine_components.get(&entity).is_some() {
             let burn_image = Image::new(state.resources.get_texture_image("burn"))
                 .bg_fill(Color32::TRANSPARENT)
                 .fit_to_exact_size(epaint::vec2(15.0, 15.0));
@@ -53,17 +224,21 @@ fn draw(state: &mut State, ui: &mut Ui) {
         }
     });
 
-    let remaining_time = state.orbit_click_point.as_ref().unwrap().get_time() - state.time;
+    let remaining_time = state.orbit_click_point.as_ref().unwrap().get_time() - state.simulation.time;
     ui.add(Label::new("T-".to_string() + format_time(remaining_time).as_str()));
 
+    let entity = state.orbit_click_point.as_ref().unwrap().get_entity();
+    ui.collapsing("Warp to", |ui| draw_warp_events(state, ui, entity));
+    ui.collapsing("Prediction horizon", |ui| draw_prediction_horizon(state, ui, entity));
+    if state.simulation.components.engine_components.get(&entity).is_some() {
+        ui.collapsing("Transfers", |ui| draw_transfers(state, ui));
+    }
+
     state.register_ui(ui);
 }
 
-pub fn orbit_point_toolbar_system(state: &mut State, context: &Context) {
-    if state.orbit_click_point.is_none() {
-        return;
-    };
-
+/// Shared with the burn toolbar so that both look the same
+pub fn apply_toolbar_style(context: &Context) {
     context.style_mut(|style| {
         let rounding = 10.0;
         let rounding_struct = Rounding { nw: rounding, ne: rounding, sw: rounding, se: rounding };
@@ -78,6 +253,14 @@ pub fn orbit_point_toolbar_system(state: &mut State, context: &Context) {
         style.visuals.widgets.active.bg_fill = Color32::from_white_alpha(100);
         style.visuals.widgets.active.rounding = rounding_struct;
     });
+}
+
+pub fn orbit_point_toolbar_system(state: &mut State, context: &Context) {
+    if state.orbit_click_point.is_none() {
+        return;
+    };
+
+    apply_toolbar_style(context);
 
     let window = Window::new("")
         .title_bar(false)
@@ -0,0 +1,114 @@
+use eframe::{egui::{Context, Window, Ui, Label, Sense, Response, Id}, emath::{Align2, Rect, Pos2}, epaint::{self, Color32, Rgba, Stroke}};
+
+use crate::{state::State, planner::{insert_transfer, porkchop::{Porkchop, PorkchopCell}}};
+
+use super::{util::format_time, background_prediction_system::request_prediction, orbit_point_toolbar_system::describe_replaced_burns};
+
+const PLOT_SIZE: f32 = 400.0;
+const MISSING_CELL_COLOR: Color32 = Color32::from_gray(40);
+const HOVERED_CELL_STROKE: Stroke = Stroke { width: 1.5, color: Color32::WHITE };
+
+/// Log scale from blue (cheapest) to red (most expensive), since the expensive corners would otherwise drown out
+/// the differences that matter around the cheapest transfers
+fn get_cell_color(total_dv: f64, dv_range: (f64, f64)) -> Color32 {
+    let (min, max) = dv_range;
+    let fraction = if max > min { (total_dv / min).ln() / (max / min).ln() } else { 0.0 };
+    let fraction = fraction.clamp(0.0, 1.0) as f32;
+    Rgba::from_rgb(fraction, 0.2 * (1.0 - (2.0 * fraction - 1.0).abs()), 1.0 - fraction).into()
+}
+
+fn get_cell_rect(plot: Rect, resolution: (usize, usize), departure_index: usize, arrival_index: usize) -> Rect {
+    let cell_size = epaint::vec2(plot.width() / resolution.0 as f32, plot.height() / resolution.1 as f32);
+    // Arrival time goes up the plot
+    let min = Pos2::new(plot.min.x + departure_index as f32 * cell_size.x, plot.max.y - (arrival_index + 1) as f32 * cell_size.y);
+    Rect::from_min_size(min, cell_size)
+}
+
+fn get_hovered_cell(porkchop: &Porkchop, plot: Rect, response: &Response) -> Option<(usize, usize)> {
+    let position = response.hover_pos()?;
+    let resolution = porkchop.get_resolution();
+    let departure_index = ((position.x - plot.min.x) / plot.width() * resolution.0 as f32) as usize;
+    let arrival_index = ((plot.max.y - position.y) / plot.height() * resolution.1 as f32) as usize;
+    (departure_index < resolution.0 && arrival_index < resolution.1).then_some((departure_index, arrival_index))
+}
+
+/// Cells departing before now can't be flown any more
+fn is_cell_available(state: &State, cell: &PorkchopCell) -> bool {
+    cell.departure_time > state.simulation.time
+}
+
+fn draw_plot(state: &State, ui: &mut Ui, porkchop: &Porkchop) -> Option<PorkchopCell> {
+    let (response, painter) = ui.allocate_painter(epaint::vec2(PLOT_SIZE, PLOT_SIZE), Sense::click());
+    let plot = response.rect;
+    let resolution = porkchop.get_resolution();
+    let dv_range = porkchop.get_dv_range();
+    for departure_index in 0..resolution.0 {
+        for arrival_index in 0..resolution.1 {
+            let color = match (porkchop.get_cell(departure_index, arrival_index), dv_range) {
+                (Some(cell), Some(dv_range)) if is_cell_available(state, cell) => get_cell_color(cell.get_total_dv(), dv_range),
+                _ => MISSING_CELL_COLOR,
+            };
+            painter.rect_filled(get_cell_rect(plot, resolution, departure_index, arrival_index), 0.0, color);
+        }
+    }
+
+    let (departure_index, arrival_index) = get_hovered_cell(porkchop, plot, &response)?;
+    let cell = porkchop.get_cell(departure_index, arrival_index)?;
+    if !is_cell_available(state, cell) {
+        return None;
+    }
+    painter.rect_stroke(get_cell_rect(plot, resolution, departure_index, arrival_index), 0.0, HOVERED_CELL_STROKE);
+    let text = format!("Departs in {}\nFlight time {}\nDeparture: {:.0} m/s\nArrival: {:.0} m/s\nTotal: {:.0} m/s{}",
+        format_time(cell.departure_time - state.simulation.time),
+        format_time(cell.arrival_time - cell.departure_time),
+        cell.departure_dv, cell.arrival_dv, cell.get_total_dv(),
+        describe_replaced_burns(state, porkchop.get_spacecraft(), porkchop.get_start_time()));
+    let clicked = response.on_hover_text(text).clicked();
+    clicked.then(|| cell.clone())
+}
+
+fn draw(state: &mut State, ui: &mut Ui, porkchop: &Porkchop) -> Option<PorkchopCell> {
+    let (departure_start, departure_end) = porkchop.get_departure_range();
+    let (arrival_start, arrival_end) = porkchop.get_arrival_range();
+    ui.add(Label::new(format!("Departure (across): T-{} to T-{}", format_time(departure_start - state.simulation.time), format_time(departure_end - state.simulation.time))));
+    ui.add(Label::new(format!("Arrival (up): T-{} to T-{}", format_time(arrival_start - state.simulation.time), format_time(arrival_end - state.simulation.time))));
+    if let Some((min, max)) = porkchop.get_dv_range() {
+        ui.add(Label::new(format!("Total delta-V: {:.0} m/s (blue) to {:.0} m/s (red)", min, max)));
+    }
+    if let Some(cell) = porkchop.get_cheapest_cell() {
+        ui.add(Label::new(format!("Cheapest departs in {} with flight time {}", format_time(cell.departure_time - state.simulation.time), format_time(cell.arrival_time - cell.departure_time))));
+    }
+    let cell = draw_plot(state, ui, porkchop);
+    state.register_ui(ui);
+    cell
+}
+
+/// Clicking a cell replaces any planned burns after the start of the plot with the cell's departure burn
+pub fn porkchop_system(state: &mut State, context: &Context) {
+    let Some(porkchop) = state.porkchop.take() else {
+        return;
+    };
+    let target_name = state.simulation.components.name_components.get(&porkchop.get_target()).unwrap().get_name();
+    let mut open = true;
+    let mut clicked_cell = None;
+    Window::new(format!("Launch windows to {}", target_name))
+        .id(Id::new("porkchop"))
+        .open(&mut open)
+        .resizable(false)
+        .anchor(Align2::RIGHT_TOP, epaint::vec2(0.0, 0.0))
+        .show(context, |ui| clicked_cell = draw(state, ui, &porkchop));
+
+    if let Some(cell) = clicked_cell {
+        if let Some(transfer) = porkchop.get_transfer(&state.simulation, &cell) {
+            if transfer.get_departure_time() > state.simulation.time {
+                insert_transfer(&mut state.simulation, porkchop.get_spacecraft(), &transfer);
+                request_prediction(state, porkchop.get_spacecraft(), transfer.start_time);
+                state.orbit_click_point = None;
+                return;
+            }
+        }
+    }
+    if open {
+        state.porkchop = Some(porkchop);
+    }
+}
@@ -0,0 +1,45 @@
+use eframe::egui::{Context, Key};
+
+use crate::{state::State, save::{save, load, QUICKSAVE_PATH}};
+
+use super::{time_step_update_system::TimeStepDescription, notification_system::notify, trajectory_prediction_system::background::BackgroundPrediction};
+
+fn quicksave(state: &mut State) {
+    match save(QUICKSAVE_PATH, &state.simulation, state.selected_entity) {
+        Ok(()) => notify(state, format!("Saved to {}", QUICKSAVE_PATH)),
+        Err(error) => notify(state, error.to_string()),
+    }
+}
+
+fn quickload(state: &mut State) {
+    match load(QUICKSAVE_PATH) {
+        Ok((simulation, selected_entity)) => {
+            state.simulation = simulation;
+            state.selected_entity = selected_entity;
+            // These refer to segments and times from the old simulation, so they can't be kept
+            state.orbit_click_point = None;
+            state.selected_manoeuvre_node = None;
+            state.dragged_manoeuvre_handle = None;
+            state.unpredicted_edit = None;
+            state.transfer_plans = None;
+            state.porkchop = None;
+            state.target = None;
+            state.closest_approaches = None;
+            state.background_prediction = BackgroundPrediction::default();
+            state.current_warp = None;
+            state.time_step_description = TimeStepDescription::Level(1);
+            notify(state, format!("Loaded {}", QUICKSAVE_PATH));
+        }
+        Err(error) => notify(state, error.to_string()),
+    }
+}
+
+pub fn save_load_system(state: &mut State, context: &Context) {
+    let (save_pressed, load_pressed) = context.input(|input| (input.key_pressed(Key::F5), input.key_pressed(Key::F9)));
+    if save_pressed {
+        quicksave(state);
+    }
+    if load_pressed {
+        quickload(state);
+    }
+}
@@ -0,0 +1,107 @@
+use eframe::{egui::{Context, Window, Ui, Label, Id}, emath::Align2, epaint};
+use nalgebra_glm::DVec2;
+
+use crate::{state::State, simulation::Simulation, storage::entity_allocator::Entity};
+
+use super::util::format_distance;
+
+/// How the target is moving as seen from the selected entity
+#[derive(Debug, Clone, Copy)]
+pub struct RelativeMotion {
+    pub distance: f64,
+    pub relative_speed: f64,
+    /// Positive when the two are getting closer
+    pub closing_speed: f64,
+    /// How far ahead the target is around their shared parent, in the direction the entity is orbiting (in radians, from -PI to PI)
+    /// Only defined when both are orbiting the same parent
+    pub phase_angle: Option<f64>,
+}
+
+fn get_parent(simulation: &Simulation, entity: Entity) -> Option<Entity> {
+    simulation.components.parent_components.get(&entity).map(|parent_component| parent_component.get_parent())
+}
+
+/// Angle from a to b, anticlockwise positive
+fn get_signed_angle(a: DVec2, b: DVec2) -> f64 {
+    f64::atan2(a.x * b.y - a.y * b.x, a.dot(&b))
+}
+
+pub fn get_relative_motion(simulation: &Simulation, entity: Entity, target: Entity) -> RelativeMotion {
+    let position = simulation.components.position_components.get(&entity).unwrap().get_absolute_position();
+    let velocity = simulation.components.velocity_components.get(&entity).unwrap().get_absolute_velocity();
+    let target_position = simulation.components.position_components.get(&target).unwrap().get_absolute_position();
+    let target_velocity = simulation.components.velocity_components.get(&target).unwrap().get_absolute_velocity();
+    let relative_position = target_position - position;
+    let relative_velocity = target_velocity - velocity;
+    let distance = relative_position.magnitude();
+    let closing_speed = if distance == 0.0 { 0.0 } else { -relative_position.dot(&relative_velocity) / distance };
+
+    let phase_angle = get_parent(simulation, entity)
+        .filter(|parent| get_parent(simulation, target) == Some(*parent))
+        .map(|parent| {
+            let parent_position = simulation.components.position_components.get(&parent).unwrap().get_absolute_position();
+            let parent_velocity = simulation.components.velocity_components.get(&parent).unwrap().get_absolute_velocity();
+            let angle = get_signed_angle(position - parent_position, target_position - parent_position);
+            let angular_momentum = get_signed_angle(position - parent_position, velocity - parent_velocity);
+            if angular_momentum < 0.0 { -angle } else { angle }
+        });
+
+    RelativeMotion { distance, relative_speed: relative_velocity.magnitude(), closing_speed, phase_angle }
+}
+
+fn draw(state: &mut State, ui: &mut Ui, target: Entity) {
+    let relative_motion = get_relative_motion(&state.simulation, state.selected_entity, target);
+    ui.add(Label::new(format!("Distance: {}", format_distance(relative_motion.distance))));
+    ui.add(Label::new(format!("Relative velocity: {:.1} m/s", relative_motion.relative_speed)));
+    ui.add(Label::new(format!("Closing speed: {:.1} m/s", relative_motion.closing_speed)));
+    if let Some(phase_angle) = relative_motion.phase_angle {
+        ui.add(Label::new(format!("Phase angle: {:.1}°", phase_angle.to_degrees())));
+    }
+    state.register_ui(ui);
+}
+
+pub fn target_hud_system(state: &mut State, context: &Context) {
+    let Some(target) = state.target else {
+        return;
+    };
+    let target_name = state.simulation.components.name_components.get(&target).unwrap().get_name();
+    Window::new(format!("Target: {}", target_name))
+        .id(Id::new("target_hud"))
+        .resizable(false)
+        .anchor(Align2::RIGHT_BOTTOM, epaint::vec2(0.0, 0.0))
+        .show(context, |ui| draw(state, ui, target));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, add_circular_spacecraft, get_circular_speed}, storage::entity_builder::add_child_object};
+
+    use super::*;
+
+    #[test]
+    fn test_relative_motion() {
+        let (mut simulation, earth) = earth_simulation();
+        let speed = get_circular_speed(&simulation, earth, 7.0e6);
+        // Same orbit, but the target is a quarter of an orbit ahead (anticlockwise)
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 7.0e6, 1.0e3, None);
+        let target = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "target".to_string(), earth, vec2(0.0, 7.0e6), vec2(-speed, 0.0), 1.0e3, None);
+
+        let relative_motion = get_relative_motion(&simulation, spacecraft, target);
+        assert!((relative_motion.distance - 7.0e6 * f64::sqrt(2.0)).abs() < 1.0e-3);
+        assert!((relative_motion.relative_speed - speed * f64::sqrt(2.0)).abs() < 1.0e-6);
+        // Both on the same circle, so they stay the same distance apart
+        assert!(relative_motion.closing_speed.abs() < 1.0e-6);
+        assert!((relative_motion.phase_angle.unwrap() - PI / 2.0).abs() < 1.0e-9);
+        // Seen from the target, the spacecraft is a quarter of an orbit behind
+        assert!((get_relative_motion(&simulation, target, spacecraft).phase_angle.unwrap() + PI / 2.0).abs() < 1.0e-9);
+
+        // The earth is the spacecraft's parent rather than a sibling, so there's no phase angle
+        let relative_motion = get_relative_motion(&simulation, spacecraft, earth);
+        assert!(relative_motion.phase_angle.is_none());
+        assert!((relative_motion.closing_speed).abs() < 1.0e-6);
+    }
+}
@@ -1,9 +1,13 @@
 use eframe::egui::{Key, Context};
 
-use crate::state::State;
+use crate::{state::State, simulation::Simulation};
+
+use super::util::{get_next_burn_time, is_any_burn_active};
 
 const MIN_TIME_STEP_LEVELS: i32 = 1;
 const MAX_TIME_STEP_LEVELS: i32 = 9;
+/// Burns (and the lead time before them) are slowed down to this so the player can see what's happening
+pub const MAX_BURN_TIME_STEP: f64 = 5.0;
 
 pub enum TimeStepDescription {
     Level(i32),
@@ -23,11 +27,47 @@ fn update_time_step_level(state: &mut State, context: &Context) {
     }
 }
 
-fn update_time_step(state: &mut State) {
-    state.time += state.delta_time * state.get_time_step();
+/// The fastest time can go this frame without a burn going by too quickly
+/// Before the lead time, it can go as fast as it likes as long as it doesn't skip past the start of the lead time in one frame
+pub fn get_time_step_limit(simulation: &Simulation, burn_lead_time: f64, delta_time: f64) -> Option<f64> {
+    if is_any_burn_active(simulation) {
+        return Some(MAX_BURN_TIME_STEP);
+    }
+    let time_until_lead = get_next_burn_time(simulation)? - burn_lead_time - simulation.time;
+    if time_until_lead <= 0.0 || delta_time <= 0.0 {
+        return Some(MAX_BURN_TIME_STEP);
+    }
+    Some(f64::max(time_until_lead / delta_time, MAX_BURN_TIME_STEP))
 }
 
 pub fn time_step_update_system(state: &mut State, context: &Context) {
     update_time_step_level(state, context);
-    update_time_step(state);
+    state.time_step_limit = get_time_step_limit(&state.simulation, state.burn_lead_time, state.delta_time);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{simulation::test_util::{earth_simulation, add_circular_spacecraft}, components::{engine_component::EngineComponent, trajectory_component::manoeuvre::Manoeuvre}};
+
+    use super::*;
+
+    #[test]
+    fn test_time_step_limit() {
+        let (mut simulation, earth) = earth_simulation();
+        let engine = EngineComponent::new(1.0e4, 300.0, 500.0, 1.0e3);
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 7.0e6, 1.0e3, Some(engine));
+        simulation.predict_until(10000.0);
+        assert!(get_time_step_limit(&simulation, 30.0, 0.1).is_none());
+
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(Manoeuvre { time: 1000.0, tangent_dv: 100.0, normal_dv: 0.0 });
+        simulation.predict_until(10000.0);
+        // Far from the burn, the only limit is not skipping past the start of the lead time
+        assert!((get_time_step_limit(&simulation, 30.0, 0.1).unwrap() - 9700.0).abs() < 1.0e-6);
+        simulation.update(980.0);
+        assert_eq!(get_time_step_limit(&simulation, 30.0, 0.1), Some(MAX_BURN_TIME_STEP));
+        // The burn only takes a few seconds
+        simulation.update(25.0);
+        assert!(is_any_burn_active(&simulation));
+        assert_eq!(get_time_step_limit(&simulation, 30.0, 0.1), Some(MAX_BURN_TIME_STEP));
+    }
 }
\ No newline at end of file
@@ -1,3 +1,9 @@
+pub mod background;
 pub mod celestial_body_prediction;
+pub mod ephemeris;
+#[cfg(test)]
+mod benchmark;
+pub mod horizon;
+mod soi_crossing;
 pub mod spacecraft_prediction;
 mod util;
\ No newline at end of file
@@ -0,0 +1,298 @@
+use std::{sync::{mpsc::{self, Receiver, TryRecvError}, atomic::{AtomicU64, Ordering}, Arc}, thread};
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::{trajectory_component::TrajectoryComponent, mass_component::MassComponent}, systems::{util::{is_celestial_body_with_trajectory, sync_all_entities}, trajectory_update_system::update_trajectory}};
+
+use super::{ephemeris::Ephemeris, horizon::extend_predictions_to};
+
+/// What the worker sends back for each entity it changed - masses change as time moves on along a burn, so they're sent along with
+/// the trajectory to move on from the same starting point
+type PredictedEntities = Vec<(Entity, TrajectoryComponent, Option<MassComponent>)>;
+
+pub enum PredictionStatus {
+    Idle,
+    Running,
+    /// The worker died, so everything it was meant to predict has been predicted on the main thread instead
+    Failed,
+}
+
+struct Worker {
+    generation: u64,
+    invalidations: Vec<(Entity, f64)>,
+    snapshot_time: f64,
+    /// None if the worker gave up because it was superseded
+    receiver: Receiver<Option<String>>,
+}
+
+/// Predictions running on a worker thread, one at a time
+/// Segments are shared with Rc, so they can't be sent between threads - instead the worker is given a serialized snapshot of the whole
+/// simulation, and sends back serialized copies of the trajectories it changed, which are then swapped in and moved on to the current time
+/// Time carries on while the worker is running, along the old trajectories
+#[derive(Default)]
+pub struct BackgroundPrediction {
+    /// Bumped by every edit, so the worker can tell (and give up) when what it's predicting is already out of date
+    generation: Arc<AtomicU64>,
+    worker: Option<Worker>,
+    /// Waiting for the worker to finish - each entity is re-predicted from the time it's paired with, in order, and then everything
+    /// is extended to its horizon
+    pending: Option<Vec<(Entity, f64)>>,
+}
+
+fn get_entities_with_trajectories(simulation: &Simulation) -> Vec<Entity> {
+    simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter(|entity| simulation.components.trajectory_components.get(entity).is_some())
+        .collect()
+}
+
+fn describe_trajectory(simulation: &Simulation, entity: Entity) -> Vec<(Entity, f64, f64)> {
+    simulation.components.trajectory_components.get(&entity).unwrap().get_segments()
+        .iter()
+        .map(|segment| (segment.get_parent(), segment.get_start_time(), segment.get_end_time()))
+        .collect()
+}
+
+/// Only the entities that were invalidated or whose segments have changed are sent back - re-predicting a celestial body can change
+/// any trajectory, which is picked up by comparing the segments before and after
+fn predict_snapshot(snapshot: &str, invalidations: &[(Entity, f64)], generation: &AtomicU64, expected_generation: u64) -> Option<String> {
+    let is_superseded = || generation.load(Ordering::Relaxed) != expected_generation;
+    let mut simulation: Simulation = ron::from_str(snapshot).expect("Failed to parse simulation snapshot");
+    simulation.ephemeris = Ephemeris::new(&simulation);
+    let entities = get_entities_with_trajectories(&simulation);
+    let old_trajectories: Vec<Vec<(Entity, f64, f64)>> = entities.iter().map(|entity| describe_trajectory(&simulation, *entity)).collect();
+    let snapshot_time = simulation.time;
+    for (entity, time) in invalidations {
+        if is_superseded() {
+            return None;
+        }
+        // Time might have moved on since the edit was made
+        simulation.invalidate_prediction(*entity, f64::max(*time, snapshot_time));
+    }
+    if is_superseded() {
+        return None;
+    }
+    simulation.predict();
+
+    let predicted: Vec<(Entity, &TrajectoryComponent, Option<&MassComponent>)> = entities.into_iter()
+        .zip(old_trajectories)
+        .filter(|(entity, old_trajectory)| invalidations.iter().any(|(invalidated, _)| invalidated == entity) || describe_trajectory(&simulation, *entity) != *old_trajectory)
+        .map(|(entity, _)| (entity, simulation.components.trajectory_components.get(&entity).unwrap(), simulation.components.mass_components.get(&entity)))
+        .collect();
+    Some(ron::to_string(&predicted).expect("Failed to serialize predicted trajectories"))
+}
+
+/// The trajectories were predicted from a snapshot taken at snapshot_time, so once they're swapped in they're moved along to the
+/// current time just like they would have been if they'd been there all along
+fn swap_in(simulation: &mut Simulation, predicted: &str, snapshot_time: f64) {
+    let predicted: PredictedEntities = ron::from_str(predicted).expect("Failed to parse predicted trajectories");
+    let mut entities = vec![];
+    for (entity, trajectory_component, mass_component) in predicted {
+        simulation.components.trajectory_components.get_mut(&entity).unwrap().replace(trajectory_component);
+        if let Some(mass_component) = mass_component {
+            *simulation.components.mass_components.get_mut(&entity).unwrap() = mass_component;
+        }
+        entities.push(entity);
+    }
+    if entities.iter().any(|entity| is_celestial_body_with_trajectory(simulation, *entity)) {
+        simulation.update_ephemeris();
+    }
+    // In case the worker took longer than its predictions last
+    extend_predictions_to(simulation, simulation.time);
+    let delta_time = simulation.time - snapshot_time;
+    for entity in &entities {
+        update_trajectory(simulation, entity, delta_time);
+    }
+    sync_all_entities(simulation);
+}
+
+impl Worker {
+    fn start(simulation: &Simulation, invalidations: Vec<(Entity, f64)>, generation: Arc<AtomicU64>) -> Self {
+        let snapshot = ron::to_string(simulation).expect("Failed to serialize simulation snapshot");
+        let expected_generation = generation.load(Ordering::Relaxed);
+        let worker_invalidations = invalidations.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Fails if the prediction was thrown away before it finished, in which case nobody wants the result anyway
+            let _ = sender.send(predict_snapshot(&snapshot, &worker_invalidations, &generation, expected_generation));
+        });
+        Self { generation: expected_generation, invalidations, snapshot_time: simulation.time, receiver }
+    }
+}
+
+impl BackgroundPrediction {
+    fn get_generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Re-predicts the entity from the time, along with anything the current worker was going to - which is superseded, since its
+    /// snapshot doesn't have whatever edit this is for
+    pub fn invalidate(&mut self, entity: Entity, time: f64) {
+        let old_generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        let mut invalidations = vec![];
+        if let Some(worker) = &self.worker {
+            if worker.generation == old_generation {
+                invalidations.extend(worker.invalidations.iter().copied());
+            }
+        }
+        invalidations.extend(self.pending.take().unwrap_or_default());
+        invalidations.push((entity, time));
+        self.pending = Some(invalidations);
+    }
+
+    /// Extends every trajectory to its horizon, unless that's already going to happen
+    pub fn extend(&mut self) {
+        if self.worker.is_none() && self.pending.is_none() {
+            self.pending = Some(vec![]);
+        }
+    }
+
+    /// Swaps in the worker's trajectories if it's finished (and not been superseded), then starts on whatever's pending
+    /// Only one snapshot is ever taken per worker, however many edits are made while it's running
+    pub fn poll(&mut self, simulation: &mut Simulation) -> PredictionStatus {
+        if let Some(worker) = &self.worker {
+            match worker.receiver.try_recv() {
+                Err(TryRecvError::Empty) => return PredictionStatus::Running,
+                Ok(predicted) => {
+                    let worker = self.worker.take().unwrap();
+                    if let Some(predicted) = predicted {
+                        if worker.generation == self.get_generation() {
+                            swap_in(simulation, &predicted, worker.snapshot_time);
+                        }
+                    }
+                }
+                Err(TryRecvError::Disconnected) => {
+                    let worker = self.worker.take().unwrap();
+                    // Otherwise whatever it was predicting is pending anyway
+                    if worker.generation == self.get_generation() {
+                        for (entity, time) in worker.invalidations {
+                            simulation.invalidate_prediction(entity, f64::max(time, simulation.time));
+                        }
+                        simulation.predict();
+                        return PredictionStatus::Failed;
+                    }
+                }
+            }
+        }
+        if let Some(invalidations) = self.pending.take() {
+            self.worker = Some(Worker::start(simulation, invalidations, self.generation.clone()));
+            return PredictionStatus::Running;
+        }
+        PredictionStatus::Idle
+    }
+}
+
+impl Drop for BackgroundPrediction {
+    /// Stops the worker (eg when a save is loaded) rather than leaving it to finish a prediction nobody wants
+    fn drop(&mut self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{scenario::{Scenario, DEFAULT_SCENARIO}, components::trajectory_component::manoeuvre::Manoeuvre, systems::trajectory_prediction_system::horizon::{extend_predictions, invalidate_prediction}};
+
+    use super::*;
+
+    fn default_simulation() -> (Simulation, Entity) {
+        let mut simulation = Simulation::new();
+        let spacecraft = Scenario::load(DEFAULT_SCENARIO).unwrap().build(&mut simulation).unwrap();
+        (simulation, spacecraft)
+    }
+
+    fn wait_for(background_prediction: &mut BackgroundPrediction, simulation: &mut Simulation) {
+        let start = Instant::now();
+        loop {
+            match background_prediction.poll(simulation) {
+                PredictionStatus::Idle => return,
+                PredictionStatus::Running => (),
+                PredictionStatus::Failed => panic!("Background prediction failed"),
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "Background prediction never finished");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn get_position(simulation: &Simulation, entity: Entity) -> nalgebra_glm::DVec2 {
+        simulation.components.position_components.get(&entity).unwrap().get_absolute_position()
+    }
+
+    #[test]
+    fn test_matches_main_thread() {
+        let (mut simulation, spacecraft) = default_simulation();
+        let (mut expected, _) = default_simulation();
+        extend_predictions(&mut expected, 0.0);
+        let mut background_prediction = BackgroundPrediction::default();
+        background_prediction.extend();
+        wait_for(&mut background_prediction, &mut simulation);
+        for entity in get_entities_with_trajectories(&simulation) {
+            assert_eq!(describe_trajectory(&simulation, entity), describe_trajectory(&expected, entity));
+        }
+
+        let manoeuvre = Manoeuvre { time: 5000.0, tangent_dv: 500.0, normal_dv: 0.0 };
+        for simulation in [&mut simulation, &mut expected] {
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(manoeuvre);
+        }
+        invalidate_prediction(&mut expected, spacecraft, 5000.0);
+        let old_trajectory = describe_trajectory(&simulation, spacecraft);
+        let old_version = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_version();
+        background_prediction.invalidate(spacecraft, 5000.0);
+        background_prediction.poll(&mut simulation);
+        // The old trajectory is kept until the new one is swapped in
+        assert_eq!(describe_trajectory(&simulation, spacecraft), old_trajectory);
+        wait_for(&mut background_prediction, &mut simulation);
+        assert_eq!(describe_trajectory(&simulation, spacecraft), describe_trajectory(&expected, spacecraft));
+        assert_eq!(get_position(&simulation, spacecraft), get_position(&expected, spacecraft));
+        assert_ne!(simulation.components.trajectory_components.get(&spacecraft).unwrap().get_version(), old_version);
+    }
+
+    #[test]
+    fn test_edited_while_predicting() {
+        let (mut simulation, spacecraft) = default_simulation();
+        let (mut expected, _) = default_simulation();
+        extend_predictions(&mut simulation, 0.0);
+        extend_predictions(&mut expected, 0.0);
+        let trajectory_component = simulation.components.trajectory_components.get_mut(&spacecraft).unwrap();
+        let id = trajectory_component.add_manoeuvre(Manoeuvre { time: 5000.0, tangent_dv: 500.0, normal_dv: 0.0 });
+        let mut background_prediction = BackgroundPrediction::default();
+        background_prediction.invalidate(spacecraft, 5000.0);
+        background_prediction.poll(&mut simulation);
+
+        // The worker that's already running is superseded, so the first burn is never swapped in
+        let manoeuvre = Manoeuvre { time: 5000.0, tangent_dv: 100.0, normal_dv: 0.0 };
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().set_manoeuvre(id, manoeuvre);
+        background_prediction.invalidate(spacecraft, 5000.0);
+        expected.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(manoeuvre);
+        invalidate_prediction(&mut expected, spacecraft, 5000.0);
+        wait_for(&mut background_prediction, &mut simulation);
+        assert_eq!(describe_trajectory(&simulation, spacecraft), describe_trajectory(&expected, spacecraft));
+    }
+
+    #[test]
+    fn test_time_moves_on_while_predicting() {
+        let (mut simulation, spacecraft) = default_simulation();
+        let (mut expected, _) = default_simulation();
+        extend_predictions(&mut simulation, 0.0);
+        extend_predictions(&mut expected, 0.0);
+        let manoeuvre = Manoeuvre { time: 1000.0, tangent_dv: 500.0, normal_dv: 0.0 };
+        for simulation in [&mut simulation, &mut expected] {
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(manoeuvre);
+        }
+        invalidate_prediction(&mut expected, spacecraft, 1000.0);
+        let mut background_prediction = BackgroundPrediction::default();
+        background_prediction.invalidate(spacecraft, 1000.0);
+        background_prediction.poll(&mut simulation);
+
+        // Partway through the burn, which the old trajectory doesn't have
+        let burn = &expected.components.trajectory_components.get(&spacecraft).unwrap().get_segments()[1];
+        let delta_time = (burn.get_start_time() + burn.get_end_time()) / 2.0;
+        simulation.update(delta_time);
+        expected.update(delta_time);
+        wait_for(&mut background_prediction, &mut simulation);
+        assert!((get_position(&simulation, spacecraft) - get_position(&expected, spacecraft)).magnitude() < 1.0e-3);
+        let mass = simulation.components.mass_components.get(&spacecraft).unwrap().get_mass();
+        assert_eq!(mass, expected.components.mass_components.get(&spacecraft).unwrap().get_mass());
+        assert!(simulation.components.trajectory_components.get(&spacecraft).unwrap().get_manoeuvres().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+//! Compares re-predicting everything from the current time after a burn is edited, with only re-predicting the spacecraft that
+//! owns the burn from the start of the burn
+//! Run with `cargo test --release benchmark -- --ignored --nocapture`
+
+use std::time::{Duration, Instant};
+
+use crate::{scenario::{Scenario, DEFAULT_SCENARIO}, simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::{manoeuvre::Manoeuvre}, systems::util::is_spacecraft_with_trajectory};
+
+use super::{celestial_body_prediction::predict_celestial_bodies, spacecraft_prediction::predict_spacecraft, horizon::{extend_predictions, get_prediction_end_time, invalidate_prediction}};
+
+const ITERATIONS: u32 = 50;
+/// Halfway through the default horizon
+const BURN_TIME: f64 = 5.0e6;
+
+fn default_simulation() -> (Simulation, Entity) {
+    let mut simulation = Simulation::new();
+    let spacecraft = Scenario::load(DEFAULT_SCENARIO).unwrap().build(&mut simulation).unwrap();
+    extend_predictions(&mut simulation, 0.0);
+    (simulation, spacecraft)
+}
+
+fn set_burn(simulation: &mut Simulation, spacecraft: Entity, tangent_dv: f64) {
+    let manoeuvre = Manoeuvre { time: BURN_TIME, tangent_dv, normal_dv: 0.0 };
+    simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(manoeuvre);
+}
+
+/// What an edit would cost without knowing what depends on what, or which part of the trajectory the edit affects
+fn repredict_everything(simulation: &mut Simulation) {
+    let time = simulation.time;
+    let entities = simulation.components.entity_allocator.get_entities();
+    for entity in &entities {
+        if let Some(trajectory_component) = simulation.components.trajectory_components.get_mut(entity) {
+            trajectory_component.remove_segments_after(time);
+        }
+    }
+    let end_time = entities.iter()
+        .filter(|entity| simulation.components.trajectory_components.get(entity).is_some())
+        .map(|entity| get_prediction_end_time(simulation, *entity))
+        .fold(f64::MIN, f64::max);
+    predict_celestial_bodies(simulation, end_time);
+    for entity in entities {
+        if is_spacecraft_with_trajectory(simulation, entity) {
+            predict_spacecraft(simulation, entity, time, get_prediction_end_time(simulation, entity));
+        }
+    }
+}
+
+/// Alternates between two burns, so every iteration actually changes the trajectory
+fn time_edits(edit: impl Fn(&mut Simulation, Entity)) -> Duration {
+    let (mut simulation, spacecraft) = default_simulation();
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        set_burn(&mut simulation, spacecraft, if i % 2 == 0 { 500.0 } else { 600.0 });
+        edit(&mut simulation, spacecraft);
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn assert_trajectories_match(a: &Simulation, b: &Simulation, entity: Entity) {
+    let (segments_a, segments_b) = (a.components.trajectory_components.get(&entity).unwrap().get_segments(), b.components.trajectory_components.get(&entity).unwrap().get_segments());
+    assert_eq!(segments_a.len(), segments_b.len());
+    for (segment_a, segment_b) in segments_a.iter().zip(segments_b) {
+        assert_eq!(segment_a.get_parent(), segment_b.get_parent());
+        // SOI crossings are only narrowed down so far, and the search steps differ depending on where it starts
+        assert!((segment_a.get_start_time() - segment_b.get_start_time()).abs() < 1.0e-3);
+        assert!((segment_a.get_end_time() - segment_b.get_end_time()).abs() < 1.0e-3);
+    }
+}
+
+#[test]
+fn test_incremental_matches_everything() {
+    let (mut incremental, spacecraft) = default_simulation();
+    let (mut everything, _) = default_simulation();
+    for simulation in [&mut incremental, &mut everything] {
+        set_burn(simulation, spacecraft, 500.0);
+    }
+    invalidate_prediction(&mut incremental, spacecraft, BURN_TIME);
+    repredict_everything(&mut everything);
+    for entity in incremental.components.entity_allocator.get_entities() {
+        if incremental.components.trajectory_components.get(&entity).is_some() {
+            assert_trajectories_match(&incremental, &everything, entity);
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn benchmark_burn_edit() {
+    let everything = time_edits(|simulation, _| repredict_everything(simulation));
+    let incremental = time_edits(|simulation, spacecraft| invalidate_prediction(simulation, spacecraft, BURN_TIME));
+    println!("Re-predicting everything: {:?} per edit", everything);
+    println!("Re-predicting the spacecraft: {:?} per edit", incremental);
+    println!("Speedup: {:.1}x", everything.as_secs_f64() / incremental.as_secs_f64());
+    assert!(incremental < everything);
+}
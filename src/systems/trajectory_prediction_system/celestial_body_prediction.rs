@@ -1,68 +1,55 @@
-use eframe::epaint::ahash::{HashSet, HashSetExt};
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, systems::util::{is_celestial_body_with_trajectory, sync_celestial_bodies_to_time}};
 
-use crate::{components::trajectory_component::segment::Segment, state::State, storage::entity_allocator::Entity, systems::util::{update_position_and_velocity, sync_to_trajectory, is_celestial_body_with_trajectory, sync_celestial_bodies_to_time}, util::get_root_entities};
+use super::{soi_crossing::find_next_soi_crossing, util::apply_soi_crossing};
 
-use super::util::update_parent_for_prediction;
-
-const SIMULATION_TIME_STEP: f64 = 40.0;
-
-fn get_sphere_of_influence(state: &State, entity: &Entity) -> Option<f64> {
-    let trajectory = state.components.trajectory_components.get(entity)?;
-    let final_segment = trajectory.get_final_segment();
-    let final_orbit = final_segment.as_orbit();
-    let final_parent = final_orbit.borrow().get_parent();
-    let semi_major_axis = final_orbit.borrow().get_semi_major_axis();
-    let mass = state.components.mass_components.get(entity)?.get_mass();
-    let parent_mass = state.components.mass_components.get(&final_parent)?.get_mass();
-    Some(semi_major_axis * (mass / parent_mass).powf(2.0 / 5.0))
+fn get_celestial_bodies(simulation: &Simulation) -> Vec<Entity> {
+    simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter(|entity| is_celestial_body_with_trajectory(simulation, *entity))
+        .collect()
 }
 
-fn update_children(state: &mut State, entity: Entity, time: f64, updated_entities: &mut HashSet<Entity>) {
-    if let Some(celestial_body_component) = state.components.celestial_body_components.get(&entity) {
-        for child in celestial_body_component.get_children().clone() {
-            // We need to keep track of which children have been updated... but why? Isn't this just recursively iterating all children?
-            // Well, yes, but the children may actually change while this is in progress
-            // So there is a feasible scenario where an object could move down into the tree and thus be iterated over twice
-            if !is_celestial_body_with_trajectory(state, child) || updated_entities.contains(&child) {
-                continue;
-            }
-            updated_entities.insert(child);
-            update_for_prediction(state, child, time);
-            update_children(state, child, time, updated_entities);
+/// Celestial bodies never burn, so their trajectories always end with an orbit
+fn extend_orbits(simulation: &mut Simulation, entities: &[Entity], end_time: f64) {
+    for entity in entities {
+        let trajectory_component = simulation.components.trajectory_components.get_mut(entity).unwrap();
+        let final_time = trajectory_component.get_final_segment().get_end_time();
+        if final_time < end_time {
+            trajectory_component.predict(end_time - final_time);
         }
     }
 }
 
-fn update_for_prediction(state: &mut State, entity: Entity, time: f64) {
-    let trajectory_component = state.components.trajectory_components.get_mut(&entity).unwrap();
-    trajectory_component.predict(SIMULATION_TIME_STEP);
-    match trajectory_component.get_final_segment() {
-        Segment::Burn(_) => {
-            // We should never encounter this situation
-            panic!("Attempt to update a burn segment for celestial body prediction")
-        }
-        Segment::Orbit(orbit) => {
-            let new_position = orbit.borrow().get_end_position();
-            let new_velocity = orbit.borrow().get_end_velocity();
-            update_position_and_velocity(state, &entity, new_position, new_velocity);
-        }
-    }
-    update_parent_for_prediction(state, Box::new(get_sphere_of_influence), entity, time, None);
-}
-
-pub fn predict_celestial_bodies(state: &mut State, end_time: f64) {
-    let mut time = state.time;
-    let time_steps = ((end_time - time) / SIMULATION_TIME_STEP) as usize;
-    for _ in 0..time_steps {
-        // Recursively predict all bodies, making sure a parent is updated before its children
-        // If this is not done, strange non-deterministic behaviour seems to arise since
-        // the objects are stored and therefore iterated in a random order
-        for entity in get_root_entities(&state) {
-            update_children(state, entity, time, &mut HashSet::new());
+/// Every body's orbit is extended all the way to end_time, then the earliest SOI change of any body is applied and the rest is
+/// predicted again from there, since the body changing parent could change whether other bodies enter its SOI
+pub fn predict_celestial_bodies(simulation: &mut Simulation, end_time: f64) {
+    let entities = get_celestial_bodies(simulation);
+    // Everything up to the current end of each trajectory has already been checked for SOI changes
+    let mut search_start_times: Vec<f64> = entities.iter()
+        .map(|entity| simulation.components.trajectory_components.get(entity).unwrap().get_final_segment().get_end_time())
+        .collect();
+
+    if search_start_times.iter().any(|start_time| *start_time < end_time) {
+        // Out of date as soon as any body changes SOI, so the bodies' segments are used directly until it's updated afterwards
+        let ephemeris = std::mem::take(&mut simulation.ephemeris);
+        loop {
+            extend_orbits(simulation, &entities, end_time);
+            let crossing = entities.iter()
+                .zip(&search_start_times)
+                .filter_map(|(entity, start_time)| Some((*entity, find_next_soi_crossing(simulation, *entity, *start_time, end_time)?)))
+                .min_by(|(_, a), (_, b)| a.time.total_cmp(&b.time));
+            let Some((entity, crossing)) = crossing else {
+                break;
+            };
+            apply_soi_crossing(simulation, entity, crossing);
+            for start_time in &mut search_start_times {
+                *start_time = f64::max(*start_time, crossing.time);
+            }
         }
-        time += SIMULATION_TIME_STEP;
+        simulation.ephemeris = ephemeris;
+        simulation.update_ephemeris();
     }
 
     // Reset the position, velocity, and parent of all entities, since they are changed during prediction
-    sync_celestial_bodies_to_time(state, state.time);
+    sync_celestial_bodies_to_time(simulation, simulation.time);
 }
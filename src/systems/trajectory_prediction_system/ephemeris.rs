@@ -0,0 +1,215 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use nalgebra_glm::DVec2;
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::segment::orbit::Orbit, systems::util::is_celestial_body_with_trajectory};
+
+/// Samples are this far apart in true anomaly, so they're closest together in time at periapsis, where the body is fastest
+/// Cubic Hermite interpolation is then out by about r * step^4 / 384 for a body at distance r from its parent, which is a few
+/// millimetres for a planet around the sun, and less for anything smaller
+const ANGLE_PER_SAMPLE: f64 = 0.002;
+
+/// Where a celestial body is at a given time, relative to its parent at that time
+#[derive(Debug, Clone, Copy)]
+pub struct EphemerisState {
+    pub parent: Entity,
+    pub position: DVec2,
+    pub velocity: DVec2,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time_since_periapsis: f64,
+    position: DVec2,
+    velocity: DVec2,
+}
+
+impl Sample {
+    /// Going from the angle to the time is much cheaper than the other way round, which needs Kepler's equation solving
+    fn new(orbit: &Orbit, theta: f64, period: f64) -> Self {
+        let time_since_periapsis = orbit.get_time_since_periapsis(theta).rem_euclid(period);
+        Self { time_since_periapsis, position: orbit.get_position_from_theta(theta), velocity: orbit.get_velocity_from_theta(theta) }
+    }
+}
+
+/// Samples of one period of an orbit, which is all that's needed however many times the body goes round
+/// The samples don't depend on when they were taken, so predictions made from a rebuilt ephemeris (eg on the background prediction
+/// thread) come out exactly the same
+#[derive(Debug)]
+struct SampledOrbit {
+    parent: Entity,
+    start_time: f64,
+    end_time: f64,
+    periapsis_time: f64,
+    period: f64,
+    /// Sorted by time since periapsis, from periapsis all the way round to the next one
+    samples: Vec<Sample>,
+}
+
+impl SampledOrbit {
+    /// None for hyperbolic orbits, which don't repeat
+    fn new(orbit: &Orbit) -> Option<Self> {
+        let period = orbit.get_period()?;
+        let periapsis_theta = orbit.get_arugment_of_periapsis();
+        let sample_count = (2.0 * PI / ANGLE_PER_SAMPLE).ceil() as usize;
+        let mut samples: Vec<Sample> = (0..sample_count)
+            .map(|i| Sample::new(orbit, periapsis_theta + i as f64 * ANGLE_PER_SAMPLE, period))
+            .collect();
+        // Otherwise rounding could put periapsis at the end of the period instead of the start
+        samples[0].time_since_periapsis = 0.0;
+        // Sorting means it doesn't matter which way round the orbit goes
+        samples.sort_by(|a, b| a.time_since_periapsis.total_cmp(&b.time_since_periapsis));
+        samples.push(Sample { time_since_periapsis: period, ..samples[0] });
+        Some(Self { parent: orbit.get_parent(), start_time: orbit.get_start_time(), end_time: orbit.get_end_time(), periapsis_time: orbit.get_periapsis_time(), period, samples })
+    }
+
+    /// Whether the samples can be reused for the orbit, which is still the case once it's been extended or trimmed
+    fn is_sample_of(&self, orbit: &Orbit) -> bool {
+        self.parent == orbit.get_parent() && self.start_time == orbit.get_start_time() && self.periapsis_time == orbit.get_periapsis_time() && Some(self.period) == orbit.get_period()
+    }
+
+    fn contains(&self, time: f64) -> bool {
+        time >= self.start_time && time <= self.end_time
+    }
+
+    fn get_state(&self, time: f64) -> EphemerisState {
+        let time_since_periapsis = (time - self.periapsis_time).rem_euclid(self.period);
+        let index = self.samples.partition_point(|sample| sample.time_since_periapsis <= time_since_periapsis).clamp(1, self.samples.len() - 1) - 1;
+        let (a, b) = (&self.samples[index], &self.samples[index + 1]);
+        let h = b.time_since_periapsis - a.time_since_periapsis;
+        let s = (time_since_periapsis - a.time_since_periapsis) / h;
+        let (s2, s3) = (s * s, s * s * s);
+        let position = (2.0 * s3 - 3.0 * s2 + 1.0) * a.position
+            + (s3 - 2.0 * s2 + s) * h * a.velocity
+            + (-2.0 * s3 + 3.0 * s2) * b.position
+            + (s3 - s2) * h * b.velocity;
+        let velocity = (6.0 * s2 - 6.0 * s) / h * (a.position - b.position)
+            + (3.0 * s2 - 4.0 * s + 1.0) * a.velocity
+            + (3.0 * s2 - 2.0 * s) * b.velocity;
+        EphemerisState { parent: self.parent, position, velocity }
+    }
+}
+
+/// Precomputed positions and velocities of every celestial body over its predicted trajectory, which can be interpolated much more
+/// cheaply than solving Kepler's equation for the body every time it's needed (eg at every step of an SOI search, or for every
+/// point of a closest approach search against a body)
+/// Has to be updated whenever a celestial body's trajectory changes - until it is, lookups for that body should fall back to the
+/// body's segments, which is what happens if the body isn't in here at all (as well as for bodies on hyperbolic orbits)
+#[derive(Debug, Default)]
+pub struct Ephemeris {
+    bodies: HashMap<Entity, Vec<SampledOrbit>>,
+}
+
+impl Ephemeris {
+    /// Covers every celestial body from the simulation's current time to the end of its prediction
+    pub fn new(simulation: &Simulation) -> Self {
+        let mut ephemeris = Self::default();
+        ephemeris.update(simulation);
+        ephemeris
+    }
+
+    /// Brings the ephemeris up to date with the bodies' trajectories, only sampling orbits that haven't been sampled already
+    /// Orbits that have been extended or trimmed keep their samples, and ones that are no longer there are dropped
+    pub fn update(&mut self, simulation: &Simulation) {
+        let mut old_bodies = std::mem::take(&mut self.bodies);
+        for entity in simulation.components.entity_allocator.get_entities() {
+            if !is_celestial_body_with_trajectory(simulation, entity) {
+                continue;
+            }
+            let mut old_orbits = old_bodies.remove(&entity).unwrap_or_default();
+            let orbits = simulation.components.trajectory_components.get(&entity).unwrap()
+                .get_segments()
+                .iter()
+                .filter(|segment| segment.get_end_time() >= simulation.time)
+                .filter_map(|segment| {
+                    let orbit = segment.as_orbit().borrow();
+                    match old_orbits.iter().position(|old_orbit| old_orbit.is_sample_of(&orbit)) {
+                        Some(index) => {
+                            let mut sampled_orbit = old_orbits.swap_remove(index);
+                            sampled_orbit.end_time = orbit.get_end_time();
+                            Some(sampled_orbit)
+                        }
+                        None => SampledOrbit::new(&orbit),
+                    }
+                })
+                .collect();
+            self.bodies.insert(entity, orbits);
+        }
+    }
+
+    /// None if the entity isn't a celestial body, or the time is outside what's been sampled
+    pub fn get_state(&self, entity: &Entity, time: f64) -> Option<EphemerisState> {
+        let orbit = self.bodies.get(entity)?.iter().find(|orbit| orbit.contains(time))?;
+        Some(orbit.get_state(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::epaint::Rgba;
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, sun_earth_simulation, get_circular_speed, MOON_DISTANCE, MOON_MASS, MOON_RADIUS}, storage::entity_builder::add_child_celestial_object, systems::{util::get_segment_at_time, trajectory_prediction_system::celestial_body_prediction::predict_celestial_bodies}};
+
+    use super::*;
+
+    const END_TIME: f64 = 1.0e7;
+
+    /// Compares the ephemeris with the body's orbits all the way through its prediction, including at times that aren't sampled
+    fn assert_matches_orbits(simulation: &Simulation, body: Entity, max_position_error: f64, max_velocity_error: f64) {
+        let ephemeris = Ephemeris::new(simulation);
+        for i in 0..=10000 {
+            let time = END_TIME * i as f64 / 10000.0;
+            let state = ephemeris.get_state(&body, time).unwrap();
+            let segment = get_segment_at_time(simulation, &body, time);
+            assert_eq!(state.parent, segment.get_parent());
+            assert!((state.position - segment.get_position_at_time(time)).magnitude() < max_position_error);
+            assert!((state.velocity - segment.get_velocity_at_time(time)).magnitude() < max_velocity_error);
+        }
+    }
+
+    #[test]
+    fn test_matches_orbits() {
+        let (mut simulation, sun, earth) = sun_earth_simulation();
+        let moon_speed = get_circular_speed(&simulation, earth, MOON_DISTANCE);
+        let moon = add_child_celestial_object(&mut simulation.components, 0.0, "moon".to_string(), "moon".to_string(), earth, vec2(MOON_DISTANCE, 0.0), vec2(0.0, moon_speed), MOON_MASS, MOON_RADIUS, Rgba::WHITE);
+        predict_celestial_bodies(&mut simulation, END_TIME);
+
+        assert_matches_orbits(&simulation, earth, 1.0e-2, 1.0e-5);
+        assert_matches_orbits(&simulation, moon, 1.0e-3, 1.0e-6);
+        assert!(Ephemeris::new(&simulation).get_state(&sun, 0.0).is_none());
+        assert!(Ephemeris::new(&simulation).get_state(&moon, END_TIME + 1.0).is_none());
+    }
+
+    /// Samples have to be close enough together to keep up with the body at periapsis
+    #[test]
+    fn test_eccentric_orbit() {
+        let (mut simulation, earth) = earth_simulation();
+        let circular_speed = get_circular_speed(&simulation, earth, MOON_DISTANCE);
+        let moon = add_child_celestial_object(&mut simulation.components, 0.0, "moon".to_string(), "moon".to_string(), earth, vec2(MOON_DISTANCE, 0.0), vec2(0.0, 0.3 * circular_speed), 1.0e20, 1.0e5, Rgba::WHITE);
+        predict_celestial_bodies(&mut simulation, END_TIME);
+
+        assert_matches_orbits(&simulation, moon, 1.0e-2, 1.0e-4);
+    }
+
+    /// Extending the bodies' predictions should give the same ephemeris as sampling everything from scratch, including after
+    /// some of the prediction has been thrown away
+    #[test]
+    fn test_update_matches_new() {
+        let (mut simulation, _, earth) = sun_earth_simulation();
+        predict_celestial_bodies(&mut simulation, END_TIME / 2.0);
+        assert!(simulation.ephemeris.get_state(&earth, END_TIME).is_none());
+
+        predict_celestial_bodies(&mut simulation, END_TIME);
+        let ephemeris = Ephemeris::new(&simulation);
+        for i in 0..=1000 {
+            let time = END_TIME * i as f64 / 1000.0;
+            assert_eq!(simulation.ephemeris.get_state(&earth, time).unwrap().position, ephemeris.get_state(&earth, time).unwrap().position);
+        }
+
+        simulation.components.trajectory_components.get_mut(&earth).unwrap().remove_segments_after(END_TIME / 4.0);
+        simulation.update_ephemeris();
+        assert!(simulation.ephemeris.get_state(&earth, END_TIME / 2.0).is_none());
+        assert!(simulation.ephemeris.get_state(&earth, END_TIME / 8.0).is_some());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::prediction_horizon::PredictionHorizon, systems::util::{is_celestial_body_with_trajectory, is_spacecraft_with_trajectory}};
+
+use super::{celestial_body_prediction::predict_celestial_bodies, soi_crossing::get_body_dependencies, spacecraft_prediction::predict_spacecraft};
+
+/// Nothing is predicted further than this past the current time, whatever the horizon
+/// Horizons that depend on how the trajectory turns out might never be reached (eg SOI changes on an orbit that never leaves its
+/// parent, or orbits of a hyperbola), so this is also where those end - is_horizon_capped tells when that's happened
+pub const MAX_PREDICTION_DURATION: f64 = 1.0e8;
+/// Predictions are only extended once less than this fraction of the horizon is left, rather than a little every frame
+const EXTENSION_THRESHOLD: f64 = 0.5;
+
+fn get_entities(simulation: &Simulation, filter: fn(&Simulation, Entity) -> bool) -> Vec<Entity> {
+    simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter(|entity| filter(simulation, *entity))
+        .collect()
+}
+
+fn get_final_time(simulation: &Simulation, entity: &Entity) -> f64 {
+    simulation.components.trajectory_components.get(entity).unwrap().get_final_segment().get_end_time()
+}
+
+/// Capped at MAX_PREDICTION_DURATION, including when the horizon can't be reached at all
+fn get_target_end_time(simulation: &Simulation, entity: &Entity, time: f64) -> f64 {
+    let max_end_time = time + MAX_PREDICTION_DURATION;
+    match simulation.components.trajectory_components.get(entity).unwrap().get_horizon_end_time(time) {
+        Some(end_time) => f64::min(end_time, max_end_time),
+        None => max_end_time,
+    }
+}
+
+/// How far to predict the entity when re-predicting it from now - outside of tests that's left to invalidate_prediction
+#[cfg(test)]
+pub fn get_prediction_end_time(simulation: &Simulation, entity: Entity) -> f64 {
+    get_target_end_time(simulation, &entity, simulation.time)
+}
+
+/// Whether the entity's prediction stops at MAX_PREDICTION_DURATION rather than its horizon, so the UI can say so
+pub fn is_horizon_capped(simulation: &Simulation, entity: Entity) -> bool {
+    let time = simulation.time;
+    simulation.components.trajectory_components.get(&entity).unwrap()
+        .get_horizon_end_time(time)
+        .is_none_or(|end_time| end_time > time + MAX_PREDICTION_DURATION)
+}
+
+fn needs_extension(final_time: f64, time: f64, target_end_time: f64) -> bool {
+    final_time - time < EXTENSION_THRESHOLD * (target_end_time - time)
+}
+
+fn get_celestial_body_final_time(simulation: &Simulation) -> Option<f64> {
+    get_entities(simulation, is_celestial_body_with_trajectory)
+        .iter()
+        .map(|body| get_final_time(simulation, body))
+        .min_by(f64::total_cmp)
+}
+
+fn get_celestial_body_target_end_time(simulation: &Simulation, time: f64) -> f64 {
+    get_entities(simulation, is_celestial_body_with_trajectory)
+        .iter()
+        .map(|body| get_target_end_time(simulation, body, time))
+        .fold(f64::MIN, f64::max)
+}
+
+/// Bodies are all predicted up to the same time, since SOI changes depend on where the other bodies are
+/// Their horizons are only a minimum - they have to be predicted at least as far as any spacecraft that might pass near them
+fn predict_celestial_bodies_to_horizon(simulation: &mut Simulation, time: f64, force: bool) {
+    let Some(final_time) = get_celestial_body_final_time(simulation) else {
+        return;
+    };
+    let target_end_time = get_celestial_body_target_end_time(simulation, time);
+    if force || needs_extension(final_time, time, target_end_time) {
+        predict_celestial_bodies(simulation, target_end_time);
+    }
+}
+
+/// Carries on from the end of the current prediction, rather than starting again, then trims off anything past the horizon
+/// (which can only be worked out for some horizons once the trajectory has been predicted past it)
+fn predict_spacecraft_to_horizon(simulation: &mut Simulation, entity: Entity, time: f64) {
+    let final_time = get_final_time(simulation, &entity);
+    let target_end_time = get_target_end_time(simulation, &entity, time);
+    if final_time < target_end_time {
+        predict_spacecraft(simulation, entity, final_time, target_end_time);
+    }
+    let trajectory_component = simulation.components.trajectory_components.get_mut(&entity).unwrap();
+    if let Some(end_time) = trajectory_component.get_horizon_end_time(time) {
+        if end_time < trajectory_component.get_final_segment().get_end_time() {
+            trajectory_component.remove_segments_after(end_time);
+        }
+    }
+}
+
+/// Makes sure every trajectory reaches at least as far as time, and extends any that are getting close to the end of what's
+/// been predicted - this is called before each update so time never runs past the end of a trajectory
+pub fn extend_predictions(simulation: &mut Simulation, time: f64) {
+    predict_celestial_bodies_to_horizon(simulation, time, false);
+    for entity in get_entities(simulation, is_spacecraft_with_trajectory) {
+        let final_time = get_final_time(simulation, &entity);
+        if needs_extension(final_time, time, get_target_end_time(simulation, &entity, time)) {
+            predict_spacecraft_to_horizon(simulation, entity, time);
+        }
+    }
+}
+
+/// Whether extend_predictions would do anything, so it only has to be run (eg on a worker thread) when it's needed
+pub fn needs_extending(simulation: &Simulation, time: f64) -> bool {
+    let bodies_need_extending = get_celestial_body_final_time(simulation)
+        .is_some_and(|final_time| needs_extension(final_time, time, get_celestial_body_target_end_time(simulation, time)));
+    bodies_need_extending || get_entities(simulation, is_spacecraft_with_trajectory)
+        .iter()
+        .any(|entity| needs_extension(get_final_time(simulation, entity), time, get_target_end_time(simulation, entity, time)))
+}
+
+/// Predicts anything that would otherwise end before time up to exactly time and no further, which is all that's needed for time
+/// to move on - much cheaper than extend_predictions, for when that's being done somewhere else and hasn't caught up yet
+pub fn extend_predictions_to(simulation: &mut Simulation, time: f64) {
+    if get_celestial_body_final_time(simulation).is_some_and(|final_time| final_time < time) {
+        predict_celestial_bodies(simulation, time);
+    }
+    for entity in get_entities(simulation, is_spacecraft_with_trajectory) {
+        let final_time = get_final_time(simulation, &entity);
+        if final_time < time {
+            predict_spacecraft(simulation, entity, final_time, time);
+        }
+    }
+}
+
+/// Returns the time the entity has to be re-predicted from (with invalidate_prediction) to match the new horizon, which is just
+/// the end of its prediction since nothing before that changes
+pub fn set_prediction_horizon(simulation: &mut Simulation, entity: Entity, horizon: PredictionHorizon) -> f64 {
+    simulation.components.trajectory_components.get_mut(&entity).unwrap().set_horizon(horizon);
+    get_final_time(simulation, &entity)
+}
+
+/// Parents and start times of every segment after time, which is enough to tell if a re-prediction came out differently
+fn describe_trajectory(simulation: &Simulation, entity: &Entity, time: f64) -> Vec<(Entity, f64)> {
+    simulation.components.trajectory_components.get(entity).unwrap().get_segments()
+        .iter()
+        .filter(|segment| segment.get_end_time() > time)
+        .map(|segment| (segment.get_parent(), segment.get_start_time()))
+        .collect()
+}
+
+/// Throws away everything predicted for the entity after time and predicts it again up to its horizon
+/// Nothing depends on a spacecraft's trajectory, so only the spacecraft itself is recomputed
+/// Celestial bodies are cheap to predict and can all affect each other, so a change to one means recomputing all of them - but spacecraft
+/// are only recomputed if their prediction read one of the bodies that's been changed or came out differently
+pub fn invalidate_prediction(simulation: &mut Simulation, entity: Entity, time: f64) {
+    if !is_celestial_body_with_trajectory(simulation, entity) {
+        simulation.components.trajectory_components.get_mut(&entity).unwrap().remove_segments_after(time);
+        predict_spacecraft_to_horizon(simulation, entity, simulation.time);
+        return;
+    }
+    let bodies = get_entities(simulation, is_celestial_body_with_trajectory);
+    let spacecraft = get_entities(simulation, is_spacecraft_with_trajectory);
+    let old_dependencies: Vec<Vec<Entity>> = spacecraft.iter().map(|spacecraft| get_body_dependencies(simulation, *spacecraft, time)).collect();
+    let old_trajectories: Vec<Vec<(Entity, f64)>> = bodies.iter().map(|body| describe_trajectory(simulation, body, time)).collect();
+    for body in &bodies {
+        simulation.components.trajectory_components.get_mut(body).unwrap().remove_segments_after(time);
+    }
+    // Drops whatever was sampled after the time, and the rest is sampled once the bodies have been predicted again
+    simulation.update_ephemeris();
+    predict_celestial_bodies_to_horizon(simulation, simulation.time, true);
+
+    let changed_bodies: Vec<Entity> = bodies.iter()
+        .zip(old_trajectories)
+        .filter(|(body, old_trajectory)| **body == entity || describe_trajectory(simulation, body, time) != *old_trajectory)
+        .map(|(body, _)| *body)
+        .collect();
+    for (spacecraft, old_dependencies) in spacecraft.into_iter().zip(old_dependencies) {
+        // Bodies that have moved into the spacecraft's path count as well as ones that have moved out of it
+        let dependencies = [old_dependencies, get_body_dependencies(simulation, spacecraft, time)].concat();
+        if dependencies.iter().any(|body| changed_bodies.contains(body)) {
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().remove_segments_after(time);
+            predict_spacecraft_to_horizon(simulation, spacecraft, simulation.time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use eframe::epaint::Rgba;
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, earth_moon_simulation, sun_earth_simulation, add_circular_spacecraft, get_circular_speed, EARTH_MASS}, storage::entity_builder::{add_child_celestial_object, add_child_object}, components::{engine_component::EngineComponent, trajectory_component::{manoeuvre::Manoeuvre, segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT}}};
+
+    use super::*;
+
+    fn get_final_parent(simulation: &Simulation, entity: Entity) -> Entity {
+        simulation.components.trajectory_components.get(&entity).unwrap().get_final_segment().get_parent()
+    }
+
+    #[test]
+    fn test_automatic_extension() {
+        let (mut simulation, earth) = earth_simulation();
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 7.0e6, 1.0e3, None);
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().set_horizon(PredictionHorizon::Duration(1.0e4));
+
+        // Updating on its own only predicts as far as it has to
+        simulation.update(1.0e3);
+        assert_eq!(get_final_time(&simulation, &spacecraft), 1.0e3);
+
+        // Time steps bigger than the whole horizon
+        for _ in 0..10 {
+            let time = simulation.time + 3.0e4;
+            assert!(needs_extending(&simulation, time));
+            extend_predictions(&mut simulation, time);
+            simulation.update(3.0e4);
+            let final_time = get_final_time(&simulation, &spacecraft);
+            assert!(final_time - simulation.time >= EXTENSION_THRESHOLD * 1.0e4);
+            assert!(final_time - simulation.time <= 1.0e4 + 3.0e4);
+        }
+        let position = simulation.components.position_components.get(&spacecraft).unwrap().get_absolute_position();
+        assert!((position.magnitude() - 7.0e6).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_horizon_end_times() {
+        let (mut simulation, earth, moon) = earth_moon_simulation();
+        let low_orbit = add_circular_spacecraft(&mut simulation, earth, 7.0e6, 1.0e3, None);
+        // Escapes the moon backwards, so it's left orbiting the earth
+        let escaping = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "escaping".to_string(), moon, vec2(2.0e6, 0.0), vec2(0.0, -2.5e3), 1.0e3, None);
+
+        let period = 2.0 * PI * f64::sqrt(7.0e6_f64.powi(3) / (GRAVITATIONAL_CONSTANT * EARTH_MASS));
+        let time = set_prediction_horizon(&mut simulation, low_orbit, PredictionHorizon::Orbits(2.0));
+        invalidate_prediction(&mut simulation, low_orbit, time);
+        assert!((get_final_time(&simulation, &low_orbit) - 2.0 * period).abs() < 1.0e-6);
+        assert!(!is_horizon_capped(&simulation, low_orbit));
+        // Never leaves the earth, so would be predicted forever if it weren't capped
+        set_prediction_horizon(&mut simulation, low_orbit, PredictionHorizon::SoiChanges(1));
+        assert!(is_horizon_capped(&simulation, low_orbit));
+
+        let time = set_prediction_horizon(&mut simulation, escaping, PredictionHorizon::SoiChanges(1));
+        invalidate_prediction(&mut simulation, escaping, time);
+        let segments = simulation.components.trajectory_components.get(&escaping).unwrap().get_segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(get_final_parent(&simulation, escaping), earth);
+        let earth_orbit = segments[1].as_orbit().borrow();
+        assert!((earth_orbit.get_end_time() - (earth_orbit.get_start_time() + earth_orbit.get_period().unwrap())).abs() < 1.0e-6);
+        drop(earth_orbit);
+
+        // Going back to a duration extends the prediction again
+        let time = set_prediction_horizon(&mut simulation, escaping, PredictionHorizon::Duration(5.0e7));
+        invalidate_prediction(&mut simulation, escaping, time);
+        assert_eq!(get_final_time(&simulation, &escaping), 5.0e7);
+        // And the moon has to be predicted at least as far
+        assert!(get_final_time(&simulation, &moon) >= 5.0e7);
+    }
+
+    #[test]
+    fn test_invalidate_prediction() {
+        let (mut simulation, earth) = earth_simulation();
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 7.0e6, 1.0e3, None);
+        let speed = get_circular_speed(&simulation, earth, 7.0e6);
+        let other = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "other".to_string(), earth, vec2(-7.0e6, 0.0), vec2(0.0, -speed), 1.0e3, None);
+        extend_predictions(&mut simulation, 0.0);
+        let other_orbit = simulation.components.trajectory_components.get(&other).unwrap().get_final_segment().as_orbit().clone();
+
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().set_horizon(PredictionHorizon::Duration(2.0e6));
+        invalidate_prediction(&mut simulation, spacecraft, 1000.0);
+        assert_eq!(get_final_time(&simulation, &spacecraft), 2.0e6);
+        // Nothing else was touched
+        let new_other_orbit = simulation.components.trajectory_components.get(&other).unwrap().get_final_segment().as_orbit().clone();
+        assert!(std::rc::Rc::ptr_eq(&other_orbit, &new_other_orbit));
+    }
+
+    #[test]
+    fn test_invalidate_body() {
+        let (mut simulation, sun, earth) = sun_earth_simulation();
+        let mars_speed = get_circular_speed(&simulation, sun, 2.28e11);
+        let mars = add_child_celestial_object(&mut simulation.components, 0.0, "planet".to_string(), "mars".to_string(), sun, vec2(-2.28e11, 0.0), vec2(0.0, -mars_speed), 6.417e23, 3.39e6, Rgba::WHITE);
+        let engine = EngineComponent::new(2.0e4, 350.0, 4.0e3, 1.0e4);
+        let around_earth = add_circular_spacecraft(&mut simulation, earth, 8.0e6, 1.0e4, Some(engine.clone()));
+        let around_mars = add_circular_spacecraft(&mut simulation, mars, 5.0e6, 1.0e4, Some(engine));
+        extend_predictions(&mut simulation, 0.0);
+        assert_eq!(get_body_dependencies(&simulation, around_earth, 0.0), vec![earth]);
+        assert_eq!(get_body_dependencies(&simulation, around_mars, 0.0), vec![mars]);
+
+        // Manoeuvres that only show up in a trajectory once it's re-predicted
+        for spacecraft in [around_earth, around_mars] {
+            let manoeuvre = Manoeuvre { time: 2000.0, tangent_dv: 100.0, normal_dv: 0.0 };
+            simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(manoeuvre);
+        }
+        invalidate_prediction(&mut simulation, earth, 1000.0);
+        let get_segment_count = |simulation: &Simulation, entity| simulation.components.trajectory_components.get(&entity).unwrap().get_segments().len();
+        assert_eq!(get_segment_count(&simulation, around_earth), 3);
+        assert_eq!(get_segment_count(&simulation, around_mars), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use nalgebra_glm::DVec2;
+
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::segment::Segment, systems::util::is_celestial_body_with_trajectory};
+
+/// The search never steps by less than this, so an SOI that's only clipped for a shorter time than this can be missed
+const MIN_SEARCH_STEP: f64 = 0.1;
+/// Crossing times are narrowed down to within this
+const CROSSING_TOLERANCE: f64 = 1.0e-6;
+const MAX_REFINEMENT_ITERATIONS: usize = 100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SoiCrossing {
+    pub time: f64,
+    pub new_parent: Entity,
+}
+
+/// An SOI boundary the entity could cross, and the parent it would have on the other side
+struct Boundary {
+    radius: f64,
+    new_parent: Entity,
+    /// Leaving the SOI of the current parent, rather than entering the SOI of one of its children
+    leaving: bool,
+    /// The predicted segments of the body whose SOI this is, so they're only looked up once per search rather than at every step
+    /// Only segments around the entity's parent are kept - once the body has left, the entity can't enter its SOI from here
+    body_segments: Vec<Segment>,
+    body_max_speed: f64,
+}
+
+/// Unlike get_segment_at_time, doesn't panic if the time hasn't been predicted
+fn get_segment_containing(simulation: &Simulation, entity: &Entity, time: f64) -> Option<Segment> {
+    simulation.components.trajectory_components.get(entity)?
+        .get_segments()
+        .iter()
+        .find(|segment| time >= segment.get_start_time() && time <= segment.get_end_time())
+        .cloned()
+}
+
+/// None for root entities, which have an infinite SOI
+fn get_sphere_of_influence(simulation: &Simulation, entity: &Entity, time: f64) -> Option<f64> {
+    let segment = get_segment_containing(simulation, entity, time)?;
+    let semi_major_axis = segment.as_orbit().borrow().get_semi_major_axis();
+    let mass = simulation.components.mass_components.get(entity)?.get_mass();
+    let parent_mass = simulation.components.mass_components.get(&segment.get_parent())?.get_mass();
+    Some(semi_major_axis * (mass / parent_mass).powf(2.0 / 5.0))
+}
+
+impl Boundary {
+    fn leaving(radius: f64, new_parent: Entity) -> Self {
+        Self { radius, new_parent, leaving: true, body_segments: vec![], body_max_speed: 0.0 }
+    }
+
+    fn entering(simulation: &Simulation, body: Entity, radius: f64, parent: Entity, start_time: f64, end_time: f64) -> Self {
+        let body_segments: Vec<Segment> = simulation.components.trajectory_components.get(&body).unwrap()
+            .get_segments()
+            .iter()
+            .filter(|segment| segment.get_end_time() >= start_time && segment.get_start_time() <= end_time && segment.get_parent() == parent)
+            .cloned()
+            .collect();
+        let body_max_speed = body_segments.iter().map(Segment::get_max_speed).fold(0.0, f64::max);
+        Self { radius, new_parent: body, leaving: false, body_segments, body_max_speed }
+    }
+
+    /// Distance from the boundary, positive on the side the entity started on
+    /// Position is relative to the entity's current parent
+    /// The body's position comes from the ephemeris where it can, since this is called at every step of the search
+    fn get_margin(&self, simulation: &Simulation, position: DVec2, time: f64) -> f64 {
+        if self.leaving {
+            return self.radius - position.magnitude();
+        }
+        match self.body_segments.iter().find(|segment| time >= segment.get_start_time() && time <= segment.get_end_time()) {
+            Some(segment) => {
+                let body_position = simulation.ephemeris.get_state(&self.new_parent, time).map_or_else(|| segment.get_position_at_time(time), |state| state.position);
+                (position - body_position).magnitude() - self.radius
+            }
+            None => f64::MAX,
+        }
+    }
+
+    /// The margin can't shrink faster than this
+    fn get_max_closing_speed(&self, entity_max_speed: f64) -> f64 {
+        entity_max_speed + self.body_max_speed
+    }
+}
+
+/// The parent's own SOI, plus the SOI of every celestial body orbiting the parent
+fn get_boundaries(simulation: &Simulation, entity: &Entity, parent: Entity, start_time: f64, end_time: f64) -> Vec<Boundary> {
+    let mut boundaries = vec![];
+    let grandparent = get_segment_containing(simulation, &parent, start_time).map(|segment| segment.get_parent());
+    if let (Some(radius), Some(grandparent)) = (get_sphere_of_influence(simulation, &parent, start_time), grandparent) {
+        boundaries.push(Boundary::leaving(radius, grandparent));
+    }
+    for body in simulation.components.entity_allocator.get_entities() {
+        if body == *entity || !is_celestial_body_with_trajectory(simulation, body) {
+            continue;
+        }
+        if get_segment_containing(simulation, &body, start_time).is_some_and(|segment| segment.get_parent() == parent) {
+            if let Some(radius) = get_sphere_of_influence(simulation, &body, start_time) {
+                boundaries.push(Boundary::entering(simulation, body, radius, parent, start_time, end_time));
+            }
+        }
+    }
+    boundaries
+}
+
+fn get_parents_after(simulation: &Simulation, entity: &Entity, time: f64) -> Vec<Entity> {
+    let Some(trajectory_component) = simulation.components.trajectory_components.get(entity) else {
+        return vec![];
+    };
+    trajectory_component.get_segments()
+        .iter()
+        .filter(|segment| segment.get_end_time() > time)
+        .map(Segment::get_parent)
+        .collect()
+}
+
+/// Every celestial body whose trajectory is read when predicting the entity after time - the entity's parents, their own parents
+/// (for leaving their SOIs), and anything else orbiting the same parents (for entering their SOIs), matching get_boundaries
+pub fn get_body_dependencies(simulation: &Simulation, entity: Entity, time: f64) -> Vec<Entity> {
+    let parents = get_parents_after(simulation, &entity, time);
+    simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter(|body| *body != entity && is_celestial_body_with_trajectory(simulation, *body))
+        .filter(|body| parents.contains(body)
+            || get_parents_after(simulation, body, time).iter().any(|parent| parents.contains(parent))
+            || parents.iter().any(|parent| get_parents_after(simulation, parent, time).contains(body)))
+        .collect()
+}
+
+/// Bisection on a bracket where the margin goes from non-negative to negative
+/// Returns the end that's already over the boundary, so the entity starts its new segment inside the SOI it's meant to be in
+fn refine_crossing(start_time: f64, end_time: f64, get_margin: impl Fn(f64) -> f64) -> f64 {
+    let (mut low, mut high) = (start_time, end_time);
+    for _ in 0..MAX_REFINEMENT_ITERATIONS {
+        if high - low < CROSSING_TOLERANCE {
+            break;
+        }
+        let middle = (low + high) / 2.0;
+        if get_margin(middle) < 0.0 {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    high
+}
+
+/// Each step goes as far as it can without any margin being able to reach zero, given how fast the entity and the bodies can move,
+/// so a crossing can't be stepped over - steps are long when nothing is nearby, and get shorter when approaching a boundary
+fn find_crossing_in_segment(simulation: &Simulation, entity: &Entity, segment: &Segment, start_time: f64, end_time: f64) -> Option<SoiCrossing> {
+    let boundaries = get_boundaries(simulation, entity, segment.get_parent(), start_time, end_time);
+    if boundaries.is_empty() {
+        return None;
+    }
+    let entity_max_speed = segment.get_max_speed();
+    let get_margins = |time: f64| {
+        let position = segment.get_position_at_time(time);
+        boundaries.iter().map(|boundary| boundary.get_margin(simulation, position, time)).collect::<Vec<f64>>()
+    };
+
+    let mut time = start_time;
+    let mut margins = get_margins(time);
+    while time < end_time {
+        let step = boundaries.iter()
+            .zip(&margins)
+            .map(|(boundary, margin)| margin / boundary.get_max_closing_speed(entity_max_speed))
+            .fold(f64::MAX, f64::min)
+            .max(MIN_SEARCH_STEP);
+        let next_time = f64::min(time + step, end_time);
+        let next_margins = get_margins(next_time);
+        let crossing = boundaries.iter()
+            .enumerate()
+            .filter(|(i, _)| margins[*i] >= 0.0 && next_margins[*i] < 0.0)
+            .map(|(_, boundary)| {
+                let crossing_time = refine_crossing(time, next_time, |time| boundary.get_margin(simulation, segment.get_position_at_time(time), time));
+                SoiCrossing { time: crossing_time, new_parent: boundary.new_parent }
+            })
+            .min_by(|a, b| a.time.total_cmp(&b.time));
+        if crossing.is_some() {
+            return crossing;
+        }
+        time = next_time;
+        margins = next_margins;
+    }
+    None
+}
+
+/// The first time after start_time (and no later than end_time) that the entity's predicted trajectory leaves its parent's SOI or enters
+/// the SOI of one of its parent's children - anything orbiting the entity's parent has to already be predicted up to end_time
+pub fn find_next_soi_crossing(simulation: &Simulation, entity: Entity, start_time: f64, end_time: f64) -> Option<SoiCrossing> {
+    for segment in simulation.components.trajectory_components.get(&entity).unwrap().get_segments() {
+        if segment.get_end_time() <= start_time {
+            continue;
+        }
+        if segment.get_start_time() >= end_time {
+            break;
+        }
+        let segment_start_time = f64::max(start_time, segment.get_start_time());
+        let segment_end_time = f64::min(end_time, segment.get_end_time());
+        if let Some(crossing) = find_crossing_in_segment(simulation, &entity, segment, segment_start_time, segment_end_time) {
+            return Some(crossing);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use eframe::epaint::Rgba;
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, earth_moon_simulation, get_circular_speed, MOON_DISTANCE}, storage::entity_builder::{add_child_celestial_object, add_child_object}};
+
+    use super::*;
+
+    fn get_parents(simulation: &Simulation, entity: Entity) -> Vec<Entity> {
+        simulation.components.trajectory_components.get(&entity).unwrap().get_segments().iter().map(|segment| segment.get_parent()).collect()
+    }
+
+    #[test]
+    fn test_crossing_on_boundary() {
+        let (mut simulation, earth, moon) = earth_moon_simulation();
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), moon, vec2(2.0e6, 0.0), vec2(0.0, 3.0e3), 1.0e3, None);
+        simulation.predict_until(1.0e6);
+
+        assert_eq!(get_parents(&simulation, spacecraft), vec![moon, earth]);
+        let segments = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_segments();
+        let sphere_of_influence = get_sphere_of_influence(&simulation, &moon, 0.0).unwrap();
+        let exit_time = segments[0].get_end_time();
+        assert!((segments[0].get_position_at_time(exit_time).magnitude() - sphere_of_influence).abs() < 1.0);
+        assert_eq!(segments[1].get_start_time(), exit_time);
+        assert_eq!(segments.back().unwrap().get_end_time(), 1.0e6);
+    }
+
+    /// Goes straight through an SOI so small that it's inside it for less than a 40 second step
+    #[test]
+    fn test_short_flyby() {
+        let (mut simulation, earth) = earth_simulation();
+        let moon_speed = get_circular_speed(&simulation, earth, MOON_DISTANCE);
+        let moon = add_child_celestial_object(&mut simulation.components, 0.0, "moon".to_string(), "moon".to_string(), earth, vec2(MOON_DISTANCE, 0.0), vec2(0.0, moon_speed), 1.0e15, 1.0e3, Rgba::WHITE);
+        // Catches up with the moon from behind at about 9 km/s, passing 20 km to one side of it
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), earth, vec2(MOON_DISTANCE + 2.0e4, -1.0e6), vec2(0.0, 1.0e4), 1.0e3, None);
+        simulation.predict_until(1000.0);
+
+        assert_eq!(get_parents(&simulation, spacecraft), vec![earth, moon, earth]);
+        let segments = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_segments();
+        let sphere_of_influence = get_sphere_of_influence(&simulation, &moon, 0.0).unwrap();
+        let flyby = &segments[1];
+        assert!(flyby.get_end_time() - flyby.get_start_time() < 40.0);
+        for time in [flyby.get_start_time(), flyby.get_end_time()] {
+            assert!((flyby.get_position_at_time(time).magnitude() - sphere_of_influence).abs() < 1.0e-2);
+        }
+    }
+}
@@ -1,84 +1,229 @@
-use crate::{state::State, systems::util::{update_position_and_velocity, get_segment_at_time, is_spacecraft_with_trajectory, sync_celestial_bodies_to_time, sync_entity_to_time, update_parent}, storage::entity_allocator::Entity, components::trajectory_component::segment::Segment};
+use std::{cell::RefCell, rc::Rc};
 
-use super::util::{SoiFunction, update_parent_for_prediction};
+use crate::{simulation::Simulation, systems::util::sync_entity_to_time, storage::entity_allocator::Entity, components::trajectory_component::{TrajectoryComponent, manoeuvre::{Manoeuvre, ManoeuvreId}, segment::{Segment, burn::Burn, orbit::Orbit}}};
 
-const SIMULATION_TIME_STEP: f64 = 40.0;
+use super::{celestial_body_prediction::predict_celestial_bodies, soi_crossing::find_next_soi_crossing, util::apply_soi_crossing};
 
-fn make_soi_function(time: f64) -> SoiFunction {
-    Box::new(move |state: &State, entity: &Entity| {
-        if state.components.trajectory_components.get(entity).is_none() {
-            // Root entity, so SOI infinite
-            return Some(f64::MAX);
-        }
-        let segment = get_segment_at_time(state, entity, time);
-        let orbit = segment.as_orbit();
-        let parent = orbit.borrow().get_parent();
-        let semi_major_axis = orbit.borrow().get_semi_major_axis();
-        let mass = state.components.mass_components.get(entity)?.get_mass();
-        let parent_mass = state.components.mass_components.get(&parent)?.get_mass();
-        Some(semi_major_axis * (mass / parent_mass).powf(2.0 / 5.0))
-    })
+/// Once a burn reaches its end, the trajectory carries on with an orbit from the end of the burn
+fn add_orbit_after_burn(simulation: &mut Simulation, entity: Entity, burn: &Burn) {
+    let orbit = Orbit::new(&simulation.components, burn.get_parent(), burn.get_end_position(), burn.get_end_velocity(), burn.get_end_time());
+    simulation.components.trajectory_components.get_mut(&entity).unwrap().add_segment(Segment::Orbit(Rc::new(RefCell::new(orbit))));
 }
 
-fn update_for_prediction(state: &mut State, entity: Entity, time: f64) {
-    let trajectory_component = state.components.trajectory_components.get_mut(&entity).unwrap();
-    trajectory_component.predict(SIMULATION_TIME_STEP);
-    let x;
-    match trajectory_component.get_final_segment() {
-        Segment::Burn(_) => {
-            todo!()
-        }
-        Segment::Orbit(orbit) => {
-            let new_parent = orbit.borrow().get_parent();
-            update_parent(state, entity, &new_parent);
-            let new_position = orbit.borrow().get_end_position();
-            let new_velocity = orbit.borrow().get_end_velocity();
-            update_position_and_velocity(state, &entity, new_position, new_velocity);
-            x = orbit.borrow().get_end_position();
+/// The orbit must already have been predicted up to the start time
+fn start_manoeuvre(simulation: &mut Simulation, entity: Entity, orbit: &Orbit, id: ManoeuvreId, manoeuvre: Manoeuvre, start_time: f64) {
+    let parent = orbit.get_parent();
+    let tangent_direction = orbit.get_end_velocity().normalize();
+    let burn = Burn::new(simulation, entity, parent, tangent_direction, id, manoeuvre, start_time);
+    simulation.components.trajectory_components.get_mut(&entity).unwrap().add_segment(Segment::Burn(Rc::new(RefCell::new(burn))));
+}
+
+/// The next manoeuvre without a burn that can be started from the end of the orbit, and when it starts
+/// A manoeuvre from before the start of the orbit was during the burn that the orbit follows, so it starts as soon as the orbit does,
+/// since the spacecraft can't do two burns at once - but one from during the orbit must have been added since the orbit was predicted,
+/// and is left for when the trajectory's re-predicted from its time
+fn get_next_manoeuvre(trajectory_component: &TrajectoryComponent, orbit: &Orbit) -> Option<(ManoeuvreId, Manoeuvre, f64)> {
+    let (start_time, end_time) = (orbit.get_start_time(), orbit.get_end_time());
+    trajectory_component.get_manoeuvres().iter()
+        .filter(|(id, _)| trajectory_component.get_manoeuvre_burn(*id).is_none())
+        .find(|(_, manoeuvre)| manoeuvre.time <= start_time || manoeuvre.time >= end_time)
+        .map(|(id, manoeuvre)| (*id, *manoeuvre, f64::max(manoeuvre.time, end_time)))
+}
+
+/// Brings the end of the trajectory up to new_time, starting any manoeuvres and finishing any burns along the way
+/// Several of these can happen in one step, eg a short burn followed closely by another manoeuvre
+fn extend_trajectory(simulation: &mut Simulation, entity: Entity, new_time: f64) {
+    loop {
+        let trajectory_component = simulation.components.trajectory_components.get(&entity).unwrap();
+        match trajectory_component.get_final_segment() {
+            Segment::Burn(burn) => {
+                if burn.borrow().get_end_time() > new_time {
+                    return;
+                }
+                add_orbit_after_burn(simulation, entity, &burn.borrow());
+            }
+            Segment::Orbit(orbit) => {
+                let end_time = orbit.borrow().get_end_time();
+                let next_manoeuvre = get_next_manoeuvre(trajectory_component, &orbit.borrow());
+                let Some((id, manoeuvre, start_time)) = next_manoeuvre.filter(|(_, _, start_time)| *start_time < new_time) else {
+                    orbit.borrow_mut().predict(new_time - end_time);
+                    return;
+                };
+                orbit.borrow_mut().predict(start_time - end_time);
+                start_manoeuvre(simulation, entity, &orbit.borrow(), id, manoeuvre, start_time);
+            }
         }
     }
-    update_parent_for_prediction(state, make_soi_function(time + SIMULATION_TIME_STEP), entity, time + SIMULATION_TIME_STEP, Some(x));
 }
 
-pub fn predict_spacecraft(state: &mut State, entity: Entity, start_time: f64, end_time: f64) {
+/// Extends the trajectory all the way to end_time and then looks for the first SOI change, starting again from there whenever one is found
+/// Everything after the change is thrown away, since it was predicted relative to the wrong parent
+fn predict(simulation: &mut Simulation, entity: Entity, start_time: f64, end_time: f64) {
+    simulation.components.trajectory_components.get_mut(&entity).unwrap().remove_segments_after(start_time);
     let mut time = start_time;
-    let time_steps = ((end_time - start_time) / SIMULATION_TIME_STEP) as usize;
+    loop {
+        extend_trajectory(simulation, entity, end_time);
+        let Some(crossing) = find_next_soi_crossing(simulation, entity, time, end_time) else {
+            return;
+        };
+        apply_soi_crossing(simulation, entity, crossing);
+        time = crossing.time;
+    }
+}
+
+/// The spacecraft's prediction only reads the celestial bodies' predictions, so they're extended first if they don't reach end_time,
+/// and nothing but the spacecraft itself needs syncing afterwards
+pub fn predict_spacecraft(simulation: &mut Simulation, entity: Entity, start_time: f64, end_time: f64) {
+    predict_celestial_bodies(simulation, end_time);
+    predict(simulation, entity, start_time, end_time);
+    sync_entity_to_time(simulation, entity, simulation.time);
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{sun_earth_simulation, add_circular_spacecraft}, storage::entity_builder::add_child_object, components::engine_component::EngineComponent, systems::util::get_segment_at_time};
+
+    use super::*;
+
+    #[test]
+    fn test_burn_leaves_soi() {
+        let (mut simulation, sun, earth) = sun_earth_simulation();
+        // A weak ion engine already heading out of the earth's SOI, so the burn is still going when it leaves
+        let engine = EngineComponent::new(10.0, 3000.0, 900.0, 1000.0);
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), earth, vec2(8.5e8, 0.0), vec2(1000.0, 800.0), 1000.0, Some(engine));
+        simulation.predict_until(300000.0);
+
+        let manoeuvre = Manoeuvre { time: 0.0, tangent_dv: 1000.0, normal_dv: 0.0 };
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(manoeuvre);
+        predict_spacecraft(&mut simulation, spacecraft, 0.0, 300000.0);
 
-    state.components.trajectory_components.get_mut(&entity).unwrap().remove_segments_after(time);
+        let segments = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_segments().clone();
+        let burns: Vec<_> = segments.iter().filter_map(|segment| match segment {
+            Segment::Burn(burn) => Some(burn.clone()),
+            Segment::Orbit(_) => None,
+        }).collect();
+        assert_eq!(burns.len(), 2);
+        let (first, second) = (burns[0].borrow(), burns[1].borrow());
+        assert_eq!(first.get_parent(), earth);
+        assert_eq!(second.get_parent(), sun);
+        assert_eq!(first.get_end_time(), second.get_start_time());
+        assert_eq!(first.get_end_mass(), second.get_start_mass());
+        assert!((first.get_total_dv() + second.get_total_dv() - 1000.0).abs() < 1.0e-6);
 
-    for _ in 0..time_steps {
-        sync_celestial_bodies_to_time(state, time);
-        update_for_prediction(state, entity, time);
-        time += SIMULATION_TIME_STEP;
+        // The split shouldn't move the spacecraft, just change the frame it's described in
+        let split_time = second.get_start_time();
+        let earth_position = get_segment_at_time(&simulation, &earth, split_time).get_position_at_time(split_time);
+        let earth_velocity = get_segment_at_time(&simulation, &earth, split_time).get_velocity_at_time(split_time);
+        assert!((first.get_end_position() + earth_position - second.get_start_position()).magnitude() < 1.0);
+        assert!((first.get_end_velocity() + earth_velocity - second.get_start_velocity()).magnitude() < 1.0e-3);
+
+        // And once the burn is done, the spacecraft coasts around the sun
+        let Segment::Orbit(orbit) = segments.back().unwrap() else { panic!("Trajectory should end with an orbit") };
+        assert_eq!(orbit.borrow().get_parent(), sun);
+        assert_eq!(orbit.borrow().get_start_time(), second.get_end_time());
     }
 
-    sync_entity_to_time(state, entity, state.time);
-    sync_celestial_bodies_to_time(state, state.time);
-}
+    /// Sun, earth and a spacecraft in a low circular orbit, with everything predicted up to end_time
+    fn orbiting_simulation(end_time: f64) -> (Simulation, Entity) {
+        let (mut simulation, _, earth) = sun_earth_simulation();
+        let engine = EngineComponent::new(2.0e4, 350.0, 4.0e3, 1.0e4);
+        let spacecraft = add_circular_spacecraft(&mut simulation, earth, 8.0e6, 1.0e4, Some(engine));
+        simulation.predict_until(end_time);
+        (simulation, spacecraft)
+    }
 
-pub fn predict_all_spacecraft(state: &mut State, end_time: f64) {
-    let mut time = state.time;
-    let time_steps = ((end_time - time) / SIMULATION_TIME_STEP) as usize;
+    fn get_burns(simulation: &Simulation, entity: Entity) -> Vec<Rc<RefCell<Burn>>> {
+        simulation.components.trajectory_components.get(&entity).unwrap().get_segments().iter().filter_map(|segment| match segment {
+            Segment::Burn(burn) => Some(burn.clone()),
+            Segment::Orbit(_) => None,
+        }).collect()
+    }
 
-    for entity in state.components.entity_allocator.get_entities() {
-        if is_spacecraft_with_trajectory(state, entity) {
-            state.components.trajectory_components.get_mut(&entity).unwrap().remove_segments_after(time)
+    /// Every segment should start where the previous one ended
+    fn assert_segments_continuous(simulation: &Simulation, entity: Entity) {
+        let segments = simulation.components.trajectory_components.get(&entity).unwrap().get_segments();
+        for (previous, next) in segments.iter().zip(segments.iter().skip(1)) {
+            assert_eq!(previous.get_end_time(), next.get_start_time());
         }
     }
 
-    for _ in 0..time_steps {
-        sync_celestial_bodies_to_time(state, time + SIMULATION_TIME_STEP);
-        for entity in state.components.entity_allocator.get_entities() {
-            if is_spacecraft_with_trajectory(state, entity) {
-                update_for_prediction(state, entity, time);
-            }
-        }
-        time += SIMULATION_TIME_STEP;
+    #[test]
+    fn test_editing_earlier_manoeuvre() {
+        let end_time = 20000.0;
+        let (mut simulation, spacecraft) = orbiting_simulation(end_time);
+        let trajectory_component = simulation.components.trajectory_components.get_mut(&spacecraft).unwrap();
+        let first = trajectory_component.add_manoeuvre(Manoeuvre { time: 1000.0, tangent_dv: 100.0, normal_dv: 0.0 });
+        trajectory_component.add_manoeuvre(Manoeuvre { time: 5000.0, tangent_dv: 100.0, normal_dv: 0.0 });
+        predict_spacecraft(&mut simulation, spacecraft, 1000.0, end_time);
+
+        let burns = get_burns(&simulation, spacecraft);
+        assert_eq!(burns.len(), 2);
+        assert_eq!(burns[0].borrow().get_start_time(), 1000.0);
+        assert_eq!(burns[1].borrow().get_start_time(), 5000.0);
+        assert_eq!(burns[1].borrow().get_start_mass(), burns[0].borrow().get_end_mass());
+        assert_segments_continuous(&simulation, spacecraft);
+        let old_second_burn_position = burns[1].borrow().get_start_position();
+
+        // Replacing the first manoeuvre should change where the second burn happens, as well as the mass it starts with
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().set_manoeuvre(first, Manoeuvre { time: 1000.0, tangent_dv: 300.0, normal_dv: 0.0 });
+        predict_spacecraft(&mut simulation, spacecraft, 1000.0, end_time);
+
+        let burns = get_burns(&simulation, spacecraft);
+        assert_eq!(burns.len(), 2);
+        assert!((burns[0].borrow().get_total_dv() - 300.0).abs() < 1.0e-9);
+        assert_eq!(burns[1].borrow().get_start_time(), 5000.0);
+        assert_eq!(burns[1].borrow().get_start_mass(), burns[0].borrow().get_end_mass());
+        assert!((burns[1].borrow().get_start_position() - old_second_burn_position).magnitude() > 1.0e5);
+        assert_segments_continuous(&simulation, spacecraft);
     }
 
-    for entity in state.components.entity_allocator.get_entities() {
-        if state.components.trajectory_components.get(&entity).is_some() {
-            sync_entity_to_time(state, entity, state.time);
-        }
+    #[test]
+    fn test_predict_during_burn() {
+        let end_time = 20000.0;
+        let (mut simulation, spacecraft) = orbiting_simulation(end_time);
+        let trajectory_component = simulation.components.trajectory_components.get_mut(&spacecraft).unwrap();
+        trajectory_component.add_manoeuvre(Manoeuvre { time: 1000.0, tangent_dv: 500.0, normal_dv: 0.0 });
+        trajectory_component.add_manoeuvre(Manoeuvre { time: 5000.0, tangent_dv: 100.0, normal_dv: 0.0 });
+        predict_spacecraft(&mut simulation, spacecraft, 1000.0, end_time);
+        let first_burn_end_time = get_burns(&simulation, spacecraft)[0].borrow().get_end_time();
+
+        // Halfway through the first burn, which has to be kept as it is rather than spliced
+        simulation.update(1000.0 + (first_burn_end_time - 1000.0) / 2.0);
+        simulation.predict_until(end_time);
+
+        let burns = get_burns(&simulation, spacecraft);
+        assert_eq!(burns.len(), 2);
+        assert_eq!(burns[0].borrow().get_end_time(), first_burn_end_time);
+        assert_eq!(burns[1].borrow().get_start_time(), 5000.0);
+        assert_segments_continuous(&simulation, spacecraft);
+    }
+
+    #[test]
+    fn test_manoeuvre_during_burn() {
+        let end_time = 20000.0;
+        let (mut simulation, spacecraft) = orbiting_simulation(end_time);
+        let trajectory_component = simulation.components.trajectory_components.get_mut(&spacecraft).unwrap();
+        let first = trajectory_component.add_manoeuvre(Manoeuvre { time: 1000.0, tangent_dv: 500.0, normal_dv: 0.0 });
+        let second = trajectory_component.add_manoeuvre(Manoeuvre { time: 1010.0, tangent_dv: 100.0, normal_dv: 0.0 });
+        predict_spacecraft(&mut simulation, spacecraft, 1000.0, end_time);
+
+        // The second manoeuvre waits for the first burn to finish rather than being dropped
+        let trajectory_component = simulation.components.trajectory_components.get(&spacecraft).unwrap();
+        let first_burn = trajectory_component.get_manoeuvre_burn(first).unwrap();
+        let second_burn = trajectory_component.get_manoeuvre_burn(second).unwrap();
+        assert!(first_burn.borrow().get_end_time() > 1010.0);
+        assert_eq!(second_burn.borrow().get_start_time(), first_burn.borrow().get_end_time());
+        assert_segments_continuous(&simulation, spacecraft);
+
+        // It's still planned after its own time, until its burn actually starts
+        simulation.update(1020.0);
+        let trajectory_component = simulation.components.trajectory_components.get(&spacecraft).unwrap();
+        assert!(trajectory_component.get_manoeuvre(first).is_none());
+        assert!(trajectory_component.get_manoeuvre(second).is_some());
+        simulation.predict_until(end_time);
+        let second_burn = simulation.components.trajectory_components.get(&spacecraft).unwrap().get_manoeuvre_burn(second).unwrap();
+        assert_eq!(second_burn.borrow().get_start_time(), first_burn.borrow().get_end_time());
     }
 }
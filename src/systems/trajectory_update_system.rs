@@ -1,13 +1,43 @@
-use crate::state::State;
+use std::{rc::Rc, cell::RefCell};
+
+use crate::{simulation::Simulation, components::trajectory_component::{TrajectoryComponent, segment::{Segment, burn::Burn}}, storage::entity_allocator::Entity};
 
 use super::util::sync_all_entities;
 
-pub fn trajectory_update_system(state: &mut State) {
-    let time_step = state.get_time_step();
-    for entity in &state.get_entities_sorted_by_mass() {
-        if let Some(trajectory_component) = state.components.trajectory_components.get_mut(entity) {
-            trajectory_component.update(state.time, state.delta_time * time_step);
-        }
+/// Burns that finish partway through the update have already been popped by the time the mass is updated (possibly along
+/// with a whole burn after them, if the time step is big enough), so the last burn to start before the end of the update is found beforehand
+fn get_last_started_burn(trajectory_component: &TrajectoryComponent, time: f64) -> Option<Rc<RefCell<Burn>>> {
+    trajectory_component.get_segments()
+        .iter()
+        .take_while(|segment| segment.get_start_time() <= time)
+        .filter_map(|segment| match segment {
+            Segment::Burn(burn) => Some(burn.clone()),
+            Segment::Orbit(_) => None,
+        })
+        .last()
+}
+
+/// Burns that have finished stay at their end point, so this gives the end mass for those
+fn update_mass(simulation: &mut Simulation, entity: &Entity, last_started_burn: Option<Rc<RefCell<Burn>>>) {
+    if let Some(burn) = last_started_burn {
+        let mass = burn.borrow().get_current_mass();
+        simulation.components.mass_components.get_mut(entity).unwrap().set_mass(mass);
+    }
+}
+
+/// Moves the entity delta_time along its trajectory, ending up at the simulation's current time
+/// Doesn't sync its position or velocity, so sync_all_entities needs calling afterwards
+pub fn update_trajectory(simulation: &mut Simulation, entity: &Entity, delta_time: f64) {
+    if let Some(trajectory_component) = simulation.components.trajectory_components.get_mut(entity) {
+        let last_started_burn = get_last_started_burn(trajectory_component, simulation.time);
+        trajectory_component.update(simulation.time, delta_time);
+        update_mass(simulation, entity, last_started_burn);
+    }
+}
+
+pub fn trajectory_update_system(simulation: &mut Simulation, delta_time: f64) {
+    for entity in &simulation.get_entities_sorted_by_mass() {
+        update_trajectory(simulation, entity, delta_time);
     }
-    sync_all_entities(state)
-}
\ No newline at end of file
+    sync_all_entities(simulation)
+}
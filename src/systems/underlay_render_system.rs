@@ -4,10 +4,13 @@ use eframe::{egui::{Context, Ui, CentralPanel}, epaint::PaintCallback, egui_glow
 
 use crate::state::State;
 
-use self::{render_segment::get_all_segment_vertices, render_object::get_all_object_vertices, render_icons::get_all_icon_vertices};
+use self::{render_segment::get_all_segment_vertices, render_object::get_all_object_vertices, render_icons::get_all_icon_vertices, render_manoeuvre_handles::get_all_manoeuvre_handle_vertices, render_closest_approaches::get_all_closest_approach_vertices, render_orbit_markers::get_all_orbit_marker_vertices};
 
+mod render_closest_approaches;
 mod render_icons;
+mod render_manoeuvre_handles;
 mod render_object;
+mod render_orbit_markers;
 mod render_segment;
 
 const ICON_NAMES: [&str; 5] = ["star", "planet", "moon", "spacecraft", "burn"];
@@ -30,7 +33,10 @@ fn add_painter_callback(state: &mut State, context: &Context, ui: &Ui) {
 
 pub fn underlay_render_system(state: &mut State, context: &Context) {
     CentralPanel::default().show(context, |ui| {
-        let object_vertices = get_all_object_vertices(state);
+        let mut object_vertices = get_all_object_vertices(state);
+        object_vertices.append(&mut get_all_manoeuvre_handle_vertices(state));
+        object_vertices.append(&mut get_all_closest_approach_vertices(state));
+        object_vertices.append(&mut get_all_orbit_marker_vertices(state));
         let orbit_vertices = get_all_segment_vertices(state);
         state.object_renderer.lock().unwrap().set_vertices(object_vertices);
         state.orbit_renderer.lock().unwrap().set_vertices(orbit_vertices);
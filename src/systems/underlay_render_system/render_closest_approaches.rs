@@ -0,0 +1,20 @@
+use crate::{state::State, camera::SCALE_FACTOR, util::add_diamond, systems::{closest_approach_system::{get_closest_approach_markers, MARKER_COLOR}, util::get_rendered_position_at_time}};
+
+/// Half the width of each marker's diamond, in pixels
+const MARKER_SIZE: f64 = 5.0;
+
+/// A marker on both the spacecraft's and the target's trajectory at each closest approach
+pub fn get_all_closest_approach_vertices(state: &mut State) -> Vec<f32> {
+    let mut vertices = vec![];
+    let Some(target) = state.target else {
+        return vertices;
+    };
+    let zoom = state.camera.lock().unwrap().get_zoom();
+    for approach in get_closest_approach_markers(state) {
+        for entity in [state.selected_entity, target] {
+            let position = get_rendered_position_at_time(&state.simulation, &entity, approach.time);
+            add_diamond(&mut vertices, position * SCALE_FACTOR, MARKER_SIZE / zoom, MARKER_COLOR.into());
+        }
+    }
+    vertices
+}
@@ -0,0 +1,39 @@
+use eframe::epaint::Rgba;
+use nalgebra_glm::{DVec2, vec2};
+
+use crate::{state::State, camera::SCALE_FACTOR, util::add_triangle, systems::manoeuvre_node_system::handle::{ManoeuvreHandle, get_handles}};
+
+/// Half the width of each handle's arrow, in pixels
+const HANDLE_SIZE: f64 = 6.0;
+
+fn get_handle_color(handle: ManoeuvreHandle, dragged: bool) -> Rgba {
+    let alpha = if dragged { 1.0 } else { 0.7 };
+    match handle {
+        ManoeuvreHandle::Prograde | ManoeuvreHandle::Retrograde => Rgba::from_rgba_unmultiplied(0.3, 1.0, 0.3, alpha),
+        ManoeuvreHandle::Normal | ManoeuvreHandle::AntiNormal => Rgba::from_rgba_unmultiplied(0.3, 0.7, 1.0, alpha),
+    }
+}
+
+/// An arrow pointing away from the node
+fn add_handle_vertices(vertices: &mut Vec<f32>, position: DVec2, direction: DVec2, zoom: f64, color: Rgba) {
+    let size = HANDLE_SIZE / zoom;
+    let scaled_position = position * SCALE_FACTOR;
+    let perpendicular = vec2(-direction.y, direction.x);
+    let tip = scaled_position + direction * size;
+    let left = scaled_position - direction * size + perpendicular * size;
+    let right = scaled_position - direction * size - perpendicular * size;
+    add_triangle(vertices, tip, left, right, color);
+}
+
+pub fn get_all_manoeuvre_handle_vertices(state: &mut State) -> Vec<f32> {
+    let mut vertices = vec![];
+    let Some(node) = state.selected_manoeuvre_node else {
+        return vertices;
+    };
+    let zoom = state.camera.lock().unwrap().get_zoom();
+    for (handle, position, direction) in get_handles(&state.simulation, node, zoom) {
+        let color = get_handle_color(handle, state.dragged_manoeuvre_handle == Some(handle));
+        add_handle_vertices(&mut vertices, position, direction, zoom, color);
+    }
+    vertices
+}
@@ -22,11 +22,11 @@ fn get_entity_object_vertices(position_component: &PositionComponent, celestial_
 
 pub fn get_all_object_vertices(state: &mut State) -> Vec<f32> {
     let mut vertices = vec![];
-    for entity in state.components.entity_allocator.get_entities() {
-        let Some(position_component) = state.components.position_components.get(&entity) else {
+    for entity in state.simulation.components.entity_allocator.get_entities() {
+        let Some(position_component) = state.simulation.components.position_components.get(&entity) else {
             continue;
         };
-        let Some(celestial_body_component) = state.components.celestial_body_components.get(&entity) else {
+        let Some(celestial_body_component) = state.simulation.components.celestial_body_components.get(&entity) else {
             continue;
         };
         vertices.append(&mut get_entity_object_vertices(position_component, celestial_body_component));
@@ -0,0 +1,34 @@
+use eframe::epaint::Rgba;
+use nalgebra_glm::vec2;
+
+use crate::{state::State, camera::SCALE_FACTOR, util::{add_triangle, add_diamond}, systems::orbit_marker_system::{get_orbit_markers, OrbitMarker, OrbitMarkerType}};
+
+/// Half the width of each marker, in pixels
+const MARKER_SIZE: f64 = 5.0;
+
+/// Apsides are arrows pointing away from the parent, and SOI changes are diamonds
+fn add_marker_vertices(state: &State, vertices: &mut Vec<f32>, marker: &OrbitMarker, zoom: f64) {
+    let size = MARKER_SIZE / zoom;
+    let scaled_position = marker.get_rendered_position(&state.simulation) * SCALE_FACTOR;
+    let color: Rgba = marker.marker_type.get_color().into();
+    match marker.marker_type {
+        OrbitMarkerType::Periapsis | OrbitMarkerType::Apoapsis => {
+            let direction = marker.position.normalize();
+            let perpendicular = vec2(-direction.y, direction.x);
+            let tip = scaled_position + direction * size;
+            let left = scaled_position - direction * size + perpendicular * size;
+            let right = scaled_position - direction * size - perpendicular * size;
+            add_triangle(vertices, tip, left, right, color);
+        }
+        OrbitMarkerType::SoiEntry | OrbitMarkerType::SoiExit => add_diamond(vertices, scaled_position, size, color),
+    }
+}
+
+pub fn get_all_orbit_marker_vertices(state: &mut State) -> Vec<f32> {
+    let mut vertices = vec![];
+    let zoom = state.camera.lock().unwrap().get_zoom();
+    for marker in get_orbit_markers(&state.simulation, state.selected_entity) {
+        add_marker_vertices(state, &mut vertices, &marker, zoom);
+    }
+    vertices
+}
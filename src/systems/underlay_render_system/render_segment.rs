@@ -9,15 +9,15 @@ mod visual_segment_point;
 
 fn get_entity_segment_vertices(state: &State, entity: &Entity, segment: &Segment) -> Vec<f32> {
     match segment {
-        Segment::Burn(burn) => get_entity_burn_vertices(state, entity, &*burn.borrow()),
-        Segment::Orbit(orbit) => get_entity_orbit_vertices(state, entity, &*orbit.borrow()),
+        Segment::Burn(burn) => get_entity_burn_vertices(state, entity, &burn.borrow()),
+        Segment::Orbit(orbit) => get_entity_orbit_vertices(state, entity, &orbit.borrow()),
     }
 }
 
 pub fn get_all_segment_vertices(state: &mut State) -> Vec<f32> {
     let mut vertices = vec![];
-    for entity in state.components.entity_allocator.get_entities() {
-        if let Some(trajectory_component) = state.components.trajectory_components.get(&entity) {
+    for entity in state.simulation.components.entity_allocator.get_entities() {
+        if let Some(trajectory_component) = state.simulation.components.trajectory_components.get(&entity) {
             for segment in trajectory_component.get_segments() {
                 vertices.append(&mut get_entity_segment_vertices(state, &entity, segment));
             }
@@ -17,7 +17,7 @@ const TESSELLATION_THRESHOLD: f64 = 5000.0;
 // impl ScreenLines {
 //     pub fn relative_to_center(&self, state: &State, orbit: &Ref<Orbit>) -> ScreenLines {
 //         let parent = orbit.get_parent();
-//         let absolute_parent_position = state.components.position_components.get(&parent).unwrap().get_absolute_position();
+//         let absolute_parent_position = state.simulation.components.position_components.get(&parent).unwrap().get_absolute_position();
 //         let argument_of_periapsis = orbit.get_arugment_of_periapsis();
 //         let relative_nominal_position = orbit.get_position_from_theta(argument_of_periapsis);
 //         let nominal_position_to_center_vector = -orbit.get_semi_major_axis() * vec2(f64::cos(argument_of_periapsis), f64::sin(argument_of_periapsis));
@@ -89,7 +89,7 @@ fn do_orbit_tessellation(mut visual_orbit_points: Vec<VisualOrbitPoint>, orbit:
 
 fn get_visual_orbit_points(state: &State, orbit: &Orbit) -> Vec<VisualOrbitPoint> {
     let parent = orbit.get_parent();
-    let absolute_parent_position = state.components.position_components.get(&parent).unwrap().get_absolute_position() * SCALE_FACTOR;
+    let absolute_parent_position = state.simulation.components.position_components.get(&parent).unwrap().get_absolute_position() * SCALE_FACTOR;
     let mut visual_orbit_points = vec![];
     let angle_to_rotate_through = orbit.get_remaining_angle();
     visual_orbit_points.push(create_visual_orbit_point(orbit, absolute_parent_position, orbit.get_current_true_anomaly()));
@@ -27,7 +27,7 @@ pub fn add_orbit_line(vertices: &mut Vec<f32>, previous_point: &dyn VisualSegmen
 }
 
 pub fn get_entity_color(state: &State, entity: &Entity) -> Rgba {
-    if let Some(celestial_body_component) = state.components.celestial_body_components.get(entity) {
+    if let Some(celestial_body_component) = state.simulation.components.celestial_body_components.get(entity) {
         celestial_body_component.get_color()
     } else {
         Rgba::from_rgba_unmultiplied(1.0, 1.0, 1.0, 1.0)
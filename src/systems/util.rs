@@ -1,6 +1,8 @@
 use nalgebra_glm::DVec2;
 
-use crate::{state::State, storage::entity_allocator::Entity, components::trajectory_component::segment::Segment};
+use crate::{simulation::Simulation, storage::entity_allocator::Entity, components::trajectory_component::segment::Segment};
+
+use super::manoeuvre_node_system::nodes::get_node_burn;
 
 /// So... why is this an entire function? Surely we can just find the parent component and use that to set the new parent?
 /// Well, the problem with that is that the old parent will still have the entity in its children
@@ -8,44 +10,42 @@ use crate::{state::State, storage::entity_allocator::Entity, components::traject
 /// 1) update the parent component of the specified entity
 /// 2) remove the specified entity from the children (celestial body) component of its old parent
 /// 3) add the specified entity to the children (celestial body) component of its new parent
-pub fn update_parent(state: &mut State, entity: Entity, new_parent: &Entity) {
-    let old_parent = state.components.parent_components.get(&entity).unwrap().get_parent();
+pub fn update_parent(simulation: &mut Simulation, entity: Entity, new_parent: &Entity) {
+    let old_parent = simulation.components.parent_components.get(&entity).unwrap().get_parent();
     if *new_parent != old_parent {
-        state.components.celestial_body_components.get_mut(&old_parent).unwrap().remove_child(entity);
-        state.components.celestial_body_components.get_mut(new_parent).unwrap().add_child(entity);
-        state.components.parent_components.get_mut(&entity).unwrap().set_parent(*new_parent);
+        simulation.components.celestial_body_components.get_mut(&old_parent).unwrap().remove_child(entity);
+        simulation.components.celestial_body_components.get_mut(new_parent).unwrap().add_child(entity);
+        simulation.components.parent_components.get_mut(&entity).unwrap().set_parent(*new_parent);
     }
 }
 
 /// Takes in relative positions/velocities, turns them into absolute positions/velocities, and updates the entity's position/velocity components accordingly
-pub fn update_position_and_velocity(state: &mut State, entity: &Entity, new_relative_position: DVec2, new_relative_velocity: DVec2) {
-    let parent = state.components.parent_components.get(entity).unwrap().get_parent();
-    let parent_absolute_position = state.components.position_components.get(&parent).unwrap().get_absolute_position();
-    let parent_absolute_velocity = state.components.velocity_components.get(&parent).unwrap().get_absolute_velocity();
-    state.components.position_components.get_mut(entity).unwrap().set_absolute_position(parent_absolute_position + new_relative_position);
-    state.components.velocity_components.get_mut(entity).unwrap().set_absolute_velocity(parent_absolute_velocity + new_relative_velocity);
+pub fn update_position_and_velocity(simulation: &mut Simulation, entity: &Entity, new_relative_position: DVec2, new_relative_velocity: DVec2) {
+    let parent = simulation.components.parent_components.get(entity).unwrap().get_parent();
+    let parent_absolute_position = simulation.components.position_components.get(&parent).unwrap().get_absolute_position();
+    let parent_absolute_velocity = simulation.components.velocity_components.get(&parent).unwrap().get_absolute_velocity();
+    simulation.components.position_components.get_mut(entity).unwrap().set_absolute_position(parent_absolute_position + new_relative_position);
+    simulation.components.velocity_components.get_mut(entity).unwrap().set_absolute_velocity(parent_absolute_velocity + new_relative_velocity);
 }
 
 /// Sync the position, velocity, and parent of the entity to the position, velocity, and parent of the current orbit
-pub fn sync_to_segment(state: &mut State, segment: Segment, entity: Entity) {
+pub fn sync_to_segment(simulation: &mut Simulation, segment: Segment, entity: Entity) {
     let new_position = segment.get_current_position();
     let new_velocity = segment.get_current_velocity();
     let new_parent = segment.get_parent();
-    update_parent(state, entity, &new_parent);
-    update_position_and_velocity(state, &entity, new_position, new_velocity);
+    update_parent(simulation, entity, &new_parent);
+    update_position_and_velocity(simulation, &entity, new_position, new_velocity);
 }
 
-pub fn sync_to_trajectory(state: &mut State, entity: Entity) {
-    let segment = state.components.trajectory_components.get(&entity).unwrap().get_current_segment();
-    sync_to_segment(state, segment, entity);
+pub fn sync_to_trajectory(simulation: &mut Simulation, entity: Entity) {
+    let segment = simulation.components.trajectory_components.get(&entity).unwrap().get_current_segment();
+    sync_to_segment(simulation, segment, entity);
 }
 
 
-pub fn get_segment_at_time(state: &State, entity: &Entity, time: f64) -> Segment {
-    for segment in state.components.trajectory_components.get(entity).unwrap().get_segments() {
-        let start_time = segment.as_orbit().borrow().get_start_time();
-        let end_time = segment.as_orbit().borrow().get_end_time();
-        if time >= start_time && time <= end_time {
+pub fn get_segment_at_time(simulation: &Simulation, entity: &Entity, time: f64) -> Segment {
+    for segment in simulation.components.trajectory_components.get(entity).unwrap().get_segments() {
+        if time >= segment.get_start_time() && time <= segment.get_end_time() {
             return segment.clone();
         }
     }
@@ -53,16 +53,73 @@ pub fn get_segment_at_time(state: &State, entity: &Entity, time: f64) -> Segment
 }
 
 
-pub fn get_all_entity_children(state: &State, entities: &Vec<Entity>) -> Vec<Entity> {
+/// Where the entity will be at the time relative to its parent then, along with the parent
+/// Celestial bodies are looked up in the ephemeris, rather than solving their orbits
+fn get_relative_position_at_time(simulation: &Simulation, entity: &Entity, time: f64) -> (DVec2, Entity) {
+    if let Some(state) = simulation.ephemeris.get_state(entity, time) {
+        return (state.position, state.parent);
+    }
+    let segment = get_segment_at_time(simulation, entity, time);
+    (segment.get_position_at_time(time), segment.get_parent())
+}
+
+/// Where the entity will actually be at the time, going up through whichever parents its segments have then
+/// Root entities never move, so their current position is used
+pub fn get_absolute_position_at_time(simulation: &Simulation, entity: &Entity, time: f64) -> DVec2 {
+    if simulation.components.parent_components.get(entity).is_none() {
+        return simulation.components.position_components.get(entity).unwrap().get_absolute_position();
+    }
+    let (position, parent) = get_relative_position_at_time(simulation, entity, time);
+    position + get_absolute_position_at_time(simulation, &parent, time)
+}
+
+/// Where the entity will be at the time relative to its parent at the time, but drawn around the parent's current position,
+/// which is how trajectories are rendered
+pub fn get_rendered_position_at_time(simulation: &Simulation, entity: &Entity, time: f64) -> DVec2 {
+    if simulation.components.parent_components.get(entity).is_none() {
+        return simulation.components.position_components.get(entity).unwrap().get_absolute_position();
+    }
+    let (position, parent) = get_relative_position_at_time(simulation, entity, time);
+    position + simulation.components.position_components.get(&parent).unwrap().get_absolute_position()
+}
+
+pub fn get_all_entity_children(simulation: &Simulation, entities: &Vec<Entity>) -> Vec<Entity> {
     let mut new_entities = vec![];
     for entity in entities {
-        if let Some(celestial_body_component) = state.components.celestial_body_components.get(entity) {
+        if let Some(celestial_body_component) = simulation.components.celestial_body_components.get(entity) {
             new_entities.extend(celestial_body_component.get_children());
         }
     }
     new_entities
 }
 
+/// Sorted by when the burns start, so earlier burns take precedence over later ones wherever that matters
+pub fn get_manoeuvre_nodes(simulation: &Simulation) -> Vec<Entity> {
+    let mut nodes: Vec<Entity> = simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter(|entity| simulation.components.manoeuvre_node_components.get(entity).is_some())
+        .collect();
+    let get_start_time = |node: &Entity| get_node_burn(simulation, *node).map_or(f64::MAX, |burn| burn.borrow().get_start_time());
+    nodes.sort_by(|a, b| get_start_time(a).total_cmp(&get_start_time(b)));
+    nodes
+}
+
+/// The start of the next planned burn of any spacecraft
+pub fn get_next_burn_time(simulation: &Simulation) -> Option<f64> {
+    simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter_map(|entity| simulation.components.trajectory_components.get(&entity)?.get_next_manoeuvre(simulation.time, f64::MAX))
+        .map(|manoeuvre| manoeuvre.time)
+        .min_by(f64::total_cmp)
+}
+
+pub fn is_any_burn_active(simulation: &Simulation) -> bool {
+    simulation.components.entity_allocator.get_entities()
+        .into_iter()
+        .filter_map(|entity| simulation.components.trajectory_components.get(&entity))
+        .any(|trajectory_component| matches!(trajectory_component.get_current_segment(), Segment::Burn(_)))
+}
+
 pub fn format_time(time: f64) -> String {
     let years_quotient = f64::floor(time / (360.0 * 24.0 * 60.0 * 60.0));
     let years_remainder = time % (360.0 * 24.0 * 60.0 * 60.0);
@@ -100,20 +157,30 @@ pub fn format_time(time: f64) -> String {
     }
 }
 
-pub fn is_celestial_body_with_trajectory(state: &State, entity: Entity) -> bool {
-    state.components.trajectory_components.get(&entity).is_some() && state.components.celestial_body_components.get(&entity).is_some()
+pub fn format_distance(distance: f64) -> String {
+    if distance < 1.0e4 {
+        format!("{:.0} m", distance)
+    } else if distance < 1.0e9 {
+        format!("{:.1} km", distance / 1.0e3)
+    } else {
+        format!("{:.3e} km", distance / 1.0e3)
+    }
 }
 
-pub fn is_spacecraft_with_trajectory(state: &State, entity: Entity) -> bool {
-    state.components.trajectory_components.get(&entity).is_some() && state.components.celestial_body_components.get(&entity).is_none()
+pub fn is_celestial_body_with_trajectory(simulation: &Simulation, entity: Entity) -> bool {
+    simulation.components.trajectory_components.get(&entity).is_some() && simulation.components.celestial_body_components.get(&entity).is_some()
 }
 
-pub fn sync_entity_to_time(state: &mut State, entity: Entity, time: f64) {
-    let mut segment = get_segment_at_time(state, &entity, time);
+pub fn is_spacecraft_with_trajectory(simulation: &Simulation, entity: Entity) -> bool {
+    simulation.components.trajectory_components.get(&entity).is_some() && simulation.components.celestial_body_components.get(&entity).is_none()
+}
+
+pub fn sync_entity_to_time(simulation: &mut Simulation, entity: Entity, time: f64) {
+    let mut segment = get_segment_at_time(simulation, &entity, time);
     let delta_time = time - segment.get_start_time();
     segment.reset();
     segment.update(delta_time);
-    sync_to_segment(state, segment, entity)
+    sync_to_segment(simulation, segment, entity)
 }
 
 /// We could in theory just do this recursively, but there's an edge case that pops up with
@@ -129,19 +196,18 @@ pub fn sync_entity_to_time(state: &mut State, entity: Entity, time: f64) {
 /// But the position/velocity of its new parent has NOT been updated yet
 /// So we're actually slightly behind
 /// This is solved by simply updating elements from the highest to lowest mass
-
-pub fn sync_all_entities(state: &mut State) {
-    for entity in state.get_entities_sorted_by_mass() {
-        if state.components.trajectory_components.get_mut(&entity).is_some() {
-            sync_to_trajectory(state, entity);
+pub fn sync_all_entities(simulation: &mut Simulation) {
+    for entity in simulation.get_entities_sorted_by_mass() {
+        if simulation.components.trajectory_components.get_mut(&entity).is_some() {
+            sync_to_trajectory(simulation, entity);
         }
     }
 }
 
-pub fn sync_celestial_bodies_to_time(state: &mut State, time: f64) {
-    for entity in state.get_entities_sorted_by_mass() {
-        if is_celestial_body_with_trajectory(state, entity) {
-            sync_entity_to_time(state, entity, time);
+pub fn sync_celestial_bodies_to_time(simulation: &mut Simulation, time: f64) {
+    for entity in simulation.get_entities_sorted_by_mass() {
+        if is_celestial_body_with_trajectory(simulation, entity) {
+            sync_entity_to_time(simulation, entity, time);
         }
     }
 }
\ No newline at end of file
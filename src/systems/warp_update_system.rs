@@ -1,6 +1,39 @@
-use crate::state::State;
+use eframe::egui::{Context, Key};
 
-use super::time_step_update_system::TimeStepDescription;
+use crate::{state::State, simulation::Simulation, storage::entity_allocator::Entity};
+
+use super::{time_step_update_system::TimeStepDescription, orbit_marker_system::{get_orbit_markers, OrbitMarkerType}, util::{get_next_burn_time, is_any_burn_active}};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpEvent {
+    Periapsis,
+    Apoapsis,
+    SoiChange,
+    /// The burn lead time before the next burn starts, to leave time to get ready
+    Burn,
+}
+
+impl WarpEvent {
+    pub const ALL: [WarpEvent; 4] = [WarpEvent::Periapsis, WarpEvent::Apoapsis, WarpEvent::SoiChange, WarpEvent::Burn];
+
+    pub fn get_name(&self) -> &str {
+        match self {
+            WarpEvent::Periapsis => "Periapsis",
+            WarpEvent::Apoapsis => "Apoapsis",
+            WarpEvent::SoiChange => "SOI change",
+            WarpEvent::Burn => "Burn",
+        }
+    }
+
+    fn get_key(&self) -> Key {
+        match self {
+            WarpEvent::Periapsis => Key::P,
+            WarpEvent::Apoapsis => Key::A,
+            WarpEvent::SoiChange => Key::S,
+            WarpEvent::Burn => Key::B,
+        }
+    }
+}
 
 pub struct WarpDescription {
     pub start_time: f64,
@@ -29,10 +62,63 @@ impl WarpDescription {
     }
 }
 
+/// When the entity next reaches the event, or None if it doesn't happen before the end of prediction
+pub fn get_warp_event_time(simulation: &Simulation, entity: Entity, event: WarpEvent, burn_lead_time: f64) -> Option<f64> {
+    let marker_types = match event {
+        WarpEvent::Periapsis => vec![OrbitMarkerType::Periapsis],
+        WarpEvent::Apoapsis => vec![OrbitMarkerType::Apoapsis],
+        WarpEvent::SoiChange => vec![OrbitMarkerType::SoiExit, OrbitMarkerType::SoiEntry],
+        WarpEvent::Burn => {
+            let manoeuvre = simulation.components.trajectory_components.get(&entity)?.get_next_manoeuvre(simulation.time, f64::MAX)?;
+            let time = manoeuvre.time - burn_lead_time;
+            return (time > simulation.time).then_some(time);
+        }
+    };
+    get_orbit_markers(simulation, entity)
+        .into_iter()
+        .filter(|marker| marker_types.contains(&marker.marker_type) && marker.time > simulation.time)
+        .map(|marker| marker.time)
+        .min_by(f64::total_cmp)
+}
+
+/// Does nothing if the event doesn't happen
+pub fn warp_to_event(state: &mut State, entity: Entity, event: WarpEvent) {
+    if let Some(end_time) = get_warp_event_time(&state.simulation, entity, event, state.burn_lead_time) {
+        state.current_warp = Some(WarpDescription { start_time: state.simulation.time, end_time });
+    }
+}
+
+/// The keys are all letters, so they're left alone while typing into a text box (eg a dv value)
+fn update_warp_keys(state: &mut State, context: &Context) {
+    if context.wants_keyboard_input() {
+        return;
+    }
+    for event in WarpEvent::ALL {
+        if context.input(|input| input.key_pressed(event.get_key())) {
+            warp_to_event(state, state.selected_entity, event);
+        }
+    }
+}
+
+/// Cuts the warp short at the start of the next burn, or stops it altogether if a burn is already underway
+fn limit_warp_to_burns(state: &mut State) {
+    let Some(current_warp) = &mut state.current_warp else {
+        return;
+    };
+    if is_any_burn_active(&state.simulation) {
+        state.current_warp = None;
+        state.time_step_description = TimeStepDescription::Level(1);
+        return;
+    }
+    if let Some(burn_time) = get_next_burn_time(&state.simulation) {
+        current_warp.end_time = f64::min(current_warp.end_time, burn_time);
+    }
+}
+
 fn check_warp_finished(state: &mut State) {
     // Weird double if needed because of borrow checker
     let warp_finished = if let Some(current_warp) = &state.current_warp {
-        state.time >= current_warp.end_time
+        state.simulation.time >= current_warp.end_time
     } else {
         return;
     };
@@ -44,19 +130,56 @@ fn check_warp_finished(state: &mut State) {
 
 fn update_warp(state: &mut State) {
     if let Some(current_warp) = &state.current_warp {
-        let mut warp_speed = current_warp.calculate_warp_speed(state.time);
-        let final_time = state.time + warp_speed * state.delta_time;
+        let mut warp_speed = current_warp.calculate_warp_speed(state.simulation.time);
+        let final_time = state.simulation.time + warp_speed * state.delta_time;
         if final_time > current_warp.end_time {
             // Oh no, we're about to overshoot
             // Calculate required warp speed to perfectly land at target point
             // Add small amount so next frame actually counts this as 'finished'
-            warp_speed = (current_warp.end_time - state.time) / state.delta_time + 0.01;
+            warp_speed = (current_warp.end_time - state.simulation.time) / state.delta_time + 0.01;
         }
         state.time_step_description = TimeStepDescription::Raw(warp_speed);
     }
 }
 
-pub fn warp_update_system(state: &mut State) {
+pub fn warp_update_system(state: &mut State, context: &Context) {
+    update_warp_keys(state, context);
+    limit_warp_to_burns(state);
     check_warp_finished(state);
     update_warp(state);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use nalgebra_glm::vec2;
+
+    use crate::{simulation::test_util::{earth_simulation, EARTH_MASS}, storage::entity_builder::add_child_object, components::{engine_component::EngineComponent, trajectory_component::{manoeuvre::Manoeuvre, segment::orbit::orbit_direction::GRAVITATIONAL_CONSTANT}}};
+
+    use super::*;
+
+    #[test]
+    fn test_warp_event_times() {
+        let (mut simulation, earth) = earth_simulation();
+        let periapsis = 7.0e6;
+        let apoapsis = 2.0e7;
+        let semi_major_axis = (periapsis + apoapsis) / 2.0;
+        let mu = GRAVITATIONAL_CONSTANT * EARTH_MASS;
+        let speed = f64::sqrt(mu * (2.0 / periapsis - 1.0 / semi_major_axis));
+        let engine = EngineComponent::new(1.0e4, 300.0, 500.0, 1.0e3);
+        let spacecraft = add_child_object(&mut simulation.components, 0.0, "spacecraft".to_string(), "spacecraft".to_string(), earth, vec2(periapsis, 0.0), vec2(0.0, speed), 1.0e3, Some(engine));
+        let period = 2.0 * PI * f64::sqrt(semi_major_axis.powi(3) / mu);
+        let burn_time = 1.2 * period;
+        simulation.components.trajectory_components.get_mut(&spacecraft).unwrap().add_manoeuvre(Manoeuvre { time: burn_time, tangent_dv: 10.0, normal_dv: 0.0 });
+        simulation.predict_until(2.0 * period);
+        simulation.update(100.0);
+
+        let get_time = |event| get_warp_event_time(&simulation, spacecraft, event, 30.0);
+        assert!((get_time(WarpEvent::Periapsis).unwrap() - period).abs() < 1.0e-3);
+        assert!((get_time(WarpEvent::Apoapsis).unwrap() - period / 2.0).abs() < 1.0e-3);
+        assert!((get_time(WarpEvent::Burn).unwrap() - (burn_time - 30.0)).abs() < 1.0e-9);
+        assert!(get_time(WarpEvent::SoiChange).is_none());
+        assert_eq!(get_next_burn_time(&simulation), Some(burn_time));
+        assert!(!is_any_burn_active(&simulation));
+    }
+}
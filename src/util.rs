@@ -1,7 +1,7 @@
 use eframe::epaint::Rgba;
 use nalgebra_glm::{DVec2, Vec2, vec2};
 
-use crate::{state::State, storage::entity_allocator::Entity};
+use crate::{simulation::Simulation, storage::entity_allocator::Entity};
 
 pub fn add_triangle(vertices: &mut Vec<f32>, v1: DVec2, v2: DVec2, v3: DVec2, color: Rgba) {
     let v1 = dvec2_to_f32_tuple(v1);
@@ -12,6 +12,17 @@ pub fn add_triangle(vertices: &mut Vec<f32>, v1: DVec2, v2: DVec2, v3: DVec2, co
     vertices.append(&mut vec![v3.0.0, v3.0.1, v3.1.0, v3.1.1, color.r(), color.g(), color.b(), color.a()]);
 }
 
+/// A square standing on one corner, centred on the position
+pub fn add_diamond(vertices: &mut Vec<f32>, position: DVec2, radius: f64, color: Rgba) {
+    let top = position + vec2(0.0, radius);
+    let bottom = position - vec2(0.0, radius);
+    let left = position - vec2(radius, 0.0);
+    let right = position + vec2(radius, 0.0);
+    add_triangle(vertices, top, left, right, color);
+    add_triangle(vertices, bottom, left, right, color);
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn add_textured_triangle(vertices: &mut Vec<f32>, v1: DVec2, v2: DVec2, v3: DVec2, color: Rgba, t1: Vec2, t2: Vec2, t3: Vec2) {
     let v1 = dvec2_to_f32_tuple(v1);
     let v2 = dvec2_to_f32_tuple(v2);
@@ -40,10 +51,10 @@ pub fn f64_to_f32_pair(v: f64) -> (f32, f32) {
     (upper, lower)
 }
 
-pub fn get_root_entities(state: &State) -> Vec<Entity> {
+pub fn get_root_entities(simulation: &Simulation) -> Vec<Entity> {
     let mut entities = vec![];
-    for entity in state.components.entity_allocator.get_entities() {
-        if state.components.celestial_body_components.get(&entity).is_some() && state.components.parent_components.get(&entity).is_none() {
+    for entity in simulation.components.entity_allocator.get_entities() {
+        if simulation.components.celestial_body_components.get(&entity).is_some() && simulation.components.parent_components.get(&entity).is_none() {
             entities.push(entity);
         }
     }